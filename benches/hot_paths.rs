@@ -0,0 +1,103 @@
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+
+use allways::do_it_allways;
+use allways::NameProvider;
+use allways::Options;
+use allways::SourceNames;
+
+/// Module sizes, in repetitions of each shape's unit.
+const SIZES: [(&str, usize); 3] = [("small", 10), ("medium", 1_000), ("huge", 50_000)];
+
+/// Generated modules of the shapes found in real packages.
+const SHAPES: [(&str, fn(usize) -> String); 3] = [
+    ("import-heavy", import_heavy),
+    ("class-heavy", class_heavy),
+    ("deeply-nested", deeply_nested),
+];
+
+/// An `__init__.py` re-exporting its modules.
+fn import_heavy(size: usize) -> String {
+    (0..size)
+        .map(|i| {
+            format!("import pkg_{i}\nfrom ._mod_{i} import name_{i}, helper_{i} as _helper_{i}\n")
+        })
+        .collect()
+}
+
+/// Classes with methods and class attributes, which aren't exported.
+fn class_heavy(size: usize) -> String {
+    (0..size)
+        .map(|i| {
+            format!(
+                "\n\nclass Model{i}(Base):\n    field: int = {i}\n\n    def method(self, x):\n        return self.field + x\n"
+            )
+        })
+        .collect()
+}
+
+/// Names bound under layers of `if`, `try` and `with` statements.
+fn deeply_nested(size: usize) -> String {
+    let mut src = String::new();
+    for i in 0..size {
+        let depth = i % 8;
+        for level in 0..depth {
+            let indent = "    ".repeat(level);
+            match level % 3 {
+                0 => src.push_str(&format!("{indent}if FLAG_{level}:\n")),
+                1 => src.push_str(&format!("{indent}try:\n")),
+                _ => src.push_str(&format!("{indent}with context_{level}():\n")),
+            }
+        }
+        src.push_str(&format!("{}value_{i} = {i}\n", "    ".repeat(depth)));
+        for level in (0..depth).rev().filter(|level| level % 3 == 1) {
+            let indent = "    ".repeat(level);
+            src.push_str(&format!(
+                "{indent}except ImportError:\n{indent}    value_{i} = None\n"
+            ));
+        }
+    }
+    src
+}
+
+fn public_names(c: &mut Criterion) {
+    let options = Options::default();
+    let mut group = c.benchmark_group("public_names");
+    for (shape, generate) in SHAPES {
+        for (size, repetitions) in SIZES {
+            let src = generate(repetitions);
+            group.throughput(Throughput::Bytes(src.len() as u64));
+            group.bench_with_input(BenchmarkId::new(shape, size), &src, |b, src| {
+                b.iter(|| SourceNames.public_names(src, None, &options).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn rewrite(c: &mut Criterion) {
+    let options = Options::default();
+    let mut group = c.benchmark_group("rewrite");
+    for (size, repetitions) in SIZES {
+        let src = class_heavy(repetitions);
+        // an existing block is replaced, new names make it change every time
+        let fixed = do_it_allways(&src, None, &options).unwrap().src.unwrap();
+        let src = format!("{fixed}\ndef added(): ...\n");
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_with_input(BenchmarkId::new("update", size), &src, |b, src| {
+            b.iter(|| do_it_allways(src, None, &options).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("insert", size),
+            &class_heavy(repetitions),
+            |b, src| b.iter(|| do_it_allways(src, None, &options).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, public_names, rewrite);
+criterion_main!(benches);
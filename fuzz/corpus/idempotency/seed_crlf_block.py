@@ -0,0 +1,12 @@
+"""Docstring."""
+from .mod import *
+x = y = 1
+
+
+# allways: start
+__all__ = [
+    "x",
+]
+# allways: end
+
+z = 3
@@ -0,0 +1,4 @@
+# -*- coding: latin-1 -*-
+nom = "t"
+def f(): ...
+__all__ += ["extra"]
\ No newline at end of file
@@ -0,0 +1,5 @@
+# allways: end
+# allways: start
+__all__ = []
+# allways: start
+name = 1
@@ -0,0 +1,21 @@
+import os
+from typing import Any as _Any
+
+A = 1
+_private = 2
+
+
+def foo(x: _Any) -> int:
+    global B
+    B = x
+    return 1
+
+
+class Foo:
+    attr = 1
+
+
+if sys.version_info >= (3, 8):
+    bar = 1
+else:
+    baz = 2
@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use allways::do_it_allways;
+use allways::Encoding;
+use allways::Options;
+
+// Fixing a source must not panic, must be writable back in the encoding it was read with, and
+// fixing the result again must leave it as is.
+fuzz_target!(|data: &[u8]| {
+    let Ok((src, encoding)) = Encoding::decode(data) else {
+        return;
+    };
+    for lenient in [false, true] {
+        let options = Options::builder().lenient(lenient).build();
+        let Ok(outcome) = do_it_allways(&src, None, &options) else {
+            continue;
+        };
+        let Some(fixed) = outcome.src else {
+            continue;
+        };
+        encoding
+            .encode(&fixed)
+            .expect("the fixed source can't be written back");
+        let again = do_it_allways(&fixed, None, &options)
+            .expect("the fixed source doesn't parse")
+            .src
+            .unwrap_or_else(|| fixed.clone());
+        assert_eq!(again, fixed, "fixing a fixed source changed it");
+    }
+});
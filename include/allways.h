@@ -0,0 +1,34 @@
+/* C interface of allways, built with `cargo build --release --features ffi`. */
+#ifndef ALLWAYS_H
+#define ALLWAYS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The source was fixed, or didn't need to be. */
+#define ALLWAYS_OK 0
+/* A pointer argument is null or the source isn't UTF-8. */
+#define ALLWAYS_INVALID_ARGUMENT 1
+/* The source couldn't be fixed, e.g. it isn't valid python, or allways panicked. */
+#define ALLWAYS_ERROR 2
+
+/*
+ * Fix the `len` bytes of UTF-8 python source at `src` with the default options.
+ *
+ * On success `*result` points to the fixed source, otherwise to an error message, both
+ * NUL-terminated and to be released with `allways_free`.
+ */
+int allways_fix(const uint8_t *src, size_t len, char **result);
+
+/* Release a string returned by `allways_fix`, doing nothing for NULL. */
+void allways_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -1,132 +1,1610 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
 
 use anyhow::Result;
+use rustpython_parser::ast;
+use rustpython_parser::ast::Ranged;
+use rustpython_parser::ast::Stmt;
+use rustpython_parser::ast::Suite;
+use rustpython_parser::Parse;
 
+use crate::autofix::fix_lints;
+use crate::comments::comments as find_comments;
+use crate::comments::starts_comment;
+use crate::diagnostic::Diagnostic;
+use crate::editorconfig;
+use crate::exports;
+use crate::filter::ExportCandidate;
+use crate::name_parser::dunder_all_value;
+use crate::name_parser::parse_module;
+use crate::name_parser::timed_parse;
+use crate::name_parser::DunderAllKind;
+use crate::name_parser::Kind;
 use crate::name_parser::NameParser;
+use crate::name_parser::PlatformCondition;
+use crate::name_parser::StarImport;
+use crate::options::BlockStyle;
+use crate::options::Options;
+use crate::options::Placement;
+use crate::options::TemplateLine;
+use crate::provider::NameProvider;
+use crate::provider::SourceNames;
+use crate::regions::safe_offset;
+use crate::repair::malformed_block;
+use crate::resolve::expand_star_import;
+use crate::rules::Rule;
 
-const INDENT: &str = "    ";
-const ALLWAYS_START_COMMENT: &str = "# allways: start";
-const ALLWAYS_END_COMMENT: &str = "# allways: end";
+pub(crate) const ALLWAYS_START_COMMENT: &str = "# allways: start";
+pub(crate) const ALLWAYS_END_COMMENT: &str = "# allways: end";
+/// Starts the line of the block holding the hash of the rest of it, see `Options::block_hash`.
+const ALLWAYS_HASH_COMMENT: &str = "# allways: fnv1a:";
+/// The layout of the allways block without `Options::block_template`.
+const DEFAULT_TEMPLATE: [TemplateLine; 4] = [
+    TemplateLine::StartMarker,
+    TemplateLine::Header,
+    TemplateLine::Names,
+    TemplateLine::EndMarker,
+];
 
-pub fn do_it_allways(src: &str) -> Result<Option<String>> {
-    let names = get_public_names(src)?;
-    if names.is_empty() {
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Outcome {
+    /// The updated source, or `None` if there are no public names to export and no allways
+    /// block to remove.
+    pub src: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compute the updated source of the python module `src`, the problems of the lint rules with
+/// an automatic fix fixed too with `Options::fix_lints`, e.g. private names listed in `__all__`.
+///
+/// `path` is where `src` was read from, star imports can only be expanded when it is known.
+pub fn do_it_allways(src: &str, path: Option<&Path>, options: &Options) -> Result<Outcome> {
+    do_it_allways_with(&SourceNames, src, path, options)
+}
+
+/// [`do_it_allways`] with the names of the allways block coming from `provider`.
+pub fn do_it_allways_with(
+    provider: &dyn NameProvider,
+    src: &str,
+    path: Option<&Path>,
+    options: &Options,
+) -> Result<Outcome> {
+    // any `__all__` is fixed, the block or not, but a malformed block is left to `allways repair`
+    let fixed = if options.fix_lints && malformed_block(src).is_none() {
+        fix_lints(src, path, options)
+    } else {
+        None
+    };
+    let Some(fixed) = fixed else {
+        return update_block(provider, src, path, options);
+    };
+    // the block is generated from the fixed source
+    let mut outcome = update_block(provider, &fixed, path, options)?;
+    outcome.src = outcome.src.or(Some(fixed));
+    Ok(outcome)
+}
+
+/// [`do_it_allways_with`] without fixing the problems of lint rules.
+pub(crate) fn update_block(
+    provider: &dyn NameProvider,
+    src: &str,
+    path: Option<&Path>,
+    options: &Options,
+) -> Result<Outcome> {
+    // another block would be added, or code between the markers replaced
+    if let Some((offset, problem)) = malformed_block(src) {
+        let message = format!("{problem}, leaving the file untouched, see `allways repair`");
+        let diagnostic = if options
+            .rules
+            .severity_in(Rule::MalformedBlock, path)
+            .is_some()
+        {
+            Diagnostic::lint(src, offset, Rule::MalformedBlock, message)
+        } else {
+            Diagnostic::at_offset(src, offset, message)
+        };
+        return Ok(Outcome {
+            src: None,
+            diagnostics: vec![diagnostic],
+        });
+    }
+    let PublicNames {
+        names,
+        unresolved,
+        mut diagnostics,
+        conflict,
+        conditional,
+    } = provider.public_names(src, path, options)?;
+    if conflict {
+        return Ok(Outcome {
+            src: None,
+            diagnostics,
+        });
+    }
+    let options = &*editorconfig::options_for(path, options)?;
+    let extensions = if options.star_import_fallback {
+        unresolved
+    } else {
+        Vec::new()
+    };
+    let no_names = names.is_empty() && extensions.is_empty() && conditional.is_empty();
+    let sections = find_sections(src);
+    // only blocks added by hand are maintained
+    if options.require_markers && sections.is_empty() && get_file_state(src) == FileState::NoAll {
+        if !no_names && options.rules.severity_in(Rule::MissingAll, path).is_some() {
+            let message = "`__all__` is missing, add allways markers to generate it between";
+            diagnostics.push(Diagnostic::lint(
+                src,
+                0,
+                Rule::MissingAll,
+                String::from(message),
+            ));
+        }
+        return Ok(Outcome {
+            src: None,
+            diagnostics,
+        });
+    }
+    if !sections.is_empty() {
+        let src = with_sections(
+            src,
+            sections,
+            &names,
+            &extensions,
+            &conditional,
+            options,
+            &mut diagnostics,
+        )?;
+        return Ok(Outcome {
+            src: Some(src),
+            diagnostics,
+        });
+    }
+    let module = exports::module_name(path, options);
+    // the lines the block replaces, the hand-written `__all__` when adopting it
+    let replaced = match get_file_state(src) {
+        FileState::YesAll(start, end) => Some(start..end),
+        FileState::NoAll => adopted_assignment(src, path, options),
+    };
+    let dynamic = match &replaced {
+        Some(range) if module.is_none() => {
+            dynamic_entries(src, range.start, range.end, &mut diagnostics)
+        }
+        _ => Vec::new(),
+    };
+    // the entries allways can't manage keep the block
+    if no_names && dynamic.iter().all(Vec::is_empty) {
+        let src = match get_file_state(src) {
+            FileState::YesAll(start, end) if options.keep_empty_block => {
+                Some(with_block_in(src, Block::Empty, Some(start..end), options))
+            }
+            FileState::YesAll(start, end) => Some(remove_allways_block(src, start, end)),
+            FileState::NoAll => None,
+        };
+        return Ok(Outcome { src, diagnostics });
+    }
+    let names = match &replaced {
+        Some(range) if !options.sort => keep_order(&listed_names(&src[range.clone()]), names),
+        _ => names,
+    };
+    // the names are generated into a sibling module, see `exports_module`
+    let block = match module {
+        Some(module) => Block::Import(module),
+        None => Block::Names {
+            names: &names,
+            dynamic: &dynamic,
+            extensions: &extensions,
+            conditional: &conditional,
+        },
+    };
+    Ok(Outcome {
+        src: Some(with_block_in(src, block, replaced, options)),
+        diagnostics,
+    })
+}
+
+/// `src`, the module at `path`, with an empty allways block inserted where
+/// `Options::placement` puts new blocks, the names being generated the next time it is fixed.
+///
+/// `None` when `src` already has allways markers, named sections included, or assigns
+/// `__all__` itself.
+pub fn add_markers(src: &str, path: Option<&Path>, options: &Options) -> Result<Option<String>> {
+    let has_markers = get_file_state(src) != FileState::NoAll
+        || !find_sections(src).is_empty()
+        || malformed_block(src).is_some();
+    if has_markers {
+        return Ok(None);
+    }
+    let assigned = NameParser::parse(src, options)?
+        .take_dunder_all_statements()
+        .iter()
+        .any(|statement| matches!(statement.kind, DunderAllKind::Assign));
+    if assigned {
         return Ok(None);
     }
-    let allways_string = get_allways_string(names);
-    Ok(Some(match get_file_state(src) {
-        FileState::NoAll => insert_new_allways_block(src, allways_string),
-        FileState::YesAll(start, end) => update_allways_block(src, start, end, allways_string),
-    }))
+    let options = &*editorconfig::options_for(path, options)?;
+    Ok(Some(with_block(src, Block::Empty, options)))
+}
+
+/// What an allways block holds.
+#[derive(Clone, Copy)]
+pub(crate) enum Block<'a> {
+    /// `__all__` assigned `names`, followed by the `dynamic` entries of the block it replaces,
+    /// extended by the `__all__` of star-imported modules and by the names only exported on
+    /// some platforms.
+    ///
+    /// `dynamic` has the entries of each statement of the replaced block: the first ones follow
+    /// `names`, the others each extend `__all__` in a statement of their own.
+    Names {
+        names: &'a [String],
+        dynamic: &'a [Vec<String>],
+        extensions: &'a [StarImport],
+        conditional: &'a [ConditionalNames],
+    },
+    /// `__all__` imported from the sibling module of `Options::exports_module`.
+    Import(&'a str),
+    /// Nothing yet, see [`add_markers`].
+    Empty,
+}
+
+/// `src` with its allways block inserted or replaced by one exporting `names`.
+pub(crate) fn with_allways_block(
+    src: &str,
+    names: &[String],
+    extensions: &[StarImport],
+    options: &Options,
+) -> String {
+    let block = Block::Names {
+        names,
+        dynamic: &[],
+        extensions,
+        conditional: &[],
+    };
+    with_block(src, block, options)
+}
+
+/// Where a new allways block goes in `src`, `None` for the end of it.
+fn insertion_offset(src: &str, options: &Options) -> Option<usize> {
+    // never inside a region other tools manage, e.g. `# fmt: off`
+    let insertion = match options.placement {
+        Placement::End => Some(safe_offset(src, src.len())),
+        Placement::AfterImports => import_section_end(src).map(|offset| safe_offset(src, offset)),
+    };
+    // with nothing after it the block goes at the end all the same
+    insertion.filter(|&offset| !src[offset..].trim().is_empty())
+}
+
+/// Byte offset of the allways block of `src`, or of where a new one goes.
+fn block_offset(src: &str, options: &Options) -> usize {
+    match get_file_state(src) {
+        FileState::YesAll(start, _) => start,
+        FileState::NoAll => insertion_offset(src, options).unwrap_or(src.len()),
+    }
+}
+
+/// The lines of the hand-written `__all__` that `Options::adopt` replaces by an allways block.
+///
+/// `None` unless `src` has no allways markers and a single module level statement assigning
+/// `__all__`, a list or tuple, on lines of its own. Other statements assigning it, e.g. in an
+/// `if`, leave the file untouched anyway.
+fn adopted_assignment(src: &str, path: Option<&Path>, options: &Options) -> Option<Range<usize>> {
+    // the block would import `__all__`, dropping the entries allways can't manage
+    let adopt = options.adopt && exports::module_name(path, options).is_none();
+    if !adopt
+        || !src.contains("__all__")
+        || get_file_state(src) != FileState::NoAll
+        || !find_sections(src).is_empty()
+        || malformed_block(src).is_some()
+    {
+        return None;
+    }
+    let (statements, _) = parse_module(src, options).ok()?;
+    let assigned = statements
+        .iter()
+        .filter(|statement| matches!(dunder_all_value(statement), Some((_, false))))
+        .collect::<Vec<_>>();
+    let [statement] = assigned.as_slice() else {
+        return None;
+    };
+    let Some((ast::Expr::List(_) | ast::Expr::Tuple(_), _)) = dunder_all_value(statement) else {
+        return None;
+    };
+    let start = usize::from(statement.start());
+    let end = usize::from(statement.end());
+    let line_end = src[end..]
+        .find('\n')
+        .map_or(src.len(), |index| end + index + 1);
+    let own_lines = src[..start].ends_with('\n') || start == 0;
+    (own_lines && src[end..line_end].trim().is_empty()).then_some(start..line_end)
+}
+
+/// `src` with its allways block inserted or replaced by `block`.
+pub(crate) fn with_block(src: &str, block: Block, options: &Options) -> String {
+    let replaced = match get_file_state(src) {
+        FileState::YesAll(start, end) => Some(start..end),
+        FileState::NoAll => None,
+    };
+    with_block_in(src, block, replaced, options)
+}
+
+/// `src` with the lines of the `replaced` range, or where `Options::placement` puts new blocks,
+/// replaced by the allways block `block`.
+///
+/// The new source is written once into a buffer sized for it up front.
+fn with_block_in(
+    src: &str,
+    block: Block,
+    replaced: Option<Range<usize>>,
+    options: &Options,
+) -> String {
+    let newline = line_ending(src);
+    let insertion = insertion_offset(src, options);
+    // black wants 2 blank lines after functions and classes, and allows them anywhere
+    let blank_lines = match (options.block_style, options.placement, insertion) {
+        // isort separates imports from what follows them with a blank line, a comment or
+        // assignment included
+        (BlockStyle::Allways, Placement::AfterImports, Some(_)) => options.blank_lines.unwrap_or(1),
+        (BlockStyle::Allways, ..) => options.blank_lines.unwrap_or_else(|| blank_lines(src)),
+        (BlockStyle::Black, ..) => options.blank_lines.unwrap_or(2),
+    };
+    // the block goes between `head` and `tail`, after `separator` line endings and followed by
+    // `tail_separator` more
+    let (head, separator, tail_separator, tail) = match (replaced, insertion) {
+        (Some(range), _) => (&src[..range.start], 0, 0, &src[range.end..]),
+        (None, _) if src.trim().is_empty() => ("", 0, 0, ""),
+        (None, Some(offset)) => {
+            let head = src[..offset].trim_end();
+            let separator = if head.is_empty() { 0 } else { blank_lines + 1 };
+            // the code after the block keeps its own spacing
+            let tail = &src[offset..];
+            let tail_separator = usize::from(!tail.starts_with(['\r', '\n']));
+            (head, separator, tail_separator, tail)
+        }
+        // trailing blank lines are replaced by the ones separating the block
+        (None, None) => (src.trim_end(), blank_lines + 1, 0, ""),
+    };
+    let block_len = match block {
+        Block::Names {
+            names,
+            dynamic,
+            extensions,
+            conditional,
+        } => {
+            names
+                .iter()
+                .chain(dynamic.iter().flatten())
+                .chain(
+                    conditional
+                        .iter()
+                        .flat_map(|conditional| &conditional.names),
+                )
+                .map(|name| 2 * options.indent.len() + name.len() + 3 + newline.len())
+                .sum::<usize>()
+                + (dynamic.len() + extensions.len() + conditional.len() + 1) * 64
+        }
+        Block::Import(module) => module.len() + 64,
+        Block::Empty => 64,
+    } + options
+        .block_template
+        .as_ref()
+        .map_or(0, |template| template.as_str().len());
+    let separators = (separator + tail_separator + 1) * newline.len();
+    let mut new_src = String::with_capacity(head.len() + separators + block_len + tail.len());
+    new_src.push_str(head);
+    for _ in 0..separator {
+        new_src.push_str(newline);
+    }
+    push_allways_block(&mut new_src, block, None, newline, options);
+    for _ in 0..tail_separator {
+        new_src.push_str(newline);
+    }
+    new_src.push_str(tail);
+    let missing_newline = !src.is_empty() && !src.ends_with('\n');
+    if missing_newline && !options.final_newline && new_src.ends_with(newline) {
+        new_src.truncate(new_src.len() - newline.len());
+    } else if options.final_newline && !new_src.ends_with('\n') {
+        new_src.push_str(newline);
+    }
+    new_src
+}
+
+/// `src` with the names of its named sections, and of its allways block if it has one, brought
+/// up to date.
+///
+/// A name goes to the first section of the module whose `Options::sections` filter matches
+/// it, otherwise to the first one without a filter, the allways block included, and is left out
+/// with a warning when every section has a filter it doesn't match. The first
+/// block assigns `__all__`, with the star import extensions and platform-conditional names, and
+/// the others extend it.
+fn with_sections(
+    src: &str,
+    sections: Vec<SectionBlock>,
+    names: &[String],
+    extensions: &[StarImport],
+    conditional: &[ConditionalNames],
+    options: &Options,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<String> {
+    let mut blocks = sections
+        .into_iter()
+        .map(|section| (Some(section.name), section.start..section.end))
+        .collect::<Vec<_>>();
+    if let FileState::YesAll(start, end) = get_file_state(src) {
+        if !blocks
+            .iter()
+            .any(|(_, range)| range.start < end && start < range.end)
+        {
+            blocks.push((None, start..end));
+        }
+    }
+    blocks.sort_by_key(|(_, range)| range.start);
+    let filters = blocks
+        .iter()
+        .map(|(name, _)| {
+            let section = options.sections.get(name.as_deref()?)?;
+            Some(section).filter(|section| !section.is_catch_all())
+        })
+        .collect::<Vec<_>>();
+    let definitions = if filters
+        .iter()
+        .flatten()
+        .any(|section| !section.kinds.is_empty())
+    {
+        NameParser::parse(src, options)?.take_definitions()
+    } else {
+        HashMap::new()
+    };
+    let mut block_names = vec![Vec::new(); blocks.len()];
+    for name in names {
+        let kind = definitions
+            .get(name)
+            .map_or(Kind::Import, |definition| definition.kind);
+        let index = filters
+            .iter()
+            .position(|section| section.is_some_and(|section| section.matches(name, kind)))
+            .or_else(|| filters.iter().position(Option::is_none));
+        match index {
+            Some(index) => block_names[index].push(name.clone()),
+            None => diagnostics.push(Diagnostic::at_offset(
+                src,
+                blocks[0].1.start,
+                format!("`{name}` matches no section of the module, it isn't exported"),
+            )),
+        }
+    }
+
+    if !options.sort {
+        for ((_, range), names) in blocks.iter().zip(&mut block_names) {
+            *names = keep_order(&listed_names(&src[range.clone()]), std::mem::take(names));
+        }
+    }
+
+    let newline = line_ending(src);
+    let mut new_src = String::with_capacity(src.len() + 64 * blocks.len());
+    let mut copied = 0;
+    for (index, ((name, range), names)) in blocks.iter().zip(&block_names).enumerate() {
+        new_src.push_str(&src[copied..range.start]);
+        let first = index == 0;
+        let dynamic = dynamic_entries(src, range.start, range.end, diagnostics);
+        let block = Block::Names {
+            names,
+            dynamic: &dynamic,
+            extensions: if first { extensions } else { &[] },
+            conditional: if first { conditional } else { &[] },
+        };
+        let section = name.as_deref().map(|name| (name, !first));
+        push_allways_block(&mut new_src, block, section, newline, options);
+        copied = range.end;
+    }
+    new_src.push_str(&src[copied..]);
+    Ok(new_src)
+}
+
+/// The names `block`, the source of an allways block, assigns or adds to `__all__`.
+fn listed_names(block: &str) -> Vec<String> {
+    block_entries(block).0
+}
+
+/// The entries of the lists `block`, the source of an allways block, assigns or adds to
+/// `__all__`: the string literals, and the offset in `block` and source of the others, e.g.
+/// `*_compat.__all__`, which allways can't manage and keeps as they are, by statement.
+fn block_entries(block: &str) -> (Vec<String>, Vec<Vec<(usize, String)>>) {
+    let mut listed = Vec::new();
+    let mut dynamic = Vec::new();
+    let Ok(statements) = Suite::parse(block, "<block>") else {
+        return (listed, dynamic);
+    };
+    for statement in &statements {
+        let Some((
+            ast::Expr::List(ast::ExprList { elts, .. })
+            | ast::Expr::Tuple(ast::ExprTuple { elts, .. }),
+            _,
+        )) = dunder_all_value(statement)
+        else {
+            continue;
+        };
+        let mut entries = Vec::new();
+        for elt in elts {
+            match elt {
+                ast::Expr::Constant(ast::ExprConstant {
+                    value: ast::Constant::Str(name),
+                    ..
+                }) => listed.push(name.clone()),
+                _ => {
+                    let range = usize::from(elt.start())..usize::from(elt.end());
+                    entries.push((range.start, block[range].to_string()));
+                }
+            }
+        }
+        dynamic.push(entries);
+    }
+    (listed, dynamic)
+}
+
+/// The entries of the allways block `src[start..end]` that aren't string literals, by
+/// statement, each reported in `diagnostics` as left as it is.
+fn dynamic_entries(
+    src: &str,
+    start: usize,
+    end: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Vec<String>> {
+    let (_, dynamic) = block_entries(&src[start..end]);
+    dynamic
+        .into_iter()
+        .map(|entries| {
+            entries
+                .into_iter()
+                .map(|(offset, entry)| {
+                    let message = format!(
+                        "`{entry}` in `__all__` isn't a string literal, allways keeps it as it is"
+                    );
+                    diagnostics.push(Diagnostic::at_offset(src, start + offset, message));
+                    entry
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `names` in the order `listed` has them, followed by the ones it doesn't list.
+fn keep_order(listed: &[String], names: Vec<String>) -> Vec<String> {
+    let mut remaining = names.iter().collect::<HashSet<_>>();
+    let mut ordered = Vec::with_capacity(names.len());
+    for name in listed {
+        if remaining.remove(name) {
+            ordered.push(name.clone());
+        }
+    }
+    ordered.extend(
+        names
+            .iter()
+            .filter(|name| remaining.contains(name))
+            .cloned(),
+    );
+    ordered
+}
+
+/// A named allways block, between `# allways: start(<name>)` and `# allways: end(<name>)`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct SectionBlock {
+    pub name: String,
+    /// Byte offset of the start of the start marker line.
+    pub start: usize,
+    /// Byte offset of the end of the end marker line.
+    pub end: usize,
+}
+
+/// The named allways blocks of `src`, in source order.
+///
+/// A start marker pairs with the next end marker of the same name. Unpaired markers are
+/// ignored, as are sections repeating a name or overlapping an earlier one.
+pub(crate) fn find_sections(src: &str) -> Vec<SectionBlock> {
+    let mut sections: Vec<SectionBlock> = Vec::new();
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if let Some(name) = section_name(trimmed, ALLWAYS_START_COMMENT) {
+            if !open.iter().any(|(open, _)| *open == name) {
+                open.push((name, offset));
+            }
+        } else if let Some(name) = section_name(trimmed, ALLWAYS_END_COMMENT) {
+            if let Some(index) = open.iter().position(|(open, _)| *open == name) {
+                let (name, start) = open.remove(index);
+                sections.push(SectionBlock {
+                    name: name.to_string(),
+                    start,
+                    end: offset + line.len(),
+                });
+            }
+        }
+        offset += line.len();
+    }
+    sections.sort_by_key(|section| section.start);
+    let mut found: Vec<SectionBlock> = Vec::with_capacity(sections.len());
+    for section in sections {
+        let repeated = found
+            .iter()
+            .any(|earlier| earlier.name == section.name || section.start < earlier.end);
+        if !repeated {
+            found.push(section);
+        }
+    }
+    found
+}
+
+/// The name of the section `line` is the `marker` of, e.g. `api` for `# allways: start(api)`.
+fn section_name<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.strip_prefix(marker)?
+        .strip_prefix('(')?
+        .strip_suffix(')')
+        .filter(|name| !name.is_empty())
 }
 
 #[derive(PartialEq, Debug)]
-enum FileState {
+pub(crate) enum FileState {
     NoAll,
     YesAll(usize, usize),
 }
 
-fn get_file_state(src: &str) -> FileState {
+impl FileState {
+    /// Whether the byte `offset` lies within the allways block.
+    pub(crate) fn contains(&self, offset: usize) -> bool {
+        match self {
+            FileState::NoAll => false,
+            FileState::YesAll(start, end) => (*start..*end).contains(&offset),
+        }
+    }
+}
+
+pub(crate) fn get_file_state(src: &str) -> FileState {
+    match find_block(src, ALLWAYS_START_COMMENT, ALLWAYS_END_COMMENT) {
+        Some((start, end)) => FileState::YesAll(start, end),
+        None => FileState::NoAll,
+    }
+}
+
+/// Byte offsets of the start of the `start_comment` line and the end of the `end_comment` line.
+///
+/// Lines in strings, e.g. a docstring showing an allways block, don't count.
+pub(crate) fn find_block(
+    src: &str,
+    start_comment: &str,
+    end_comment: &str,
+) -> Option<(usize, usize)> {
     let mut start: Option<usize> = None;
     let mut end: Option<usize> = None;
+    // only looked for once a marker line is found
+    let mut comments: Option<Vec<Range<usize>>> = None;
+    let mut is_comment = |offset| {
+        let comments = comments.get_or_insert_with(|| find_comments(src));
+        starts_comment(comments, offset)
+    };
 
     let mut offset = 0_usize;
-    for line in src.lines() {
-        match line.trim_end() {
-            ALLWAYS_START_COMMENT => {
-                start = Some(offset);
-            }
-            ALLWAYS_END_COMMENT => {
-                end = Some(offset + line.len() + 1);
-            }
-            _ => {}
+    // lines keep their `\n` or `\r\n` so offsets stay exact whatever the line endings
+    for line in src.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == start_comment && is_comment(offset) {
+            start = Some(offset);
+        } else if trimmed == end_comment && is_comment(offset) {
+            end = Some(offset + line.len());
         }
-        offset += line.len() + 1;
+        offset += line.len();
     }
 
     if let (Some(start), Some(end)) = (start, end) {
         if start < end {
-            return FileState::YesAll(start, end);
+            return Some((start, end));
         }
     }
 
-    FileState::NoAll
+    None
 }
 
-fn get_allways_string(names: Vec<String>) -> String {
-    let names_str = names
-        .into_iter()
-        .map(|name| format!("{INDENT}\"{name}\""))
-        .collect::<Vec<_>>()
-        .join(",\n");
-    format!(
-        "\
-{ALLWAYS_START_COMMENT}
-__all__ = [
-{names_str},
-]
-{ALLWAYS_END_COMMENT}
-"
-    )
+/// Append the allways block `block` to `src`, its lines ending with `newline`, or the named
+/// section `section` extending `__all__` when it isn't the first block of the module.
+fn push_allways_block(
+    src: &mut String,
+    block: Block,
+    section: Option<(&str, bool)>,
+    newline: &str,
+    options: &Options,
+) {
+    let mut names_src = String::new();
+    match block {
+        Block::Names {
+            names,
+            dynamic,
+            extensions,
+            conditional,
+        } => {
+            let extend = section.is_some_and(|(_, extend)| extend);
+            let (first, rest) = match dynamic {
+                [first, rest @ ..] => (first.as_slice(), rest),
+                [] => (&[][..], &[][..]),
+            };
+            push_names(&mut names_src, names, first, extend, newline, options);
+            // the entries of the other statements each keep a statement of their own
+            for dynamic in rest.iter().filter(|dynamic| !dynamic.is_empty()) {
+                push_names(&mut names_src, &[], dynamic, true, newline, options);
+            }
+            for extension in extensions {
+                names_src.push_str(&get_extension_string(extension, newline));
+            }
+            for conditional in conditional {
+                push_conditional_names(&mut names_src, conditional, newline, options);
+            }
+        }
+        Block::Import(module) => {
+            names_src.push_str("from .");
+            names_src.push_str(module);
+            names_src.push_str(" import __all__");
+            names_src.push_str(newline);
+        }
+        Block::Empty => {}
+    }
+    let marker = |marker: &str| match section {
+        Some((name, _)) => format!("{marker}({name})"),
+        None => marker.to_string(),
+    };
+    let markers = (marker(ALLWAYS_START_COMMENT), marker(ALLWAYS_END_COMMENT));
+    let mut header = String::new();
+    if let Some(block_header) = options
+        .block_header
+        .as_ref()
+        .filter(|_| !matches!(block, Block::Empty))
+    {
+        for line in block_header.lines() {
+            if !line.starts_with('#') {
+                header.push_str("# ");
+            }
+            header.push_str(line);
+            header.push_str(newline);
+        }
+    }
+    let block_start = src.len();
+    push_template(src, &markers, &header, &names_src, newline, options);
+    if options.block_hash && !matches!(block, Block::Empty) {
+        // the hash covers the lines between the markers, the header comment included
+        let body_start = block_start + markers.0.len() + newline.len();
+        let body_end = src.len() - markers.1.len() - newline.len();
+        let hash = block_hash(&src[body_start..body_end]);
+        header.push_str(&format!("{ALLWAYS_HASH_COMMENT}{hash}{newline}"));
+        src.truncate(block_start);
+        push_template(src, &markers, &header, &names_src, newline, options);
+    }
+}
+
+/// Append the lines of `Options::block_template` to `src`, its placeholders replaced by the
+/// `markers`, `header` and `names` lines, which end with `newline`.
+fn push_template(
+    src: &mut String,
+    markers: &(String, String),
+    header: &str,
+    names: &str,
+    newline: &str,
+    options: &Options,
+) {
+    let lines = match &options.block_template {
+        Some(template) => template.lines().collect(),
+        None => DEFAULT_TEMPLATE.to_vec(),
+    };
+    let has_header = lines.contains(&TemplateLine::Header);
+    for line in lines {
+        match line {
+            TemplateLine::StartMarker => {
+                src.push_str(&markers.0);
+                src.push_str(newline);
+                if !has_header {
+                    src.push_str(header);
+                }
+            }
+            TemplateLine::Header => src.push_str(header),
+            TemplateLine::Names => src.push_str(names),
+            TemplateLine::EndMarker => {
+                src.push_str(&markers.1);
+                src.push_str(newline);
+            }
+            TemplateLine::Text(text) => {
+                src.push_str(text);
+                src.push_str(newline);
+            }
+        }
+    }
 }
 
-fn insert_new_allways_block(src: &str, mut allways_string: String) -> String {
-    allways_string.insert_str(0, "\n\n");
-    allways_string.insert_str(0, src);
-    allways_string
+/// The 64-bit FNV-1a hash of the lines of `body`, whatever their line endings, in hex.
+///
+/// FNV-1a isn't cryptographic, the hash only tells edits apart from generated blocks.
+fn block_hash(body: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for line in body.lines() {
+        for byte in line.trim_end_matches('\r').bytes().chain([b'\n']) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
 }
 
-fn update_allways_block(src: &str, start: usize, end: usize, mut allways_string: String) -> String {
-    allways_string.insert_str(0, &src[..start]);
-    if end < src.len() {
-        allways_string.push_str(&src[end..]);
+/// The offset of the allways block of `src` when it holds a hash that doesn't match the rest of
+/// it, which was then edited since it was generated.
+pub(crate) fn edited_block(src: &str) -> Option<usize> {
+    let FileState::YesAll(start, end) = get_file_state(src) else {
+        return None;
+    };
+    let block = &src[start..end];
+    let mut hash = None;
+    let mut body = String::with_capacity(block.len());
+    // the markers aren't part of the hash
+    let lines = block.lines().collect::<Vec<_>>();
+    for line in &lines[1..lines.len() - 1] {
+        match line.trim_end().strip_prefix(ALLWAYS_HASH_COMMENT) {
+            Some(found) if hash.is_none() => hash = Some(found),
+            _ => {
+                body.push_str(line);
+                body.push('\n');
+            }
+        }
     }
-    allways_string
+    hash.filter(|&hash| hash != block_hash(&body))
+        .map(|_| start)
 }
 
-fn get_public_names(src: &str) -> Result<Vec<String>> {
-    let mut public_names = src
-        .parse::<NameParser>()?
+/// Append `__all__ = [...]` listing `names`, and then the `dynamic` entries as they are, to
+/// `src`, or `__all__ += [...]` to `extend` it.
+fn push_names(
+    src: &mut String,
+    names: &[String],
+    dynamic: &[String],
+    extend: bool,
+    newline: &str,
+    options: &Options,
+) {
+    let indent = match options.block_style {
+        BlockStyle::Allways => options.indent.as_str(),
+        BlockStyle::Black => "    ",
+    };
+    let assign = if extend {
+        "__all__ += ["
+    } else {
+        "__all__ = ["
+    };
+    let push_entry = |src: &mut String, index: usize| match names.get(index) {
+        Some(name) => {
+            src.push('"');
+            src.push_str(name);
+            src.push('"');
+        }
+        None => src.push_str(&dynamic[index - names.len()]),
+    };
+    let entries = names.len() + dynamic.len();
+    if entries == 0 {
+        src.push_str(assign);
+        src.push(']');
+    } else if options.block_style == BlockStyle::Black
+        && dynamic.iter().all(|entry| !entry.contains('\n'))
+        && fits_on_one_line(assign, names, dynamic, options)
+    {
+        // without the magic trailing comma, which would make black explode it
+        src.push_str(assign);
+        for index in 0..entries {
+            if index > 0 {
+                src.push_str(", ");
+            }
+            push_entry(src, index);
+        }
+        src.push(']');
+    } else {
+        src.push_str(assign);
+        src.push_str(newline);
+        for index in 0..entries {
+            src.push_str(indent);
+            push_entry(src, index);
+            src.push(',');
+            src.push_str(newline);
+        }
+        src.push(']');
+    }
+    src.push_str(newline);
+}
+
+/// Append `if <condition>: __all__ += [...]` to `src`.
+fn push_conditional_names(
+    src: &mut String,
+    conditional: &ConditionalNames,
+    newline: &str,
+    options: &Options,
+) {
+    let indent = match options.block_style {
+        BlockStyle::Allways => options.indent.as_str(),
+        BlockStyle::Black => "    ",
+    };
+    src.push_str("if ");
+    src.push_str(&conditional.condition);
+    src.push(':');
+    src.push_str(newline);
+    src.push_str(indent);
+    src.push_str("__all__ += [");
+    src.push_str(newline);
+    for name in &conditional.names {
+        src.push_str(indent);
+        src.push_str(indent);
+        src.push('"');
+        src.push_str(name);
+        src.push_str("\",");
+        src.push_str(newline);
+    }
+    src.push_str(indent);
+    src.push(']');
+    src.push_str(newline);
+}
+
+/// Whether `__all__ = ["a", "b", *c]`, starting with `assign`, fits in the line length,
+/// counting characters like black.
+fn fits_on_one_line(assign: &str, names: &[String], dynamic: &[String], options: &Options) -> bool {
+    let entries_len = names
+        .iter()
+        .map(|name| name.chars().count() + 2)
+        .chain(dynamic.iter().map(|entry| entry.chars().count()))
+        .sum::<usize>()
+        + (names.len() + dynamic.len() - 1) * 2;
+    assign.len() + 1 + entries_len <= options.line_length
+}
+
+/// Extend `__all__` at runtime with the `__all__` of a star-imported module.
+///
+/// `from module import *` doesn't bind `module`, so its `__all__` is imported under a
+/// private alias.
+fn get_extension_string(star_import: &StarImport, newline: &str) -> String {
+    let module = star_import.module.as_deref();
+    let alias = format!("_{}_all", module.unwrap_or("package").replace('.', "_"));
+    let dots = ".".repeat(star_import.level as usize);
+    let module = module.unwrap_or_default();
+    format!("from {dots}{module} import __all__ as {alias}{newline}__all__ += {alias}{newline}")
+}
+
+/// The dominant line ending of `src`, `\r\n` or `\n`.
+pub(crate) fn line_ending(src: &str) -> &'static str {
+    let lines = src.matches('\n').count();
+    let crlf = src.matches("\r\n").count();
+    if crlf * 2 > lines {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// The offset of the line following the last import of the import section of `src`, as isort
+/// sees it: the imports at the top of the module, after its docstring, and those of every
+/// section starting after an `# isort: split` comment.
+///
+/// `None` when there are no imports, or `src` doesn't parse.
+fn import_section_end(src: &str) -> Option<usize> {
+    let statements = timed_parse(|| Suite::parse(src, "<embedded>")).ok()?;
+    let mut end = None;
+    let mut in_section = true;
+    let mut previous_end = 0;
+    for (index, statement) in statements.iter().enumerate() {
+        let start = usize::from(statement.start());
+        if !in_section && is_split(&src[previous_end..start]) {
+            in_section = true;
+        }
+        previous_end = usize::from(statement.end());
+        if !in_section {
+            continue;
+        }
+        match statement {
+            Stmt::Import(_) | Stmt::ImportFrom(_) => {
+                // trailing comments, e.g. `# noqa`, stay with the import
+                end = Some(
+                    src[previous_end..]
+                        .find('\n')
+                        .map_or(src.len(), |newline| previous_end + newline + 1),
+                );
+            }
+            Stmt::Expr(ast::StmtExpr { value, .. }) if index == 0 && value.is_constant_expr() => {}
+            _ => in_section = false,
+        }
+    }
+    end
+}
+
+/// Whether `between`, the source between two statements, holds an `# isort: split` comment.
+fn is_split(between: &str) -> bool {
+    between.lines().any(|line| {
+        line.trim()
+            .strip_prefix('#')
+            .is_some_and(|comment| comment.trim() == "isort: split")
+    })
+}
+
+/// The blank lines `src` separates statements with: 1 if it never uses more, otherwise 2.
+fn blank_lines(src: &str) -> usize {
+    let mut blank = 0;
+    let mut most = 0;
+    for line in src.trim().lines() {
+        if line.trim().is_empty() {
+            blank += 1;
+            most = most.max(blank);
+        } else {
+            blank = 0;
+        }
+    }
+    if most == 1 {
+        1
+    } else {
+        2
+    }
+}
+
+/// `src` without its allways block nor the blank lines separating it.
+fn remove_allways_block(src: &str, start: usize, end: usize) -> String {
+    let head = src[..start].trim_end();
+    let tail = src[end..].trim_start_matches(['\r', '\n']);
+    if head.is_empty() {
+        return tail.to_string();
+    }
+    let newline = line_ending(src);
+    if tail.is_empty() {
+        format!("{head}{newline}")
+    } else {
+        format!("{head}{newline}{newline}{tail}")
+    }
+}
+
+/// The names a module exports, as found by a [`NameProvider`].
+#[derive(Debug, Default)]
+pub struct PublicNames {
+    /// Sorted ignoring case.
+    pub names: Vec<String>,
+    /// Star imports of modules that couldn't be found on disk.
+    pub unresolved: Vec<StarImport>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// `__all__` is assigned outside the allways block, adding another would contradict it.
+    pub conflict: bool,
+    /// Names only exported on some platforms, with `Options::platform_conditional`.
+    pub conditional: Vec<ConditionalNames>,
+}
+
+/// Names exported only when a test on the platform holds, e.g. `sys.platform == "win32"`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConditionalNames {
+    /// The python expression of the test.
+    pub condition: String,
+    /// Sorted ignoring case.
+    pub names: Vec<String>,
+}
+
+pub(crate) fn get_public_names(
+    src: &str,
+    path: Option<&Path>,
+    options: &Options,
+) -> Result<PublicNames> {
+    public_names_of(NameParser::parse(src, options)?, src, path, options)
+}
+
+/// [`get_public_names`] of `src`, already parsed by `parser`.
+pub(crate) fn public_names_of(
+    mut parser: NameParser,
+    src: &str,
+    path: Option<&Path>,
+    options: &Options,
+) -> Result<PublicNames> {
+    let mut diagnostics = parser.take_diagnostics();
+    let decorated = parser.take_decorated();
+    let dunder_all_statements = parser.take_dunder_all_statements();
+    let definitions = parser.take_definitions();
+    let platform_names = parser.take_platform_names();
+    let platform_star_imports = parser.take_platform_star_imports();
+    // the condition each name is bound under, `None` once bound under different ones
+    let mut conditions: HashMap<String, Option<PlatformCondition>> = HashMap::new();
+    let mut add_condition = |name: &String, condition: &PlatformCondition| {
+        conditions
+            .entry(name.clone())
+            .and_modify(|bound| {
+                if bound.as_ref() != Some(condition) {
+                    *bound = None;
+                }
+            })
+            .or_insert_with(|| Some(condition.clone()));
+    };
+    let mut unconditional = HashSet::new();
+    let mut unresolved = Vec::new();
+    let mut names = HashSet::new();
+    for star_import in parser.take_star_imports() {
+        let Some(path) = path else {
+            unresolved.push(star_import);
+            continue;
+        };
+        match expand_star_import(path, &star_import, options) {
+            Ok(Some(expanded)) => {
+                match platform_star_imports.get(&star_import.offset) {
+                    Some(condition) => expanded
+                        .iter()
+                        .for_each(|name| add_condition(name, condition)),
+                    None if options.platform_conditional => {
+                        unconditional.extend(expanded.iter().cloned())
+                    }
+                    None => {}
+                }
+                names.extend(expanded);
+            }
+            Ok(None) => unresolved.push(star_import),
+            Err(err) => diagnostics.push(Diagnostic::at_offset(
+                src,
+                star_import.offset,
+                format!("Could not expand star import: {err}"),
+            )),
+        }
+    }
+    for (name, condition) in &platform_names {
+        add_condition(name, condition);
+    }
+    // the names of the module itself win over those of a star import
+    let conditions = conditions
+        .into_iter()
+        .filter_map(|(name, condition)| {
+            let bound = parser.contains(&name) && !platform_names.contains_key(&name);
+            let condition = condition.filter(|_| !bound && !unconditional.contains(&name))?;
+            Some((name, condition))
+        })
+        .collect::<HashMap<_, _>>();
+    names.extend(parser);
+    if options.decorated_only {
+        names.retain(|name| decorated.contains(name));
+        unresolved.clear();
+    }
+    let mut public_names = names
+        .into_iter()
+        .filter(|s| !s.starts_with('_') || decorated.contains(s))
+        .collect::<HashSet<_>>();
+    if let Some(filter) = &options.name_filter {
+        public_names.retain(|name| {
+            let definition = definitions.get(name);
+            filter.export(&ExportCandidate {
+                name,
+                kind: definition.map(|definition| definition.kind),
+                span: definition.map(|definition| definition.offset..definition.end),
+            })
+        });
+    }
+
+    let file_state = get_file_state(src);
+    let sections = find_sections(src);
+    // an adopted `__all__` is where the block goes
+    let adopted = adopted_assignment(src, path, options);
+    let outside = dunder_all_statements
         .into_iter()
-        .filter(|s| !s.starts_with('_'))
+        .filter(|statement| {
+            let in_section = sections
+                .iter()
+                .any(|section| (section.start..section.end).contains(&statement.offset));
+            let is_adopted = adopted
+                .as_ref()
+                .is_some_and(|range| range.start == statement.offset);
+            !in_section && !is_adopted && !file_state.contains(statement.offset)
+        })
         .collect::<Vec<_>>();
+    // the block replaces what statements before it did to `__all__`, so their changes are made
+    // in it, while statements after it change the list it assigns at runtime
+    let block_start = adopted.map_or_else(|| block_offset(src, options), |range| range.start);
+    // once assigned outside the block, `__all__` is the module's to modify
+    let conflict = outside
+        .iter()
+        .any(|statement| statement.kind == DunderAllKind::Assign);
+    for statement in outside {
+        let before = statement.offset < block_start;
+        let message = match statement.kind {
+            DunderAllKind::Assign => {
+                "`__all__` is assigned outside the allways block, leaving the file untouched"
+            }
+            _ if conflict => continue,
+            DunderAllKind::Add(_) | DunderAllKind::Remove(_) if !before => {
+                "`__all__` is modified after the allways block, which doesn't account for it"
+            }
+            DunderAllKind::Add(Some(added)) => {
+                public_names.extend(added);
+                "`__all__` is modified before the allways block assigns it, the change is made in \
+                 the block instead"
+            }
+            DunderAllKind::Remove(Some(removed)) => {
+                for name in removed {
+                    public_names.remove(&name);
+                }
+                "`__all__` is modified before the allways block assigns it, the change is made in \
+                 the block instead"
+            }
+            DunderAllKind::Add(None) | DunderAllKind::Remove(None) => {
+                "`__all__` is modified outside the allways block by names that aren't literals"
+            }
+        };
+        diagnostics.push(Diagnostic::at_offset(
+            src,
+            statement.offset,
+            String::from(message),
+        ));
+    }
+
+    // grouped by condition, in the order of the tests in the source
+    let mut conditional = BTreeMap::new();
+    public_names.retain(|name| {
+        let Some(condition) = conditions.get(name) else {
+            return true;
+        };
+        let Some(python) = condition_src(src, condition) else {
+            return true;
+        };
+        let key = condition
+            .iter()
+            .map(|(test, negated)| (test.start, test.end, *negated))
+            .collect::<Vec<_>>();
+        let names: &mut ConditionalNames =
+            conditional.entry(key).or_insert_with(|| ConditionalNames {
+                condition: python,
+                names: Vec::new(),
+            });
+        names.names.push(name.clone());
+        false
+    });
+    let conditional = conditional
+        .into_values()
+        .map(|mut conditional| {
+            conditional.names.sort_by(case_insensitive_cmp);
+            conditional
+        })
+        .collect();
+
+    let mut public_names = public_names.into_iter().collect::<Vec<_>>();
     public_names.sort_by(case_insensitive_cmp);
-    Ok(public_names)
+    Ok(PublicNames {
+        names: public_names,
+        unresolved,
+        diagnostics,
+        conflict,
+        conditional,
+    })
+}
+
+/// The python expression of `condition`, `None` when one of its tests spans several lines.
+fn condition_src(src: &str, condition: &PlatformCondition) -> Option<String> {
+    let tests = condition
+        .iter()
+        .map(|(test, negated)| {
+            let test = src.get(test.clone()).filter(|test| !test.contains('\n'))?;
+            Some(match (negated, condition.len()) {
+                (true, _) => format!("not ({test})"),
+                (false, 1) => test.to_string(),
+                (false, _) => format!("({test})"),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(tests.join(" and "))
 }
 
-fn case_insensitive_cmp(left: &String, right: &String) -> Ordering {
+pub(crate) fn case_insensitive_cmp(left: &String, right: &String) -> Ordering {
     let cmp = left.to_lowercase().cmp(&right.to_lowercase());
     if let Ordering::Equal = cmp {
         left.cmp(right)
     } else {
         cmp
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::options::Section;
+
+    fn fix(src: &str) -> String {
+        do_it_allways(src, None, &Options::default())
+            .unwrap()
+            .src
+            .unwrap()
+    }
+
+    #[test]
+    fn public_names() {
+        let src = "
+C = 2
+a = 1
+def foo():
+    ...
+__all__ = []
+_fooey = 3
+bar = 3
+";
+        assert_eq!(
+            get_public_names(src, None, &Options::default())
+                .unwrap()
+                .names,
+            vec![
+                String::from("a"),
+                String::from("bar"),
+                String::from("C"),
+                String::from("foo"),
+            ]
+        );
+    }
+
+    #[test]
+    fn star_import_expanded() {
+        let root = tempfile::tempdir().unwrap();
+        let init = root.path().join("__init__.py");
+        std::fs::write(root.path().join("_foo.py"), "A = 1\n_b = 2\n").unwrap();
+        let src = "
+from ._foo import *
+";
+        let outcome = do_it_allways(src, Some(&init), &Options::default()).unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "
+from ._foo import *
+
+
+# allways: start
+__all__ = [
+    \"A\",
+]
+# allways: end
+"
+        );
+    }
+
+    #[test]
+    fn star_import_fallback() {
+        let src = "
+from numpy import *
+from . import *
+";
+        let options = Options {
+            star_import_fallback: true,
+            ..Default::default()
+        };
+        let outcome = do_it_allways(src, None, &options).unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "
+from numpy import *
+from . import *
+
+
+# allways: start
+__all__ = []
+from numpy import __all__ as _numpy_all
+__all__ += _numpy_all
+from . import __all__ as _package_all
+__all__ += _package_all
+# allways: end
+"
+        );
+        assert!(do_it_allways(src, None, &Options::default())
+            .unwrap()
+            .src
+            .is_none());
+    }
+
+    #[test]
+    fn decorated_only() {
+        let src = "
+from public import public
+
+@public
+def foo():
+    ...
+
+@public
+def _bar():
+    ...
+
+def baz():
+    ...
+";
+        let options = Options {
+            export_decorators: vec![String::from("public")],
+            decorated_only: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_public_names(src, None, &options).unwrap().names,
+            vec![String::from("_bar"), String::from("foo")]
+        );
+    }
+
+    #[test]
+    fn dunder_all_modified_before_block() {
+        let src = "
+from ._lazy import load
+__all__.append('lazy_thing')
+__all__.remove('load')
+__all__.extend(load.__all__)
+
+
+# allways: start
+__all__ = [
+    \"load\",
+]
+# allways: end
+";
+        let outcome = do_it_allways(src, None, &Options::default()).unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "
+from ._lazy import load
+__all__.append('lazy_thing')
+__all__.remove('load')
+__all__.extend(load.__all__)
+
+
+# allways: start
+__all__ = [
+    \"lazy_thing\",
+]
+# allways: end
+"
+        );
+        let lines = outcome
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [3, 4, 5]);
+        assert_eq!(
+            outcome.diagnostics[2].message,
+            "`__all__` is modified outside the allways block by names that aren't literals"
+        );
+    }
+
+    #[test]
+    fn dunder_all_modified_after_block() {
+        // folding these in would make `remove` raise and list `lazy_thing` twice
+        let src = "
+from ._lazy import load
+
+
+# allways: start
+__all__ = [
+    \"load\",
+]
+# allways: end
+
+__all__.append('lazy_thing')
+__all__.remove('load')
+";
+        let outcome = do_it_allways(src, None, &Options::default()).unwrap();
+        assert_eq!(outcome.src.unwrap(), src);
+        assert_eq!(
+            outcome.diagnostics,
+            [11, 12].map(|line| Diagnostic {
+                line,
+                message: String::from(
+                    "`__all__` is modified after the allways block, which doesn't account for it"
+                ),
+                rule: None,
+            })
+        );
+    }
+
+    #[test]
+    fn dunder_all_assigned_outside_block() {
+        let src = "
+A = 1
+__all__ = ['A']
+";
+        let outcome = do_it_allways(src, None, &Options::default()).unwrap();
+        assert_eq!(outcome.src, None);
+        assert_eq!(outcome.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn private_names_fixed_with_fix_lints() {
+        let src = "\
+def _helper(): ...
+def foo(): ...
+
+
+# allways: start
+__all__ = [
+    \"foo\",
+]
+# allways: end
+__all__ += [\"_helper\"]
+";
+        let outcome = do_it_allways(src, None, &Options::default()).unwrap();
+        assert_eq!(outcome.src.unwrap(), src);
+        let options = Options::builder().fix_lints(true).build();
+        let outcome = do_it_allways(src, None, &options).unwrap();
+        assert_eq!(outcome.src.unwrap(), src.replace("[\"_helper\"]", "[]"));
+
+        // files whose block is left untouched are fixed all the same
+        let require_markers = Options::builder()
+            .fix_lints(true)
+            .require_markers(true)
+            .build();
+        let src = "def foo(): ...\n__all__ += [\"_helper\"]\n";
+        assert_eq!(
+            do_it_allways(src, None, &require_markers)
+                .unwrap()
+                .src
+                .unwrap(),
+            "def foo(): ...\n__all__ += []\n"
+        );
+        let src = "def foo(): ...\n__all__ = [\"foo\", \"_helper\"]\n";
+        let outcome = do_it_allways(src, None, &options).unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "def foo(): ...\n__all__ = [\"foo\"]\n"
+        );
+        // but for a malformed block
+        let src = "def foo(): ...\n# allways: start\n__all__ = [\"_helper\"]\n";
+        assert_eq!(do_it_allways(src, None, &options).unwrap().src, None);
+    }
 
     #[test]
-    fn public_names() {
-        let src = "
-C = 2
-a = 1
-def foo():
-    ...
-__all__ = []
-_fooey = 3
-bar = 3
+    fn lists_fixed_with_fix_lints() {
+        let src = "\
+def foo(): ...
+
+
+# allways: start
+__all__ = [
+    \"foo\",
+]
+# allways: end
+__all__ += [\"bar\", \"bar\"]
 ";
+        let outcome = do_it_allways(src, None, &Options::default()).unwrap();
+        assert_eq!(outcome.src.unwrap(), src);
+        let options = Options::builder().fix_lints(true).build();
+        let outcome = do_it_allways(src, None, &options).unwrap();
         assert_eq!(
-            get_public_names(src).unwrap(),
-            vec![
-                String::from("a"),
-                String::from("bar"),
-                String::from("C"),
-                String::from("foo"),
-            ]
+            outcome.src.unwrap(),
+            src.replace("[\"bar\", \"bar\"]", "[\"bar\"]")
+        );
+
+        // hand-written lists of modules without a block are sorted and de-duplicated too
+        let require_markers = Options::builder()
+            .fix_lints(true)
+            .require_markers(true)
+            .build();
+        let src = "def a(): ...\n__all__ += [\"a\", \"a\"]\n";
+        assert_eq!(
+            do_it_allways(src, None, &require_markers)
+                .unwrap()
+                .src
+                .unwrap(),
+            "def a(): ...\n__all__ += [\"a\"]\n"
+        );
+        let src = "def a(): ...\ndef b(): ...\n__all__ = [\"b\", \"a\", \"b\"]\n";
+        assert_eq!(
+            do_it_allways(src, None, &options).unwrap().src.unwrap(),
+            "def a(): ...\ndef b(): ...\n__all__ = [\"a\", \"b\"]\n"
         );
+        // but not with a malformed block
+        let src = "def a(): ...\n# allways: end\n__all__ = [\"b\", \"a\"]\n";
+        assert_eq!(do_it_allways(src, None, &options).unwrap().src, None);
+    }
+
+    #[test]
+    fn lenient_diagnostics_after_skipped_statement() {
+        let src = "ñ = = 1\nnaïve = 2\n__all__ = ['naïve']\n";
+        let options = Options::builder().lenient(true).build();
+        let outcome = do_it_allways(src, None, &options).unwrap();
+        assert_eq!(outcome.src, None);
+        let lines = outcome
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [1, 3]);
     }
 
     #[test]
@@ -169,7 +1647,7 @@ def foo():
     ...
 ";
         assert_eq!(
-            do_it_allways(src).unwrap().unwrap().as_str(),
+            fix(src).as_str(),
             "
 A = 1
 def foo():
@@ -186,6 +1664,603 @@ __all__ = [
         );
     }
 
+    #[test]
+    fn crlf_line_endings() {
+        let src = "import os\r\nimport sys\r\n";
+        let fixed = fix(src);
+        assert_eq!(
+            fixed,
+            "import os\r\nimport sys\r\n\r\n\r\n# allways: start\r\n__all__ = [\r\n    \"os\",\r\n    \"sys\",\r\n]\r\n# allways: end\r\n"
+        );
+        let start = fixed.find("# allways: start").unwrap();
+        assert_eq!(
+            get_file_state(&fixed),
+            FileState::YesAll(start, fixed.len())
+        );
+        assert_eq!(fix(&fixed), fixed);
+        assert_eq!(line_ending("a\nb\r\nc\n"), "\n");
+    }
+
+    #[test]
+    fn no_trailing_newline() {
+        let block = "# allways: start\n__all__ = [\n    \"A\",\n]\n# allways: end";
+        assert_eq!(fix("A = 1"), format!("A = 1\n\n\n{block}\n"));
+        assert_eq!(
+            fix(&format!("A = 1\n\n\n{block}")),
+            format!("A = 1\n\n\n{block}\n")
+        );
+
+        let options = Options {
+            final_newline: false,
+            ..Options::default()
+        };
+        let fix = |src: &str| do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert_eq!(fix("A = 1"), format!("A = 1\n\n\n{block}"));
+        assert_eq!(
+            fix(&format!("A = 1\n\n\n{block}")),
+            format!("A = 1\n\n\n{block}")
+        );
+        assert_eq!(fix("A = 1\n"), format!("A = 1\n\n\n{block}\n"));
+    }
+
+    #[test]
+    fn blank_lines_before_new_block() {
+        let block = "# allways: start\n__all__ = [\n    \"A\",\n    \"B\",\n]\n# allways: end\n";
+        assert_eq!(fix("A = 1\nB = 2\n"), format!("A = 1\nB = 2\n\n\n{block}"));
+        assert_eq!(
+            fix("A = 1\n\nB = 2\n"),
+            format!("A = 1\n\nB = 2\n\n{block}")
+        );
+        assert_eq!(
+            fix("A = 1\n\n\nB = 2\n\n\n\n"),
+            format!("A = 1\n\n\nB = 2\n\n\n{block}")
+        );
+
+        let options = Options {
+            blank_lines: Some(1),
+            ..Options::default()
+        };
+        let fixed = do_it_allways("A = 1\nB = 2\n", None, &options).unwrap().src;
+        assert_eq!(fixed, Some(format!("A = 1\nB = 2\n\n{block}")));
+    }
+
+    #[test]
+    fn black_block_style() {
+        let options = Options::builder()
+            .block_style(BlockStyle::Black)
+            .indent("\t")
+            .build();
+        let fix = |src: &str| do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert_eq!(
+            fix("A = 1\nB = 2\n\nC = 3\n"),
+            "A = 1\nB = 2\n\nC = 3\n\n\n# allways: start\n__all__ = [\"A\", \"B\", \"C\"]\n# allways: end\n"
+        );
+
+        // 88 characters fit, 89 don't
+        let name = "x".repeat(74);
+        assert_eq!(format!("__all__ = [\"{name}\"]").len(), 88);
+        assert!(fix(&format!("{name} = 1\n")).contains(&format!("__all__ = [\"{name}\"]\n")));
+        let name = "x".repeat(75);
+        assert!(
+            fix(&format!("{name} = 1\n")).contains(&format!("__all__ = [\n    \"{name}\",\n]\n"))
+        );
+    }
+
+    #[test]
+    fn platform_conditional() {
+        let root = tempfile::tempdir().unwrap();
+        let init = root.path().join("__init__.py");
+        std::fs::write(root.path().join("_win.py"), "def shared(): ...\nWIN = 1\n").unwrap();
+        std::fs::write(
+            root.path().join("_posix.py"),
+            "def shared(): ...\nPOSIX = 1\n",
+        )
+        .unwrap();
+        let src = "\
+import sys
+
+if sys.platform == \"win32\":
+    from ._win import *
+    HANDLE = 1
+else:
+    from ._posix import *
+";
+        let options = Options::builder().platform_conditional(true).build();
+        let fixed = do_it_allways(src, Some(&init), &options)
+            .unwrap()
+            .src
+            .unwrap();
+        assert_eq!(
+            &fixed[src.len()..],
+            "
+
+# allways: start
+__all__ = [
+    \"shared\",
+    \"sys\",
+]
+if sys.platform == \"win32\":
+    __all__ += [
+        \"HANDLE\",
+        \"WIN\",
+    ]
+if not (sys.platform == \"win32\"):
+    __all__ += [
+        \"POSIX\",
+    ]
+# allways: end
+"
+        );
+        let again = do_it_allways(&fixed, Some(&init), &options).unwrap();
+        assert_eq!(again.src.unwrap(), fixed);
+
+        // flattened unless asked for
+        let outcome = do_it_allways(src, Some(&init), &Options::default()).unwrap();
+        assert!(outcome
+            .src
+            .unwrap()
+            .contains("    \"HANDLE\",\n    \"POSIX\",\n"));
+    }
+
+    #[test]
+    fn dynamic_entries_kept() {
+        let src = "\
+from . import _compat
+
+foo = 1
+EXTRA = \"bar\"
+
+
+# allways: start
+__all__ = [
+    \"old\",
+    *_compat.__all__,
+    EXTRA,
+]
+# allways: end
+";
+        let outcome = do_it_allways(src, None, &Options::default()).unwrap();
+        let fixed = outcome.src.unwrap();
+        assert!(fixed.ends_with(
+            "__all__ = [\n    \"EXTRA\",\n    \"foo\",\n    *_compat.__all__,\n    EXTRA,\n]\n# allways: end\n"
+        ));
+        let messages = outcome
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                (
+                    10,
+                    "`*_compat.__all__` in `__all__` isn't a string literal, allways keeps it as it is"
+                ),
+                (
+                    11,
+                    "`EXTRA` in `__all__` isn't a string literal, allways keeps it as it is"
+                ),
+            ]
+        );
+        assert_eq!(
+            do_it_allways(&fixed, None, &Options::default())
+                .unwrap()
+                .src
+                .unwrap(),
+            fixed
+        );
+
+        // the block stays for its dynamic entries when no name is left
+        let src = "# allways: start\n__all__ = [\"gone\", *other]\n# allways: end\n";
+        assert_eq!(
+            do_it_allways(src, None, &Options::default())
+                .unwrap()
+                .src
+                .unwrap(),
+            "# allways: start\n__all__ = [\n    *other,\n]\n# allways: end\n"
+        );
+
+        // each entry stays in the statement it came from
+        let src = "\
+foo = 1
+
+
+# allways: start
+__all__ = [\"foo\", *_compat.__all__]
+__all__ += [\"old\", *_extra.__all__]
+# allways: end
+";
+        let fixed = do_it_allways(src, None, &Options::default())
+            .unwrap()
+            .src
+            .unwrap();
+        assert!(fixed.ends_with(
+            "__all__ = [\n    \"foo\",\n    *_compat.__all__,\n]\n__all__ += [\n    *_extra.__all__,\n]\n# allways: end\n"
+        ));
+    }
+
+    #[test]
+    fn adopt_dunder_all() {
+        let src = "\
+from . import _compat
+
+foo = 1
+bar = 2
+
+__all__ = [
+    \"foo\",
+    \"gone\",
+    *_compat.__all__,
+]
+
+print(__all__)
+";
+        let outcome = do_it_allways(src, None, &Options::default()).unwrap();
+        assert_eq!(outcome.src, None);
+
+        let options = Options::builder().adopt(true).build();
+        let outcome = do_it_allways(src, None, &options).unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "\
+from . import _compat
+
+foo = 1
+bar = 2
+
+# allways: start
+__all__ = [
+    \"bar\",
+    \"foo\",
+    *_compat.__all__,
+]
+# allways: end
+
+print(__all__)
+"
+        );
+        let messages = outcome
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [(
+                9,
+                "`*_compat.__all__` in `__all__` isn't a string literal, allways keeps it as it is"
+            )]
+        );
+
+        // the list is only adopted when allways can tell where it ends
+        for src in [
+            "foo = 1\n__all__ = [\"foo\"]  # public\n",
+            "foo = 1\n__all__ = [\"foo\"]\nif foo:\n    __all__ = []\n",
+            "foo = 1\n__all__ = list(globals())\n",
+        ] {
+            assert_eq!(do_it_allways(src, None, &options).unwrap().src, None);
+        }
+    }
+
+    #[test]
+    fn keep_block_order() {
+        let src = "\
+b = 1
+c = 1
+d = 1
+
+
+# allways: start
+__all__ = [
+    \"c\",
+    \"a\",
+    \"b\",
+]
+# allways: end
+";
+        let options = Options::builder().sort(false).build();
+        let fixed = do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert!(
+            fixed.ends_with("__all__ = [\n    \"c\",\n    \"b\",\n    \"d\",\n]\n# allways: end\n")
+        );
+        assert_eq!(
+            do_it_allways(&fixed, None, &options).unwrap().src.unwrap(),
+            fixed
+        );
+        assert!(fix(src)
+            .ends_with("__all__ = [\n    \"b\",\n    \"c\",\n    \"d\",\n]\n# allways: end\n"));
+    }
+
+    #[test]
+    fn require_markers() {
+        let options = Options::builder().require_markers(true).build();
+        let outcome = do_it_allways("def foo(): ...\n", None, &options).unwrap();
+        assert_eq!(outcome.src, None);
+        assert_eq!(outcome.diagnostics.len(), 1);
+        assert_eq!(outcome.diagnostics[0].rule, Some(Rule::MissingAll));
+        let outcome = do_it_allways("_foo = 1\n", None, &options).unwrap();
+        assert_eq!((outcome.src, outcome.diagnostics.len()), (None, 0));
+
+        let src = "def foo(): ...\n\n\n# allways: start\n# allways: end\n";
+        assert_eq!(
+            do_it_allways(src, None, &options).unwrap().src.unwrap(),
+            "def foo(): ...\n\n\n# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n"
+        );
+    }
+
+    #[test]
+    fn empty_markers() {
+        let options = Options::default();
+        let src = "import os\n\n\ndef foo(): ...\n";
+        let marked = add_markers(src, None, &options).unwrap().unwrap();
+        assert_eq!(
+            marked,
+            "import os\n\n\ndef foo(): ...\n\n\n# allways: start\n# allways: end\n"
+        );
+        assert_eq!(add_markers(&marked, None, &options).unwrap(), None);
+        assert_eq!(add_markers("__all__ = []\n", None, &options).unwrap(), None);
+
+        let options = Options::builder()
+            .placement(Placement::AfterImports)
+            .build();
+        assert_eq!(
+            add_markers(src, None, &options).unwrap().unwrap(),
+            "import os\n\n# allways: start\n# allways: end\n\n\ndef foo(): ...\n"
+        );
+    }
+
+    #[test]
+    fn block_header() {
+        let header = "This list is generated by allways; edit source definitions instead.";
+        let options = Options::builder()
+            .block_header(header.parse().unwrap())
+            .build();
+        let block = "# allways: start\n# This list is generated by allways; edit source definitions instead.\n__all__ = [\n    \"foo\",\n]\n# allways: end\n";
+        let fixed = do_it_allways("foo = 1\n", None, &options)
+            .unwrap()
+            .src
+            .unwrap();
+        assert_eq!(fixed, format!("foo = 1\n\n\n{block}"));
+        // an outdated header is replaced
+        let outdated = fixed.replace("allways; edit", "allways, edit");
+        assert_eq!(
+            do_it_allways(&outdated, None, &options)
+                .unwrap()
+                .src
+                .unwrap(),
+            fixed
+        );
+        // without the option the header goes away
+        assert_eq!(fix(&fixed), fix("foo = 1\n"));
+    }
+
+    #[test]
+    fn block_template() {
+        let template = "{start_marker}\n# Generated, edit the definitions instead.\n{header}\n{names}\n{end_marker}\n";
+        let options = Options::builder()
+            .block_template(template.parse().unwrap())
+            .build();
+        let fixed = do_it_allways("foo = 1\n", None, &options)
+            .unwrap()
+            .src
+            .unwrap();
+        assert_eq!(
+            fixed,
+            "foo = 1\n\n\n# allways: start\n# Generated, edit the definitions instead.\n__all__ = [\n    \"foo\",\n]\n# allways: end\n"
+        );
+        assert_eq!(
+            do_it_allways(&fixed, None, &options).unwrap().src.unwrap(),
+            fixed
+        );
+
+        // the hash goes in place of `{header}` and covers the other lines of the template
+        let options = Options::builder()
+            .block_template(template.parse().unwrap())
+            .block_hash(true)
+            .build();
+        let fixed = do_it_allways("foo = 1\n", None, &options)
+            .unwrap()
+            .src
+            .unwrap();
+        assert!(fixed.contains("instead.\n# allways: fnv1a:"));
+        assert_eq!(edited_block(&fixed), None);
+        assert!(edited_block(&fixed.replace("Generated", "Written")).is_some());
+    }
+
+    #[test]
+    fn named_sections() {
+        let src = "\
+from os import path
+
+API_VERSION = 1
+MAX_SIZE = 2
+
+def fetch(): ...
+
+# allways: start(constants)
+# allways: end(constants)
+
+# allways: start(api)
+__all__ += [\"stale\"]
+# allways: end(api)
+";
+        let options = Options::builder()
+            .section(
+                "constants",
+                Section {
+                    kinds: vec![Kind::Variable],
+                    ..Section::default()
+                },
+            )
+            .build();
+        let fixed = do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert_eq!(
+            &fixed[src.find("# allways: start(constants)").unwrap()..],
+            "\
+# allways: start(constants)
+__all__ = [
+    \"API_VERSION\",
+    \"MAX_SIZE\",
+]
+# allways: end(constants)
+
+# allways: start(api)
+__all__ += [
+    \"fetch\",
+    \"path\",
+]
+# allways: end(api)
+"
+        );
+        assert_eq!(
+            do_it_allways(&fixed, None, &options).unwrap().src.unwrap(),
+            fixed
+        );
+
+        let options = Options::builder()
+            .section(
+                "constants",
+                Section {
+                    include: vec![String::from("*_VERSION")],
+                    ..Section::default()
+                },
+            )
+            .build();
+        let fixed = do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert!(fixed.contains("__all__ = [\n    \"API_VERSION\",\n]\n# allways: end(constants)"));
+        assert!(fixed.contains("__all__ += [\n    \"fetch\",\n    \"MAX_SIZE\",\n    \"path\",\n]"));
+
+        // without a section to fall back to, names matching none are reported
+        let options = Options::builder()
+            .section(
+                "constants",
+                Section {
+                    kinds: vec![Kind::Variable],
+                    ..Section::default()
+                },
+            )
+            .section(
+                "api",
+                Section {
+                    include: vec![String::from("fetch")],
+                    ..Section::default()
+                },
+            )
+            .build();
+        let outcome = do_it_allways(src, None, &options).unwrap();
+        assert!(!outcome.src.unwrap().contains("\"path\""));
+        assert_eq!(
+            outcome.diagnostics,
+            vec![Diagnostic {
+                line: 8,
+                message: String::from("`path` matches no section of the module, it isn't exported"),
+                rule: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn after_imports_placement() {
+        let block = "# allways: start\n__all__ = [\n    \"foo\",\n    \"os\",\n]\n# allways: end\n";
+        let options = Options::builder()
+            .placement(Placement::AfterImports)
+            .build();
+        let fix = |src: &str| do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert_eq!(
+            fix("\"\"\"Docs.\"\"\"\nimport os  # noqa\n\n\ndef foo(): ...\n"),
+            format!("\"\"\"Docs.\"\"\"\nimport os  # noqa\n\n{block}\n\ndef foo(): ...\n")
+        );
+        // comments before the code stay with it
+        assert_eq!(
+            fix("import os\n# the code\nfoo = 1"),
+            format!("import os\n\n{block}\n# the code\nfoo = 1\n")
+        );
+        // nothing after the imports
+        assert_eq!(
+            fix("import os\nimport foo\n"),
+            format!("import os\nimport foo\n\n\n{block}")
+        );
+        assert_eq!(import_section_end("foo = 1\n"), None);
+    }
+
+    #[test]
+    fn foreign_regions_avoided() {
+        let block = "# allways: start\n__all__ = [\n    \"A\",\n    \"B\",\n]\n# allways: end\n";
+        assert_eq!(
+            fix("A = 1\n\n# fmt: off\nB = [1,\n  2]\n"),
+            format!("A = 1\n\n{block}\n# fmt: off\nB = [1,\n  2]\n")
+        );
+        assert_eq!(
+            fix("# fmt: off\nA = B = 1\n"),
+            format!("{block}\n# fmt: off\nA = B = 1\n")
+        );
+
+        let options = Options::builder()
+            .placement(Placement::AfterImports)
+            .build();
+        let src = "# fmt: off\nimport A\nimport B\n# fmt: on\n\ndef _foo(): ...\n";
+        assert_eq!(
+            do_it_allways(src, None, &options).unwrap().src.unwrap(),
+            format!("# fmt: off\nimport A\nimport B\n# fmt: on\n\n{block}\ndef _foo(): ...\n")
+        );
+    }
+
+    #[test]
+    fn isort_split_sections() {
+        let src = "\
+import os
+import sys
+sys.path.insert(0, \"vendor\")
+# isort: split
+import foo
+from bar import (
+    baz,
+)
+
+def qux(): ...
+import late
+";
+        let end = src.find("\n\ndef").unwrap() + 1;
+        assert_eq!(import_section_end(src), Some(end));
+        assert_eq!(
+            import_section_end("import os\nx = 1\nimport late\n"),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn remove_allways_block_without_names() {
+        let block = "# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n";
+        assert_eq!(fix(&format!("_foo = 1\n\n\n{block}")), "_foo = 1\n");
+        assert_eq!(
+            fix(&format!("_foo = 1\n\n\n{block}\nimport _bar\n")),
+            "_foo = 1\n\nimport _bar\n"
+        );
+        assert_eq!(fix(block), "");
+
+        let options = Options::builder().keep_empty_block(true).build();
+        let fixed = do_it_allways(&format!("_foo = 1\n\n\n{block}"), None, &options).unwrap();
+        assert_eq!(
+            fixed.src.unwrap(),
+            "_foo = 1\n\n\n# allways: start\n# allways: end\n"
+        );
+    }
+
+    #[test]
+    fn name_filter() {
+        let src = "import os\n\nclass Foo: ...\n\ndef foo_deprecated(): ...\n\nBAR = 1\n";
+        let options = Options::builder()
+            .name_filter(|candidate: &ExportCandidate| {
+                if candidate.name == "Foo" {
+                    assert_eq!(candidate.span, Some(11..25));
+                }
+                candidate.kind != Some(Kind::Import) && !candidate.name.ends_with("_deprecated")
+            })
+            .build();
+        let names = get_public_names(src, None, &options).unwrap().names;
+        assert_eq!(names, vec!["BAR", "Foo"]);
+    }
+
     #[test]
     fn update_allways_block_without_tail() {
         let src = "
@@ -204,7 +2279,7 @@ __all__ = [
 # allways: end
 ";
         assert_eq!(
-            do_it_allways(src).unwrap().unwrap().as_str(),
+            fix(src).as_str(),
             "
 A = 1
 def foo():
@@ -244,7 +2319,7 @@ __all__ = [
 import sys, os
 ";
         assert_eq!(
-            do_it_allways(src).unwrap().unwrap().as_str(),
+            fix(src).as_str(),
             "
 A = 1
 def foo():
@@ -267,4 +2342,122 @@ import sys, os
 "
         );
     }
+
+    /// Invariants of the fixer over generated modules.
+    mod properties {
+        use proptest::prelude::*;
+        use rustpython_parser::ast::Suite;
+        use rustpython_parser::Parse;
+
+        use super::*;
+
+        const KEYWORDS: [&str; 38] = [
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "case",
+            "class", "continue", "def", "del", "elif", "else", "except", "finally", "for", "from",
+            "global", "if", "import", "in", "is", "lambda", "match", "nonlocal", "not", "or",
+            "pass", "raise", "return", "try", "type", "while", "with", "yield",
+        ];
+
+        /// Public and private names, not dunders which have a meaning of their own.
+        fn identifier() -> impl Strategy<Value = String> {
+            "_{0,2}[a-zA-Z][a-zA-Z0-9_]{0,6}".prop_filter("not a plain name", |name| {
+                !KEYWORDS.contains(&name.as_str()) && !name.ends_with("__")
+            })
+        }
+
+        fn statement() -> impl Strategy<Value = String> {
+            prop_oneof![
+                (identifier(), 0..100_u8).prop_map(|(name, value)| format!("{name} = {value}")),
+                identifier().prop_map(|name| format!("{name}: int = 1")),
+                identifier().prop_map(|name| format!("def {name}():\n    return 1")),
+                identifier().prop_map(|name| format!("class {name}:\n    attr = 1")),
+                identifier().prop_map(|name| format!("import {name}")),
+                (identifier(), identifier())
+                    .prop_map(|(module, name)| format!("from {module} import {name} as {name}")),
+                (identifier(), identifier())
+                    .prop_map(|(a, b)| format!("if FLAG:\n    {a} = 1\nelse:\n    {b} = 2")),
+            ]
+        }
+
+        /// Statements separated by 1 to 3 blank lines, with either line ending and with or
+        /// without a final one.
+        fn module() -> impl Strategy<Value = String> {
+            (
+                prop::collection::vec(statement(), 0..12),
+                1..4_usize,
+                any::<bool>(),
+                any::<bool>(),
+            )
+                .prop_map(|(statements, blank_lines, crlf, final_newline)| {
+                    let mut src = statements.join(&"\n".repeat(blank_lines + 1));
+                    if final_newline {
+                        src.push('\n');
+                    }
+                    if crlf {
+                        src = src.replace('\n', "\r\n");
+                    }
+                    src
+                })
+        }
+
+        fn fixed(src: &str) -> String {
+            do_it_allways(src, None, &Options::default())
+                .unwrap()
+                .src
+                .unwrap_or_else(|| src.to_string())
+        }
+
+        fn is_identifier(name: &str) -> bool {
+            let mut characters = name.chars();
+            characters
+                .next()
+                .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+                && characters.all(|character| character.is_ascii_alphanumeric() || character == '_')
+                && !KEYWORDS.contains(&name)
+        }
+
+        proptest! {
+            #[test]
+            fn idempotent(src in module()) {
+                let once = fixed(&src);
+                prop_assert_eq!(fixed(&once), once);
+            }
+
+            #[test]
+            fn names_are_identifiers(src in module()) {
+                let names = get_public_names(&src, None, &Options::default()).unwrap().names;
+                for name in names {
+                    prop_assert!(is_identifier(&name), "{:?} isn't an identifier", name);
+                }
+            }
+
+            #[test]
+            fn block_parses(src in module()) {
+                let fixed = fixed(&src);
+                if let FileState::YesAll(start, end) = get_file_state(&fixed) {
+                    prop_assert!(Suite::parse(&fixed[start..end], "<block>").is_ok());
+                }
+                prop_assert!(Suite::parse(&fixed, "<fixed>").is_ok());
+            }
+
+            #[test]
+            fn rest_of_file_untouched(src in module()) {
+                let fixed = fixed(&src);
+                let FileState::YesAll(start, end) = get_file_state(&fixed) else {
+                    return Ok(());
+                };
+                // only trailing whitespace is replaced when a block is added
+                prop_assert!(fixed.starts_with(src.trim_end()));
+
+                let newline = line_ending(&fixed);
+                let src = format!("{fixed}{newline}later = 1{newline}");
+                let fixed = self::fixed(&src);
+                let FileState::YesAll(new_start, new_end) = get_file_state(&fixed) else {
+                    panic!("the block was removed");
+                };
+                prop_assert_eq!(&fixed[..new_start], &src[..start]);
+                prop_assert_eq!(&fixed[new_end..], &src[end..]);
+            }
+        }
+    }
 }
@@ -0,0 +1,270 @@
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::manifest::ExportedName;
+use crate::manifest::Manifest;
+
+/// Public names added, removed and renamed between two manifests of a package.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApiDiff {
+    /// Modules whose names changed, sorted by name.
+    pub modules: Vec<ModuleDiff>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModuleDiff {
+    pub module: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// `(old, new)` pairs of a removed and an added name of the same kind, when they are the
+    /// only ones of that kind in the module.
+    pub renamed: Vec<(String, String)>,
+}
+
+impl ApiDiff {
+    /// Whether any public name went away, which breaks code importing it.
+    pub fn is_breaking(&self) -> bool {
+        self.modules
+            .iter()
+            .any(|module| !module.removed.is_empty() || !module.renamed.is_empty())
+    }
+
+    /// A changelog section listing the added and removed names of every module.
+    pub fn markdown(&self) -> String {
+        let mut added = String::new();
+        let mut removed = String::new();
+        for module in &self.modules {
+            if !module.added.is_empty() {
+                let names = quoted(&module.added).collect::<Vec<_>>().join(", ");
+                added.push_str(&format!("- `{}`: {names}\n", module.module));
+            }
+            if !module.removed.is_empty() || !module.renamed.is_empty() {
+                let renamed = module
+                    .renamed
+                    .iter()
+                    .map(|(old, new)| format!("`{old}` (renamed to `{new}`)"));
+                let names = quoted(&module.removed)
+                    .chain(renamed)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                removed.push_str(&format!("- `{}`: {names}\n", module.module));
+            }
+        }
+        let mut sections = Vec::new();
+        if !added.is_empty() {
+            sections.push(format!("### Added public API\n\n{added}"));
+        }
+        if !removed.is_empty() {
+            sections.push(format!("### Removed public API\n\n{removed}"));
+        }
+        sections.join("\n")
+    }
+}
+
+/// Compare the `old` manifest of a package with its `new` one.
+pub fn api_diff(old: &Manifest, new: &Manifest) -> ApiDiff {
+    let modules = old
+        .modules
+        .iter()
+        .chain(&new.modules)
+        .map(|module| module.module.as_str())
+        .collect::<BTreeSet<_>>();
+    ApiDiff {
+        modules: modules
+            .into_iter()
+            .map(|module| diff_names(module, names(old, module), names(new, module)))
+            .filter(|diff| !diff.is_empty())
+            .collect(),
+    }
+}
+
+fn quoted(names: &[String]) -> impl Iterator<Item = String> + '_ {
+    names.iter().map(|name| format!("`{name}`"))
+}
+
+fn names<'a>(manifest: &'a Manifest, module: &str) -> &'a [ExportedName] {
+    manifest
+        .modules
+        .iter()
+        .find(|candidate| candidate.module == module)
+        .map(|candidate| candidate.names.as_slice())
+        .unwrap_or_default()
+}
+
+/// The changes between the `old` and `new` names of `module`.
+pub fn diff_names(module: &str, old: &[ExportedName], new: &[ExportedName]) -> ModuleDiff {
+    let old_names = old
+        .iter()
+        .map(|name| name.name.as_str())
+        .collect::<HashSet<_>>();
+    let new_names = new
+        .iter()
+        .map(|name| name.name.as_str())
+        .collect::<HashSet<_>>();
+    let mut removed = old
+        .iter()
+        .filter(|name| !new_names.contains(name.name.as_str()))
+        .collect::<Vec<_>>();
+    let mut added = new
+        .iter()
+        .filter(|name| !old_names.contains(name.name.as_str()))
+        .collect::<Vec<_>>();
+    let mut renamed = Vec::new();
+    let kinds = removed.iter().map(|name| name.kind).collect::<Vec<_>>();
+    for kind in kinds {
+        let position = |names: &[&ExportedName]| {
+            let mut of_kind = names
+                .iter()
+                .enumerate()
+                .filter(|(_, name)| name.kind == kind);
+            match (of_kind.next(), of_kind.next()) {
+                (Some((index, _)), None) => Some(index),
+                _ => None,
+            }
+        };
+        if let (Some(old_index), Some(new_index)) = (position(&removed), position(&added)) {
+            let old = removed.remove(old_index);
+            let new = added.remove(new_index);
+            renamed.push((old.name.clone(), new.name.clone()));
+        }
+    }
+    ModuleDiff {
+        module: module.to_string(),
+        added: added.into_iter().map(|name| name.name.clone()).collect(),
+        removed: removed.into_iter().map(|name| name.name.clone()).collect(),
+        renamed,
+    }
+}
+
+impl ModuleDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
+impl fmt::Display for ApiDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for module in &self.modules {
+            write!(f, "{module}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ModuleDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let module = &self.module;
+        for name in &self.removed {
+            writeln!(f, "{module}: removed `{name}`")?;
+        }
+        for (old, new) in &self.renamed {
+            writeln!(f, "{module}: renamed `{old}` to `{new}`")?;
+        }
+        for name in &self.added {
+            writeln!(f, "{module}: added `{name}`")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::manifest::ModuleManifest;
+    use crate::name_parser::Kind;
+
+    fn manifest(modules: &[(&str, &[(&str, Kind)])]) -> Manifest {
+        Manifest {
+            modules: modules
+                .iter()
+                .map(|(module, names)| ModuleManifest {
+                    module: module.to_string(),
+                    path: format!("{}.py", module.replace('.', "/")),
+                    names: names
+                        .iter()
+                        .map(|(name, kind)| ExportedName {
+                            name: name.to_string(),
+                            kind: Some(*kind),
+                            line: Some(1),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn added_removed_and_renamed() {
+        let old = manifest(&[
+            ("pkg", &[("foo", Kind::Function), ("Bar", Kind::Class)]),
+            ("pkg._gone", &[("baz", Kind::Variable)]),
+        ]);
+        let new = manifest(&[(
+            "pkg",
+            &[
+                ("foo", Kind::Function),
+                ("Baz", Kind::Class),
+                ("qux", Kind::Function),
+            ],
+        )]);
+        let diff = api_diff(&old, &new);
+        assert_eq!(
+            diff.modules,
+            vec![
+                ModuleDiff {
+                    module: String::from("pkg"),
+                    added: vec![String::from("qux")],
+                    removed: vec![],
+                    renamed: vec![(String::from("Bar"), String::from("Baz"))],
+                },
+                ModuleDiff {
+                    module: String::from("pkg._gone"),
+                    added: vec![],
+                    removed: vec![String::from("baz")],
+                    renamed: vec![],
+                },
+            ]
+        );
+        assert!(diff.is_breaking());
+        assert_eq!(
+            diff.to_string(),
+            "pkg: renamed `Bar` to `Baz`\npkg: added `qux`\npkg._gone: removed `baz`\n"
+        );
+        assert_eq!(
+            diff.markdown(),
+            "### Added public API
+
+- `pkg`: `qux`
+
+### Removed public API
+
+- `pkg`: `Bar` (renamed to `Baz`)
+- `pkg._gone`: `baz`
+"
+        );
+    }
+
+    #[test]
+    fn ambiguous_renames_are_removals() {
+        let old = manifest(&[("pkg", &[("a", Kind::Function), ("b", Kind::Function)])]);
+        let new = manifest(&[("pkg", &[("c", Kind::Function), ("d", Kind::Function)])]);
+        let diff = api_diff(&old, &new);
+        assert_eq!(diff.modules[0].removed, vec!["a", "b"]);
+        assert_eq!(diff.modules[0].added, vec!["c", "d"]);
+        assert!(diff.modules[0].renamed.is_empty());
+    }
+
+    #[test]
+    fn only_additions() {
+        let old = manifest(&[("pkg", &[("foo", Kind::Function)])]);
+        let new = manifest(&[("pkg", &[("foo", Kind::Function), ("bar", Kind::Class)])]);
+        let diff = api_diff(&old, &new);
+        assert!(!diff.is_breaking());
+        assert_eq!(diff.markdown(), "### Added public API\n\n- `pkg`: `bar`\n");
+        assert_eq!(api_diff(&old, &old), ApiDiff::default());
+    }
+}
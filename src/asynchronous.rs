@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tokio::task::JoinSet;
+
+use crate::discover::packages_with;
+use crate::fix::fix_file;
+use crate::fix::FixOutcome;
+use crate::options::Options;
+
+/// [`fix_file`] run on tokio's blocking thread pool.
+pub async fn fix_path(path: impl Into<PathBuf>, options: &Options) -> Result<FixOutcome> {
+    let path = path.into();
+    let options = options.clone();
+    tokio::task::spawn_blocking(move || fix_file(&path, &options)).await?
+}
+
+/// Fix the `__init__.py` of every package in `dir` concurrently, without writing them back.
+///
+/// Outcomes are sorted by path. Namespace packages have no `__init__.py` and are skipped.
+pub async fn fix_dir(
+    dir: impl Into<PathBuf>,
+    options: &Options,
+) -> Result<Vec<(PathBuf, FixOutcome)>> {
+    let dir = dir.into();
+    let walk_options = options.clone();
+    let packages =
+        tokio::task::spawn_blocking(move || packages_with(&dir, &walk_options)).await??;
+    let mut tasks = JoinSet::new();
+    for package in packages.into_iter().filter(|package| !package.namespace) {
+        let init = package.init();
+        let options = options.clone();
+        tasks.spawn_blocking(move || fix_file(&init, &options).map(|outcome| (init, outcome)));
+    }
+    let mut outcomes = Vec::new();
+    while let Some(outcome) = tasks.join_next().await {
+        outcomes.push(outcome??);
+    }
+    outcomes.sort_by(|left, right| left.0.cmp(&right.0));
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fix_packages_concurrently() {
+        let root = tempfile::tempdir().unwrap();
+        for package in ["a", "b", "b/c"] {
+            let dir = root.path().join(package);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("__init__.py"), "def foo(): ...\n").unwrap();
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let outcomes = runtime
+            .block_on(fix_dir(root.path(), &Options::default()))
+            .unwrap();
+        let inits = outcomes
+            .iter()
+            .map(|(init, _)| init.strip_prefix(root.path()).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inits,
+            ["a/__init__.py", "b/__init__.py", "b/c/__init__.py"].map(PathBuf::from)
+        );
+        assert!(outcomes.iter().all(|(_, outcome)| outcome.changed));
+
+        let init = root.path().join("a").join("__init__.py");
+        let outcome = runtime
+            .block_on(fix_path(&init, &Options::default()))
+            .unwrap();
+        assert_eq!(outcome.added, vec!["foo"]);
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+
+use rustpython_parser::ast;
+use rustpython_parser::ast::Ranged;
+use rustpython_parser::ast::Stmt;
+
+use crate::add_all::case_insensitive_cmp;
+use crate::add_all::find_sections;
+use crate::add_all::get_file_state;
+use crate::diagnostic::line_number;
+use crate::name_parser::dunder_all_value;
+use crate::name_parser::parse_module;
+use crate::name_parser::NameParser;
+use crate::noqa;
+use crate::options::Options;
+use crate::rules::Rule;
+
+/// A list or tuple a module-level statement outside the allways block assigns or adds to
+/// `__all__`, e.g. `__all__ += ["foo"]`.
+pub(crate) struct DunderAllList {
+    /// Byte range of the list, its brackets included.
+    pub range: Range<usize>,
+    pub entries: Vec<ListEntry>,
+    /// Whether the list is in brackets or parentheses, which a bare tuple isn't.
+    pub enclosed: bool,
+    /// Whether the list is added to `__all__` rather than assigned to it.
+    pub augmented: bool,
+}
+
+/// An entry of a [`DunderAllList`].
+pub(crate) struct ListEntry {
+    /// The name, `None` when the entry isn't a string literal.
+    pub name: Option<String>,
+    pub range: Range<usize>,
+}
+
+/// The lists of names the `statements` of `src` assign or add to `__all__` outside its allways
+/// block and named sections.
+pub(crate) fn dunder_all_lists(src: &str, statements: &[Stmt]) -> Vec<DunderAllList> {
+    let file_state = get_file_state(src);
+    let sections = find_sections(src);
+    let mut lists = Vec::new();
+    for statement in statements {
+        let offset = usize::from(statement.start());
+        let managed = file_state.contains(offset)
+            || sections
+                .iter()
+                .any(|section| (section.start..section.end).contains(&offset));
+        if managed {
+            continue;
+        }
+        let Some((value, augmented)) = dunder_all_value(statement) else {
+            continue;
+        };
+        let (ast::Expr::List(ast::ExprList { elts, .. })
+        | ast::Expr::Tuple(ast::ExprTuple { elts, .. })) = value
+        else {
+            continue;
+        };
+        let range = usize::from(value.start())..usize::from(value.end());
+        let entries = elts
+            .iter()
+            .map(|elt| ListEntry {
+                name: match elt {
+                    ast::Expr::Constant(ast::ExprConstant {
+                        value: ast::Constant::Str(name),
+                        ..
+                    }) => Some(name.clone()),
+                    _ => None,
+                },
+                range: usize::from(elt.start())..usize::from(elt.end()),
+            })
+            .collect();
+        lists.push(DunderAllList {
+            enclosed: src[range.clone()].starts_with(['[', '(']),
+            augmented,
+            range,
+            entries,
+        });
+    }
+    lists
+}
+
+/// Whether `name` is private, e.g. `_helper`, dunder names like `__version__` aside.
+pub(crate) fn is_private(name: &str) -> bool {
+    name.starts_with('_') && !(name.starts_with("__") && name.ends_with("__"))
+}
+
+/// The private names of `lists` with their offset, leaving out the `decorated` ones, marked for
+/// export with one of `Options::export_decorators`.
+pub(crate) fn private_names(
+    lists: &[DunderAllList],
+    decorated: &HashSet<String>,
+) -> Vec<(usize, String)> {
+    lists
+        .iter()
+        .flat_map(|list| &list.entries)
+        .filter_map(|entry| {
+            let name = entry.name.as_ref().filter(|name| is_private(name))?;
+            Some((entry.range.start, name.clone())).filter(|_| !decorated.contains(name))
+        })
+        .collect()
+}
+
+/// The names of `lists` that are already listed, with their offset.
+pub(crate) fn duplicate_names(lists: &[DunderAllList]) -> Vec<(usize, String)> {
+    let mut listed = HashSet::new();
+    let mut duplicates = Vec::new();
+    for list in lists {
+        // an assignment replaces the names listed before it
+        if !list.augmented {
+            listed.clear();
+        }
+        for entry in &list.entries {
+            let Some(name) = &entry.name else {
+                continue;
+            };
+            if !listed.insert(name) {
+                duplicates.push((entry.range.start, name.clone()));
+            }
+        }
+    }
+    duplicates
+}
+
+/// `src`, the module at `path`, with the problems of the rules enabled in `options` that can be
+/// fixed automatically fixed, `None` when there are none.
+///
+/// Private names (`Rule::PrivateName`) and names already listed (`Rule::DuplicateName`) are
+/// removed from the lists of names assigned or added to `__all__`, and the lists assigned to it
+/// are sorted (`Rule::UnsortedAll`) when they only hold string literals, unless `Options::sort`
+/// is off. A `# allways: noqa`
+/// comment suppressing the rule on the line of an entry, or of the list for sorting, leaves it
+/// as it is.
+pub(crate) fn fix_lints(src: &str, path: Option<&Path>, options: &Options) -> Option<String> {
+    let enabled = |rule| options.rules.severity_in(rule, path).is_some();
+    let private = enabled(Rule::PrivateName);
+    let duplicate = enabled(Rule::DuplicateName);
+    // the order of the lists is kept on purpose without `Options::sort`
+    let unsorted = enabled(Rule::UnsortedAll) && options.sort;
+    if !private && !duplicate && !unsorted {
+        return None;
+    }
+    let suppressed = |offset: usize, rule| noqa::is_suppressed(src, line_number(src, offset), rule);
+    let (statements, _) = parse_module(src, options).ok()?;
+    let lists = dunder_all_lists(src, &statements);
+    let decorated = NameParser::from_statements(src, statements, Vec::new(), options)
+        .map(|mut parser| parser.take_decorated())
+        .unwrap_or_default();
+    let duplicates = duplicate_names(&lists)
+        .into_iter()
+        .map(|(offset, _)| offset)
+        .collect::<HashSet<_>>();
+    let mut edits = Vec::new();
+    for list in &lists {
+        // removing entries of `__all__ = "a", "_b"` would leave a string
+        if !list.enclosed {
+            continue;
+        }
+        let remove = list
+            .entries
+            .iter()
+            .map(|entry| {
+                let offset = entry.range.start;
+                entry.name.as_deref().is_some_and(|name| {
+                    let private = private
+                        && is_private(name)
+                        && !decorated.contains(name)
+                        && !suppressed(offset, Rule::PrivateName);
+                    let duplicate = duplicate
+                        && duplicates.contains(&offset)
+                        && !suppressed(offset, Rule::DuplicateName);
+                    private || duplicate
+                })
+            })
+            .collect::<Vec<_>>();
+        edits.extend(
+            removals(src, list, &remove)
+                .into_iter()
+                .map(|range| (range, String::new())),
+        );
+        if unsorted && !list.augmented && !suppressed(list.range.start, Rule::UnsortedAll) {
+            edits.extend(sorting(src, list, &remove));
+        }
+    }
+    if edits.is_empty() {
+        return None;
+    }
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut fixed = String::with_capacity(src.len());
+    let mut copied = 0;
+    for (range, replacement) in edits {
+        fixed.push_str(&src[copied..range.start]);
+        fixed.push_str(&replacement);
+        copied = range.end;
+    }
+    fixed.push_str(&src[copied..]);
+    Some(fixed)
+}
+
+/// The edits putting the entries of `list` kept after `remove` in order, ignoring case, each
+/// replaced by the source of the one going in its place. None unless they are all string
+/// literals.
+fn sorting(src: &str, list: &DunderAllList, remove: &[bool]) -> Vec<(Range<usize>, String)> {
+    let kept = list
+        .entries
+        .iter()
+        .zip(remove)
+        .filter(|(_, removed)| !**removed)
+        .map(|(entry, _)| entry)
+        .collect::<Vec<_>>();
+    let Some(names) = kept
+        .iter()
+        .map(|entry| entry.name.as_ref())
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Vec::new();
+    };
+    let mut order = (0..kept.len()).collect::<Vec<_>>();
+    order.sort_by(|&left, &right| case_insensitive_cmp(names[left], names[right]));
+    kept.iter()
+        .zip(order)
+        .enumerate()
+        .filter(|(index, (_, from))| index != from)
+        .map(|(_, (entry, from))| {
+            let replacement = src[kept[from].range.clone()].to_string();
+            (entry.range.clone(), replacement)
+        })
+        .collect()
+}
+
+/// The sorted, disjoint byte ranges to delete from `src` to remove the entries of `list` for
+/// which `remove` holds, along with what separates them from the others.
+fn removals(src: &str, list: &DunderAllList, remove: &[bool]) -> Vec<Range<usize>> {
+    let entries = &list.entries;
+    if !remove.contains(&true) {
+        return Vec::new();
+    }
+    if !remove.contains(&false) {
+        // everything between the brackets
+        return vec![list.range.start + 1..list.range.end - 1];
+    }
+    let mut ranges = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if !remove[index] {
+            continue;
+        }
+        let range = if let Some(line) = own_line(src, &entry.range) {
+            line
+        } else if let Some(next) = entries.get(index + 1) {
+            entry.range.start..next.range.start
+        } else {
+            // the last entry goes with the comma after the last entry kept
+            let kept = (0..index).rev().find(|&kept| !remove[kept]);
+            match kept {
+                Some(kept) => entries[kept].range.end..entry.range.end,
+                None => entry.range.clone(),
+            }
+        };
+        ranges.push(range);
+    }
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// The range of the line, line ending included, `entry` is alone on, but for its comma and a
+/// comment.
+fn own_line(src: &str, entry: &Range<usize>) -> Option<Range<usize>> {
+    let start = src[..entry.start]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    let end = src[entry.end..]
+        .find('\n')
+        .map_or(src.len(), |newline| entry.end + newline + 1);
+    let after = src[entry.end..end].trim_start();
+    let after = after.strip_prefix(',').unwrap_or(after).trim();
+    let alone =
+        src[start..entry.start].trim().is_empty() && (after.is_empty() || after.starts_with('#'));
+    alone.then_some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(src: &str) -> Option<String> {
+        fix_lints(src, None, &Options::default())
+    }
+
+    fn lists(src: &str) -> Vec<DunderAllList> {
+        let (statements, _) = parse_module(src, &Options::default()).unwrap();
+        dunder_all_lists(src, &statements)
+    }
+
+    #[test]
+    fn private_names_found() {
+        let src = "\
+def _helper(): ...
+__version__ = \"1.0\"
+__all__ = [\"foo\", \"_helper\", \"__version__\"]
+
+# allways: start
+__all__ += [
+    \"_managed\",
+]
+# allways: end
+";
+        let offset = src.find("\"_helper\"").unwrap();
+        let names = private_names(&lists(src), &HashSet::new());
+        assert_eq!(names, [(offset, String::from("_helper"))]);
+        let decorated = HashSet::from([String::from("_helper")]);
+        assert!(private_names(&lists(src), &decorated).is_empty());
+    }
+
+    #[test]
+    fn private_names_removed() {
+        assert_eq!(
+            fixed("__all__ = [\"a\", \"_b\"]\n").unwrap(),
+            "__all__ = [\"a\"]\n"
+        );
+        assert_eq!(
+            fixed("__all__ = [\"_a\", \"b\", \"_c\", \"_d\"]\n").unwrap(),
+            "__all__ = [\"b\"]\n"
+        );
+        assert_eq!(fixed("__all__ += (\"_a\",)\n").unwrap(), "__all__ += ()\n");
+        assert_eq!(
+            fixed("__all__ = [\n    \"a\",  # public\n    \"_b\",  # oops\n]\n").unwrap(),
+            "__all__ = [\n    \"a\",  # public\n]\n"
+        );
+        assert_eq!(fixed("__all__ = [\"a\", \"__version__\"]\n"), None);
+        // a bare tuple would become a string
+        assert_eq!(fixed("__all__ = \"a\", \"_b\"\n"), None);
+        assert_eq!(
+            fixed("__all__ = [\n    \"_b\",  # allways: noqa ALW010\n]\n"),
+            None
+        );
+
+        let mut options = Options::default();
+        options.rules.ignore(&[Rule::PrivateName]);
+        assert_eq!(fix_lints("__all__ = [\"_b\"]\n", None, &options), None);
+        let options = Options::builder().export_decorators(["public"]).build();
+        let src = "@public\ndef _helper(): ...\n__all__ = [\"_helper\"]\n";
+        assert_eq!(fix_lints(src, None, &options), None);
+    }
+
+    #[test]
+    fn duplicates_found() {
+        let src = "__all__ = [\"a\", \"b\", \"a\"]\n__all__ += [\"b\"]\n__all__ = [\"b\"]\n";
+        let duplicates = duplicate_names(&lists(src))
+            .into_iter()
+            .map(|(offset, name)| (line_number(src, offset), name))
+            .collect::<Vec<_>>();
+        assert_eq!(duplicates, [(1, String::from("a")), (2, String::from("b"))]);
+    }
+
+    #[test]
+    fn duplicates_removed_and_sorted() {
+        assert_eq!(
+            fixed("__all__ = [\"b\", \"a\", \"b\"]\n__all__ += [\"a\", \"c\"]\n").unwrap(),
+            "__all__ = [\"a\", \"b\"]\n__all__ += [\"c\"]\n"
+        );
+        // entries keep their quotes, comments stay on their line
+        assert_eq!(
+            fixed("__all__ = [\n    'b',\n    \"A\",  # first\n    '_c',\n]\n").unwrap(),
+            "__all__ = [\n    \"A\",\n    'b',  # first\n]\n"
+        );
+        // added names keep their order, dynamic lists aren't sorted
+        assert_eq!(fixed("__all__ += [\"b\", \"a\"]\n"), None);
+        assert_eq!(fixed("__all__ = [\"b\", *other.__all__, \"a\"]\n"), None);
+        assert_eq!(
+            fixed("__all__ = [\"b\", \"a\"]  # allways: noqa ALW004\n"),
+            None
+        );
+        let options = Options::builder().sort(false).build();
+        let src = "__all__ = [\"b\", \"a\", \"b\"]\n";
+        assert_eq!(
+            fix_lints(src, None, &options).unwrap(),
+            "__all__ = [\"b\", \"a\"]\n"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::editorconfig;
+use crate::encoding::write_source;
+use crate::encoding::Encoding;
+use crate::options::Options;
+use crate::rules::Rule;
+
+/// Files found up to date by previous runs, skipped until their source or the options change.
+///
+/// Stored in the cache directory as one `<key>\t<path>` line per file, the key coming from
+/// [`cache_key`]. Each version of allways has its own file, see [`Cache::file`].
+#[derive(Clone, Debug)]
+pub struct Cache {
+    path: PathBuf,
+    /// The key of each file when it was last up to date, by absolute path.
+    entries: BTreeMap<PathBuf, u64>,
+    changed: bool,
+}
+
+impl Cache {
+    /// The file holding the cache of this version of allways in `dir`.
+    pub fn file(dir: &Path) -> PathBuf {
+        dir.join(format!("allways-{}.cache", env!("CARGO_PKG_VERSION")))
+    }
+
+    /// The cache stored in `dir`, empty when there is none yet or it can't be read.
+    pub fn load(dir: &Path) -> Self {
+        let path = Self::file(dir);
+        let content = std::fs::read_to_string(&path).unwrap_or_default();
+        // a line that doesn't parse only costs its file a run
+        let entries = content
+            .lines()
+            .filter_map(|line| {
+                let (key, file) = line.split_once('\t')?;
+                let key = u64::from_str_radix(key, 16).ok()?;
+                Some((PathBuf::from(file), key))
+            })
+            .collect();
+        Self {
+            path,
+            entries,
+            changed: false,
+        }
+    }
+
+    /// The number of files known to be up to date.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether `file` was up to date with the source and options `key` stands for.
+    pub fn is_fresh(&self, file: &Path, key: u64) -> bool {
+        self.entries.get(&absolute(file)) == Some(&key)
+    }
+
+    /// Remember `file` as up to date with the source and options `key` stands for.
+    pub fn insert(&mut self, file: &Path, key: u64) {
+        if self.entries.insert(absolute(file), key) != Some(key) {
+            self.changed = true;
+        }
+    }
+
+    /// Write the cache back to its directory, when files were added to it, dropping the entries
+    /// of files that no longer exist.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        self.entries.retain(|file, _| file.exists());
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let content = self
+            .entries
+            .iter()
+            .map(|(file, key)| format!("{key:016x}\t{}\n", file.display()))
+            .collect::<String>();
+        write_source(&self.path, &content, Encoding::Utf8)
+    }
+}
+
+/// The key under which `src`, the module at `path`, is cached once found up to date when
+/// fixed, or `check`ed, with `options`.
+///
+/// `None` when the outcome depends on other files: modules with star imports, with
+/// `Options::exports_module` the package the generated module belongs to, and when checking
+/// with `ALW006` or `ALW007` enabled the stub of the module and the modules it imports from.
+pub fn cache_key(src: &str, path: &Path, check: bool, options: &Options) -> Result<Option<u64>> {
+    if src.contains("import *") || options.exports_module.is_some() {
+        return Ok(None);
+    }
+    let reads_others = [Rule::StubMismatch, Rule::UnknownImport]
+        .into_iter()
+        .any(|rule| options.rules.severity_in(rule, Some(path)).is_some());
+    if check && reads_others {
+        return Ok(None);
+    }
+    // `.editorconfig` settings count as options
+    let options = editorconfig::options_for(Some(path), options)?;
+    let mut hasher = DefaultHasher::new();
+    src.hash(&mut hasher);
+    check.hash(&mut hasher);
+    format!("{options:?}").hash(&mut hasher);
+    Ok(Some(hasher.finish()))
+}
+
+/// The `allways` directory of `XDG_CACHE_HOME`, or else of the platform cache directory:
+/// `~/.cache`, `~/Library/Caches` on macOS or `%LOCALAPPDATA%` on Windows.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = match var("XDG_CACHE_HOME") {
+        Some(base) => base,
+        None if cfg!(windows) => var("LOCALAPPDATA")?,
+        None if cfg!(target_os = "macos") => var("HOME")?.join("Library").join("Caches"),
+        None => var("HOME")?.join(".cache"),
+    };
+    Some(base.join("allways"))
+}
+
+/// `path` joined to the current directory, so that files of different projects don't share
+/// entries.
+fn absolute(path: &Path) -> PathBuf {
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("cache");
+        let module = root.path().join("module.py");
+        let options = Options::default();
+        let key = cache_key("foo = 1\n", &module, false, &options)
+            .unwrap()
+            .unwrap();
+
+        let mut cache = Cache::load(&dir);
+        assert!(cache.is_empty());
+        std::fs::write(&module, "foo = 1\n").unwrap();
+        cache.insert(&module, key);
+        cache.insert(&root.path().join("deleted.py"), key);
+        cache.save().unwrap();
+
+        let cache = Cache::load(&dir);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.is_fresh(&module, key));
+        let changed = cache_key("foo = 2\n", &module, false, &options)
+            .unwrap()
+            .unwrap();
+        assert!(!cache.is_fresh(&module, changed));
+    }
+
+    #[test]
+    fn keys() {
+        let path = Path::new("module.py");
+        let options = Options::default();
+        let key = |src, check, options: &Options| cache_key(src, path, check, options).unwrap();
+        assert_ne!(
+            key("x = 1\n", false, &options),
+            key("x = 1\n", true, &options)
+        );
+        let sorted = Options::builder().sort(false).build();
+        assert_ne!(
+            key("x = 1\n", false, &options),
+            key("x = 1\n", false, &sorted)
+        );
+        assert_eq!(key("from .a import *\n", false, &options), None);
+
+        // the stub and imported modules aren't part of the key
+        let mut selected = Options::default();
+        selected.rules.select(&[Rule::UnknownImport]);
+        assert_eq!(key("x = 1\n", true, &selected), None);
+        assert!(key("x = 1\n", false, &selected).is_some());
+    }
+}
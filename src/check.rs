@@ -0,0 +1,471 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::add_all::case_insensitive_cmp;
+use crate::add_all::edited_block;
+use crate::add_all::find_sections;
+use crate::add_all::get_file_state;
+use crate::add_all::get_public_names;
+use crate::add_all::public_names_of;
+use crate::add_all::update_block;
+use crate::add_all::FileState;
+use crate::add_all::PublicNames;
+use crate::autofix::dunder_all_lists;
+use crate::autofix::duplicate_names;
+use crate::autofix::private_names;
+use crate::diagnostic::Diagnostic;
+use crate::exports::exports_modules;
+use crate::name_parser::parse_module;
+use crate::name_parser::DunderAllKind;
+use crate::name_parser::ImportFrom;
+use crate::name_parser::NameParser;
+use crate::noqa;
+use crate::options::Options;
+use crate::provider::NameProvider;
+use crate::resolve::defined_names;
+use crate::resolve::is_submodule;
+use crate::resolve::module_names;
+use crate::resolve::resolve_import;
+use crate::rules::Rule;
+
+/// Lint the python module `src` with every rule enabled in `options`.
+///
+/// Warnings found while computing its public names are included too.
+pub fn check(src: &str, path: Option<&Path>, options: &Options) -> Result<Vec<Diagnostic>> {
+    // parsed once, as the language server and the daemon check on every change
+    let (statements, parse_diagnostics) = parse_module(src, options)?;
+    let lists = dunder_all_lists(src, &statements);
+    let mut parser = NameParser::from_statements(src, statements, parse_diagnostics, options)?;
+    let imports = parser.take_imports();
+    let assignment = parser
+        .dunder_all_statements()
+        .iter()
+        .rev()
+        .find(|statement| statement.kind == DunderAllKind::Assign)
+        .map(|statement| statement.offset);
+    let dunder_all = parser.dunder_all().map(<[String]>::to_vec);
+    // star imported names can't be told apart from unknown ones
+    let unknown = dunder_all
+        .iter()
+        .flatten()
+        .filter(|name| parser.star_imports().is_empty() && !parser.contains(name))
+        .cloned()
+        .collect::<Vec<_>>();
+    let decorated = parser.decorated().clone();
+    // the fixes of other rules don't make the block out of date
+    let outcome = update_block(&ParsedNames(Cell::new(Some(parser))), src, path, options)?;
+
+    let mut diagnostics = outcome.diagnostics;
+    let mut lint = |offset, rule, message| {
+        if options.rules.severity_in(rule, path).is_some() {
+            diagnostics.push(Diagnostic::lint(src, offset, rule, message));
+        }
+    };
+    if let Some(new_src) = &outcome.src {
+        match get_file_state(src) {
+            FileState::NoAll if assignment.is_none() => {
+                lint(0, Rule::MissingAll, String::from("`__all__` is missing"))
+            }
+            FileState::YesAll(start, _) if new_src != src => lint(
+                start,
+                Rule::StaleAll,
+                String::from("`__all__` is out of date"),
+            ),
+            // named sections without an allways block, or an adopted `__all__`
+            FileState::NoAll if new_src != src => {
+                let start = find_sections(src)
+                    .first()
+                    .map_or(assignment.unwrap_or(0), |section| section.start);
+                lint(
+                    start,
+                    Rule::StaleAll,
+                    String::from("`__all__` is out of date"),
+                );
+            }
+            _ => {}
+        }
+    }
+    if let Some(offset) = edited_block(src) {
+        let message = String::from("The allways block was edited since it was generated");
+        lint(offset, Rule::EditedBlock, message);
+    }
+    if let (Some(names), Some(offset)) = (&dunder_all, assignment) {
+        for name in &unknown {
+            let message = format!("`{name}` is listed in `__all__` but never defined");
+            lint(offset, Rule::UnknownName, message);
+        }
+        let sorted = names
+            .windows(2)
+            .all(|pair| case_insensitive_cmp(&pair[0], &pair[1]) != Ordering::Greater);
+        // the order of the allways block is kept on purpose without `Options::sort`
+        let kept_order = !options.sort && get_file_state(src).contains(offset);
+        if !sorted && !kept_order {
+            let message = String::from("`__all__` is not sorted");
+            lint(offset, Rule::UnsortedAll, message);
+        }
+    }
+    for (offset, name) in private_names(&lists, &decorated) {
+        let message = format!("`{name}` is private but listed in `__all__`");
+        lint(offset, Rule::PrivateName, message);
+    }
+    for (offset, name) in duplicate_names(&lists) {
+        let message = format!("`{name}` is listed in `__all__` more than once");
+        lint(offset, Rule::DuplicateName, message);
+    }
+    if let Some(path) = path {
+        for message in stub_mismatches(path, options)? {
+            lint(0, Rule::StubMismatch, message);
+        }
+        for (offset, message) in unknown_imports(path, &imports, options)? {
+            lint(offset, Rule::UnknownImport, message);
+        }
+        // reported at the block importing the module
+        let block = match get_file_state(src) {
+            FileState::YesAll(start, _) => start,
+            FileState::NoAll => 0,
+        };
+        for change in exports_modules(src, path, options)? {
+            let name = change
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let message = match change.src {
+                Some(_) => format!("`{name}` is out of date"),
+                None => format!("`{name}` is no longer imported and can be deleted"),
+            };
+            lint(block, Rule::StaleAll, message);
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    Ok(noqa::suppress(src, diagnostics, |rule| {
+        options.rules.severity_in(rule, path).is_some()
+    }))
+}
+
+/// The names of the module [`check`] parsed, handed to [`update_block`] so that it isn't parsed
+/// again.
+struct ParsedNames(Cell<Option<NameParser>>);
+
+impl NameProvider for ParsedNames {
+    fn public_names(
+        &self,
+        src: &str,
+        path: Option<&Path>,
+        options: &Options,
+    ) -> Result<PublicNames> {
+        match self.0.take() {
+            Some(parser) => public_names_of(parser, src, path, options),
+            None => get_public_names(src, path, options),
+        }
+    }
+}
+
+/// Names exported by only one of the module at `path` and its stub, or the stub and its module.
+fn stub_mismatches(path: &Path, options: &Options) -> Result<Vec<String>> {
+    let sibling = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("py") => path.with_extension("pyi"),
+        Some("pyi") => path.with_extension("py"),
+        _ => return Ok(Vec::new()),
+    };
+    if !sibling.is_file() {
+        return Ok(Vec::new());
+    }
+    let mut exported = module_names(path, options)?;
+    let mut sibling_exported = module_names(&sibling, options)?;
+    exported.sort_by(case_insensitive_cmp);
+    sibling_exported.sort_by(case_insensitive_cmp);
+    let file_name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let (name, sibling_name) = (file_name(path), file_name(&sibling));
+    let mut messages = Vec::new();
+    for export in exported
+        .iter()
+        .filter(|export| !sibling_exported.contains(export))
+    {
+        messages.push(format!(
+            "`{export}` is exported by `{name}` but not by `{sibling_name}`"
+        ));
+    }
+    for export in sibling_exported
+        .iter()
+        .filter(|export| !exported.contains(export))
+    {
+        messages.push(format!(
+            "`{export}` is exported by `{sibling_name}` but not by `{name}`"
+        ));
+    }
+    Ok(messages)
+}
+
+/// Names imported from modules on disk that don't define them.
+fn unknown_imports(
+    importer: &Path,
+    imports: &[ImportFrom],
+    options: &Options,
+) -> Result<Vec<(usize, String)>> {
+    let mut defined = HashMap::new();
+    let mut unknown = Vec::new();
+    let root = options.project_root();
+    for import in imports {
+        let module = import.module.as_deref();
+        let Some(source) = resolve_import(importer, module, import.level, &root) else {
+            continue;
+        };
+        let names = match defined.entry(source.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(defined_names(&source, options)?),
+        };
+        let Some(names) = names else {
+            continue;
+        };
+        let dots = ".".repeat(import.level as usize);
+        let module = module.unwrap_or_default();
+        for name in &import.names {
+            if !names.contains(name) && !is_submodule(&source, name) {
+                let message = format!("`{name}` is not defined in `{dots}{module}`");
+                unknown.push((import.offset, message));
+            }
+        }
+    }
+    Ok(unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::add_all::do_it_allways;
+
+    fn codes(src: &str, options: &Options) -> Vec<(usize, Option<Rule>)> {
+        check(src, None, options)
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.rule))
+            .collect()
+    }
+
+    #[test]
+    fn missing_all() {
+        let src = "def foo():\n    ...\n";
+        assert_eq!(
+            codes(src, &Options::default()),
+            vec![(1, Some(Rule::MissingAll))]
+        );
+    }
+
+    #[test]
+    fn stale_all() {
+        let src = "
+A = 1
+B = 2
+
+
+# allways: start
+__all__ = [
+    \"A\",
+]
+# allways: end
+";
+        assert_eq!(
+            codes(src, &Options::default()),
+            vec![(6, Some(Rule::StaleAll))]
+        );
+    }
+
+    #[test]
+    fn edited_block() {
+        let options = Options::builder().block_hash(true).build();
+        let src = "A = 1\nB = 2\n";
+        let fixed = do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert!(fixed.contains("# allways: start\n# allways: fnv1a:"));
+        assert_eq!(codes(&fixed, &options), vec![]);
+        // the hash stays valid with other line endings
+        assert_eq!(codes(&fixed.replace('\n', "\r\n"), &options), vec![]);
+
+        let edited = fixed.replace("    \"B\",\n", "");
+        assert_eq!(
+            codes(&edited, &options),
+            vec![(5, Some(Rule::StaleAll)), (5, Some(Rule::EditedBlock))]
+        );
+    }
+
+    #[test]
+    fn unknown_and_unsorted_names() {
+        let src = "
+b = 1
+__all__ = ['b', 'a']
+";
+        assert_eq!(
+            codes(src, &Options::default()),
+            vec![
+                (3, None),
+                (3, Some(Rule::UnknownName)),
+                (3, Some(Rule::UnsortedAll)),
+            ]
+        );
+    }
+
+    #[test]
+    fn private_names() {
+        let src = "
+def _helper(): ...
+def foo(): ...
+__all__ = [
+    '_helper',
+    'foo',
+]
+";
+        assert_eq!(
+            codes(src, &Options::default()),
+            vec![(4, None), (5, Some(Rule::PrivateName))]
+        );
+        // fixed with `fix_lints`, in a file assigning `__all__` itself too
+        let options = Options::builder().fix_lints(true).build();
+        let fixed = do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert_eq!(codes(&fixed, &options), vec![(4, None)]);
+
+        // and along with the block
+        let src = "
+def _helper(): ...
+def foo(): ...
+
+
+# allways: start
+__all__ = [
+    \"foo\",
+]
+# allways: end
+__all__ += ['_helper']
+";
+        let private = (11, Some(Rule::PrivateName));
+        assert!(codes(src, &options).contains(&private));
+        let fixed = do_it_allways(src, None, &options).unwrap().src.unwrap();
+        assert!(fixed.ends_with("# allways: end\n__all__ += []\n"));
+        assert!(!codes(&fixed, &options).contains(&private));
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let src = "
+a = 1
+b = 1
+__all__ = ['a', 'a', 'b']
+__all__ += ['b']
+";
+        assert_eq!(
+            codes(src, &Options::default()),
+            vec![
+                (4, None),
+                (4, Some(Rule::DuplicateName)),
+                (5, Some(Rule::DuplicateName)),
+            ]
+        );
+    }
+
+    #[test]
+    fn stale_exports_module() {
+        let dir = tempfile::tempdir().unwrap();
+        let init = dir.path().join("__init__.py");
+        let src = "foo = 1\n\n\n# allways: start\nfrom ._exports import __all__\n# allways: end\n";
+        let options = Options::builder()
+            .exports_module("_exports".parse().unwrap())
+            .build();
+        let diagnostics = check(src, Some(&init), &options).unwrap();
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(4, "`_exports.py` is out of date")]);
+    }
+
+    #[test]
+    fn ignored_rules() {
+        let src = "
+b = 1
+__all__ = ['b', 'a']
+";
+        let mut options = Options::default();
+        options
+            .rules
+            .ignore(&[Rule::UnknownName, Rule::UnsortedAll]);
+        assert_eq!(codes(src, &options), vec![(3, None)]);
+    }
+
+    #[test]
+    fn noqa_comment() {
+        let src = "
+b = 1
+__all__ = ['b', 'a']  # allways: noqa ALW003, ALW004
+";
+        assert_eq!(codes(src, &Options::default()), vec![(3, None)]);
+    }
+
+    #[test]
+    fn stub_mismatch() {
+        let root = tempfile::tempdir().unwrap();
+        let module = root.path().join("mod.py");
+        let src = "def foo():\n    ...\ndef bar():\n    ...\n__all__ = ['bar', 'foo']\n";
+        std::fs::write(&module, src).unwrap();
+        std::fs::write(
+            root.path().join("mod.pyi"),
+            "def foo() -> None: ...\ndef baz() -> None: ...\n",
+        )
+        .unwrap();
+        let messages = check(src, Some(&module), &Options::default())
+            .unwrap()
+            .into_iter()
+            .filter(|diagnostic| diagnostic.rule == Some(Rule::StubMismatch))
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                String::from("`bar` is exported by `mod.py` but not by `mod.pyi`"),
+                String::from("`baz` is exported by `mod.pyi` but not by `mod.py`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_import() {
+        let root = tempfile::tempdir().unwrap();
+        let init = root.path().join("__init__.py");
+        std::fs::write(root.path().join("utils.py"), "def helper():\n    ...\n").unwrap();
+        let src = "from .utils import helper, missing\nfrom . import utils\n";
+        let messages = check(src, Some(&init), &Options::default())
+            .unwrap()
+            .into_iter()
+            .filter(|diagnostic| diagnostic.rule == Some(Rule::UnknownImport))
+            .map(|diagnostic| (diagnostic.line, diagnostic.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![(1, String::from("`missing` is not defined in `.utils`"))]
+        );
+    }
+
+    #[test]
+    fn up_to_date() {
+        let src = "
+A = 1
+
+
+# allways: start
+__all__ = [
+    \"A\",
+]
+# allways: end
+";
+        assert!(codes(src, &Options::default()).is_empty());
+    }
+}
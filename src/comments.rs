@@ -0,0 +1,75 @@
+use std::ops::Range;
+
+/// The byte ranges of the comments of the python source `src`, from their `#` to the end of
+/// their line, line ending excluded.
+///
+/// A `#` in a string literal, a docstring included, doesn't start a comment. Strings are found
+/// by their quotes alone, which is all it takes to skip them.
+pub(crate) fn comments(src: &str) -> Vec<Range<usize>> {
+    let bytes = src.as_bytes();
+    let mut comments = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'#' => {
+                let end = src[index..]
+                    .find(['\r', '\n'])
+                    .map_or(src.len(), |end| index + end);
+                comments.push(index..end);
+                index = end;
+            }
+            quote @ (b'"' | b'\'') => index = string_end(bytes, index, quote),
+            _ => index += 1,
+        }
+    }
+    comments
+}
+
+/// Whether one of `comments`, as found by [`comments`], starts at the byte `offset`.
+pub(crate) fn starts_comment(comments: &[Range<usize>], offset: usize) -> bool {
+    comments
+        .binary_search_by_key(&offset, |comment| comment.start)
+        .is_ok()
+}
+
+/// The offset past the string literal opened by `quote` at `start`, or of the line ending of a
+/// single-quoted one left open.
+fn string_end(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let triple = bytes[start..].starts_with(&[quote; 3]);
+    let mut index = start + if triple { 3 } else { 1 };
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 2,
+            b'\r' | b'\n' if !triple => return index,
+            byte if byte == quote && !triple => return index + 1,
+            byte if byte == quote && bytes[index..].starts_with(&[quote; 3]) => return index + 3,
+            _ => index += 1,
+        }
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(src: &str) -> Vec<&str> {
+        comments(src).into_iter().map(|range| &src[range]).collect()
+    }
+
+    #[test]
+    fn strings_skipped() {
+        let src = "\
+\"\"\"
+# allways: start
+\"\"\"
+x = '#'  # a comment
+y = \"it's \\\" # not\"  # another
+z = '''don't # stop'''
+# last\r
+";
+        assert_eq!(texts(src), ["# a comment", "# another", "# last"]);
+        // an unterminated string ends with its line
+        assert_eq!(texts("x = 'open\n# comment\n"), ["# comment"]);
+    }
+}
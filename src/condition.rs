@@ -0,0 +1,241 @@
+use rustpython_parser::ast;
+use rustpython_parser::ast::CmpOp;
+use rustpython_parser::ast::Expr;
+
+use crate::options::Options;
+use crate::options::PythonVersion;
+
+/// Statically evaluate a branch condition, `None` when its value is only known at runtime.
+pub fn evaluate(test: &Expr, options: &Options) -> Option<bool> {
+    match test {
+        Expr::Constant(ast::ExprConstant {
+            value: ast::Constant::Bool(value),
+            ..
+        }) => Some(*value),
+        Expr::Constant(ast::ExprConstant {
+            value: ast::Constant::Int(value),
+            ..
+        }) => Some(value.to_string() != "0"),
+        Expr::UnaryOp(ast::ExprUnaryOp {
+            op: ast::UnaryOp::Not,
+            operand,
+            ..
+        }) => evaluate(operand, options).map(|value| !value),
+        Expr::Compare(compare) => evaluate_main_guard(compare)
+            .or_else(|| evaluate_version_compare(compare, options.target_version?)),
+        _ => None,
+    }
+}
+
+/// Whether `test` depends on the platform, through `sys.platform`, `os.name` or a function of
+/// the `platform` module, e.g. `sys.platform.startswith("linux")`.
+pub fn is_platform_test(test: &Expr) -> bool {
+    match test {
+        Expr::Attribute(ast::ExprAttribute { value, attr, .. }) => {
+            let module = match value.as_ref() {
+                Expr::Name(ast::ExprName { id, .. }) => id.as_str(),
+                _ => "",
+            };
+            matches!(
+                (module, attr.as_str()),
+                ("sys", "platform") | ("os", "name")
+            ) || (module == "platform")
+                || is_platform_test(value)
+        }
+        Expr::Call(ast::ExprCall { func, args, .. }) => {
+            is_platform_test(func) || args.iter().any(is_platform_test)
+        }
+        Expr::Compare(ast::ExprCompare {
+            left, comparators, ..
+        }) => is_platform_test(left) || comparators.iter().any(is_platform_test),
+        Expr::BoolOp(ast::ExprBoolOp { values, .. }) => values.iter().any(is_platform_test),
+        Expr::UnaryOp(ast::ExprUnaryOp { operand, .. }) => is_platform_test(operand),
+        _ => false,
+    }
+}
+
+/// Evaluate `__name__ == "__main__"`, which never holds while the module is being imported.
+fn evaluate_main_guard(compare: &ast::ExprCompare) -> Option<bool> {
+    let ([op], [right]) = (compare.ops.as_slice(), compare.comparators.as_slice()) else {
+        return None;
+    };
+    let left = compare.left.as_ref();
+    let is_guard = (is_dunder_name(left) && is_main_str(right))
+        || (is_main_str(left) && is_dunder_name(right));
+    if !is_guard {
+        return None;
+    }
+    match op {
+        CmpOp::Eq => Some(false),
+        CmpOp::NotEq => Some(true),
+        _ => None,
+    }
+}
+
+fn is_dunder_name(expr: &Expr) -> bool {
+    matches!(expr, Expr::Name(ast::ExprName { id, .. }) if id.as_str() == "__name__")
+}
+
+fn is_main_str(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Constant(ast::ExprConstant {
+            value: ast::Constant::Str(value),
+            ..
+        }) if value == "__main__"
+    )
+}
+
+/// Evaluate `sys.version_info <op> (major, minor)` for every python at least `target`.
+fn evaluate_version_compare(compare: &ast::ExprCompare, target: PythonVersion) -> Option<bool> {
+    let ([op], [right]) = (compare.ops.as_slice(), compare.comparators.as_slice()) else {
+        return None;
+    };
+    let left = compare.left.as_ref();
+    let (is_greater, version) = if is_version_info(left) {
+        (is_greater(op)?, version_tuple(right)?)
+    } else if is_version_info(right) {
+        (!is_greater(op)?, version_tuple(left)?)
+    } else {
+        return None;
+    };
+
+    // `sys.version_info` is always longer than the tuple it is compared against, so only
+    // its leading elements matter and `>` behaves like `>=` (likewise `<` like `<=`).
+    let minimum = [target.major, target.minor];
+    let always_at_least = match version.len() {
+        0 => return None,
+        n @ (1 | 2) => minimum[..n] >= version[..],
+        _ => minimum[..] > version[..2],
+    };
+    always_at_least.then_some(is_greater)
+}
+
+/// `Some(true)` for `>`/`>=`, `Some(false)` for `<`/`<=`.
+fn is_greater(op: &CmpOp) -> Option<bool> {
+    match op {
+        CmpOp::Gt | CmpOp::GtE => Some(true),
+        CmpOp::Lt | CmpOp::LtE => Some(false),
+        _ => None,
+    }
+}
+
+fn is_version_info(expr: &Expr) -> bool {
+    let Expr::Attribute(ast::ExprAttribute { value, attr, .. }) = expr else {
+        return false;
+    };
+    attr.as_str() == "version_info"
+        && matches!(value.as_ref(), Expr::Name(ast::ExprName { id, .. }) if id.as_str() == "sys")
+}
+
+fn version_tuple(expr: &Expr) -> Option<Vec<u32>> {
+    let Expr::Tuple(ast::ExprTuple { elts, .. }) = expr else {
+        return None;
+    };
+    elts.iter()
+        .map(|elt| match elt {
+            Expr::Constant(ast::ExprConstant {
+                value: ast::Constant::Int(value),
+                ..
+            }) => value.to_string().parse().ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustpython_parser::Parse;
+
+    fn evaluate_for(src: &str, target_version: Option<PythonVersion>) -> Option<bool> {
+        let test = Expr::parse(src, "<test>").unwrap();
+        let options = Options {
+            target_version,
+            ..Default::default()
+        };
+        evaluate(&test, &options)
+    }
+
+    #[test]
+    fn constant_literals() {
+        for (src, expected) in [
+            ("True", Some(true)),
+            ("False", Some(false)),
+            ("1", Some(true)),
+            ("0", Some(false)),
+            ("not True", Some(false)),
+            ("not 0", Some(true)),
+            ("not not False", Some(false)),
+            ("foo", None),
+            ("not foo", None),
+        ] {
+            assert_eq!(evaluate_for(src, None), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn main_guard() {
+        for (src, expected) in [
+            ("__name__ == \"__main__\"", Some(false)),
+            ("'__main__' == __name__", Some(false)),
+            ("__name__ != '__main__'", Some(true)),
+            ("__name__ == 'foo'", None),
+            ("name == '__main__'", None),
+        ] {
+            assert_eq!(evaluate_for(src, None), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn version_info_without_target() {
+        assert_eq!(evaluate_for("sys.version_info >= (3, 11)", None), None);
+    }
+
+    #[test]
+    fn version_info_at_least() {
+        let target = Some(PythonVersion::new(3, 11));
+        for (src, expected) in [
+            ("sys.version_info >= (3, 11)", true),
+            ("sys.version_info > (3, 10)", true),
+            ("sys.version_info >= (3,)", true),
+            ("sys.version_info < (3, 11)", false),
+            ("sys.version_info <= (3, 8)", false),
+            ("(3, 8) <= sys.version_info", true),
+            ("(3, 8) > sys.version_info", false),
+        ] {
+            assert_eq!(evaluate_for(src, target), Some(expected), "{src}");
+        }
+    }
+
+    #[test]
+    fn platform_tests() {
+        for (src, expected) in [
+            ("sys.platform == 'win32'", true),
+            ("sys.platform.startswith('linux')", true),
+            ("os.name != 'nt'", true),
+            ("platform.system() == 'Darwin'", true),
+            ("not (sys.platform == 'win32' or FLAG)", true),
+            ("sys.version_info >= (3, 8)", false),
+            ("name == 'nt'", false),
+        ] {
+            let test = Expr::parse(src, "<test>").unwrap();
+            assert_eq!(is_platform_test(&test), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn version_info_unknown() {
+        let target = Some(PythonVersion::new(3, 8));
+        for src in [
+            "sys.version_info >= (3, 11)",
+            "sys.version_info < (3, 11)",
+            "sys.version_info >= (3, 8, 2)",
+            "sys.version_info == (3, 8)",
+            "sys.platform >= (3, 8)",
+        ] {
+            assert_eq!(evaluate_for(src, target), None, "{src}");
+        }
+    }
+}
@@ -0,0 +1,747 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::options::BlockHeader;
+use crate::options::BlockStyle;
+use crate::options::BlockTemplate;
+use crate::options::ModuleName;
+use crate::options::Options;
+use crate::options::Placement;
+use crate::options::PythonVersion;
+use crate::options::Section;
+use crate::rules::Rule;
+use crate::rules::Severity;
+
+const ALLWAYS_TOML: &str = "allways.toml";
+const PYPROJECT: &str = "pyproject.toml";
+const SETUP_CFG: &str = "setup.cfg";
+
+/// The keys of an `allways.toml`, the `[tool.allways]` table of a `pyproject.toml`, or the
+/// `[allways]` section of a `setup.cfg`.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub type_aliases: Option<bool>,
+    pub lenient: Option<bool>,
+    pub type_checking: Option<bool>,
+    pub target_version: Option<PythonVersion>,
+    pub star_import_fallback: Option<bool>,
+    pub global_declarations: Option<bool>,
+    pub warn_conditional: Option<bool>,
+    pub export_decorators: Option<Vec<String>>,
+    pub decorated_only: Option<bool>,
+    pub select: Option<Vec<Rule>>,
+    pub ignore: Option<Vec<Rule>>,
+    pub severity: Option<BTreeMap<Rule, Severity>>,
+    pub per_file_ignores: Option<BTreeMap<String, Vec<Rule>>>,
+    pub create_init: Option<bool>,
+    pub final_newline: Option<bool>,
+    pub blank_lines: Option<usize>,
+    pub editorconfig: Option<bool>,
+    pub block_style: Option<BlockStyle>,
+    /// Taken from `[tool.black]` when missing.
+    pub line_length: Option<usize>,
+    pub placement: Option<Placement>,
+    pub exports_module: Option<ModuleName>,
+    pub platform_conditional: Option<bool>,
+    pub block_hash: Option<bool>,
+    pub block_template: Option<BlockTemplate>,
+    pub block_header: Option<BlockHeader>,
+    pub sections: Option<BTreeMap<String, Section>>,
+    pub sort: Option<bool>,
+    pub exclude: Option<Vec<String>>,
+    pub force_exclude: Option<bool>,
+    pub default_exclude: Option<bool>,
+    pub require_markers: Option<bool>,
+    pub include_site_packages: Option<bool>,
+    /// Named groups of settings applied over the others when selected, e.g. with
+    /// `--profile library`.
+    pub profiles: Option<BTreeMap<String, Config>>,
+    pub adopt: Option<bool>,
+    pub fix_lints: Option<bool>,
+    pub keep_empty_block: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct PyProject {
+    tool: Option<Tool>,
+}
+
+#[derive(Deserialize)]
+struct Tool {
+    allways: Option<Config>,
+    black: Option<Black>,
+}
+
+/// The settings of `[tool.black]` allways follows.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Black {
+    line_length: Option<usize>,
+}
+
+impl Config {
+    /// Search `start` and its ancestors for an `allways.toml`, a `pyproject.toml` with a
+    /// `[tool.allways]` table or a `setup.cfg` with an `[allways]` section.
+    ///
+    /// The closest directory with any wins, see [`Config::in_dir`].
+    pub fn discover(start: &Path) -> Result<Option<Self>> {
+        Ok(Self::find(start)?.map(|(_, config)| config))
+    }
+
+    /// [`Config::discover`], along with the directory the config was found in.
+    pub fn find(start: &Path) -> Result<Option<(PathBuf, Self)>> {
+        for dir in start.ancestors() {
+            if let Some(config) = Self::in_dir(dir)? {
+                return Ok(Some((dir.to_path_buf(), config)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The files of the directory [`Config::find`] finds the config in that may hold one, by
+    /// precedence.
+    pub fn find_files(start: &Path) -> Result<Vec<PathBuf>> {
+        let Some((dir, _)) = Self::find(start)? else {
+            return Ok(Vec::new());
+        };
+        Ok([ALLWAYS_TOML, PYPROJECT, SETUP_CFG]
+            .into_iter()
+            .map(|name| dir.join(name))
+            .filter(|path| path.is_file())
+            .collect())
+    }
+
+    /// The config of `dir` itself: its `allways.toml`, over its `pyproject.toml`, over its
+    /// `setup.cfg`.
+    pub fn in_dir(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(ALLWAYS_TOML);
+        if path.is_file() {
+            return Self::from_allways_toml(&path).map(Some);
+        }
+        let path = dir.join(PYPROJECT);
+        if path.is_file() {
+            if let Some(config) = Self::from_pyproject(&path)? {
+                return Ok(Some(config));
+            }
+        }
+        let path = dir.join(SETUP_CFG);
+        if path.is_file() {
+            return Self::from_setup_cfg(&path);
+        }
+        Ok(None)
+    }
+
+    /// The configs of the directories below `root` down to `dir`, outermost first, each
+    /// overriding the keys it sets, e.g. for a `tests` directory, along with their directory.
+    /// Empty when `dir` isn't below `root`.
+    pub fn nested(root: &Path, dir: &Path) -> Result<Vec<(PathBuf, Self)>> {
+        let Ok(relative) = dir.strip_prefix(root) else {
+            return Ok(Vec::new());
+        };
+        let mut configs = Vec::new();
+        let mut dir = root.to_path_buf();
+        for component in relative.components() {
+            dir.push(component);
+            if let Some(config) = Self::in_dir(&dir)? {
+                configs.push((dir.clone(), config));
+            }
+        }
+        Ok(configs)
+    }
+
+    /// An `allways.toml`, holding the keys of `[tool.allways]` at the top level.
+    pub fn from_allways_toml(path: &Path) -> Result<Self> {
+        let src = std::fs::read_to_string(path)?;
+        toml::from_str(&src).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The config of the file at `path`, read as an `allways.toml`, a `setup.cfg` or a
+    /// `pyproject.toml` depending on its name.
+    pub fn from_file(path: &Path) -> Result<Option<Self>> {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(ALLWAYS_TOML) => Self::from_allways_toml(path).map(Some),
+            Some(SETUP_CFG) => Self::from_setup_cfg(path),
+            _ => Self::from_pyproject(path),
+        }
+    }
+
+    pub fn from_pyproject(path: &Path) -> Result<Option<Self>> {
+        let src = std::fs::read_to_string(path)?;
+        Self::from_pyproject_str(&src)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn from_pyproject_str(src: &str) -> Result<Option<Self>> {
+        let pyproject: PyProject = toml::from_str(src)?;
+        let Some(tool) = pyproject.tool else {
+            return Ok(None);
+        };
+        let black_line_length = tool.black.and_then(|black| black.line_length);
+        Ok(tool.allways.map(|mut config| {
+            config.line_length = config.line_length.or(black_line_length);
+            config
+        }))
+    }
+
+    pub fn from_setup_cfg(path: &Path) -> Result<Option<Self>> {
+        let src = std::fs::read_to_string(path)?;
+        Self::from_setup_cfg_str(&src)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The `[allways]` section of a `setup.cfg`, holding the keys of `[tool.allways]`.
+    ///
+    /// Keys may use underscores instead of dashes. Lists are separated by commas or lines,
+    /// tables are written one `key: value` per line, e.g. `tests/*: ALW001, ALW002`.
+    fn from_setup_cfg_str(src: &str) -> Result<Option<Self>> {
+        let Some(section) = ini_section(src, "allways") else {
+            return Ok(None);
+        };
+        let mut table = toml::value::Table::new();
+        for (key, value) in section {
+            let key = key.replace('_', "-");
+            let value = match key.as_str() {
+                "export-decorators" | "select" | "ignore" => list(&value),
+                "severity" | "per-file-ignores" => {
+                    let mut entries = toml::value::Table::new();
+                    for line in value.lines().filter(|line| !line.trim().is_empty()) {
+                        let (name, value) = line.split_once([':', '=']).ok_or_else(|| {
+                            anyhow!("Expected `key: value` in {key}, got {line:?}")
+                        })?;
+                        let value = match key.as_str() {
+                            "severity" => toml::Value::String(value.trim().to_string()),
+                            _ => list(value),
+                        };
+                        entries.insert(name.trim().to_string(), value);
+                    }
+                    toml::Value::Table(entries)
+                }
+                _ => scalar(&value),
+            };
+            table.insert(key, value);
+        }
+        Ok(Some(toml::Value::Table(table).try_into()?))
+    }
+
+    /// Settings that contradict each other or have no effect, which parsing lets through.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.force_exclude == Some(true) && self.exclude.as_ref().map_or(true, Vec::is_empty) {
+            problems.push(String::from(
+                "`force-exclude` has no effect without `exclude` patterns",
+            ));
+        }
+        for rule in self.severity.iter().flatten().map(|(rule, _)| rule) {
+            if self.ignore.iter().flatten().any(|ignored| ignored == rule) {
+                problems.push(format!(
+                    "The `severity` of {rule} has no effect, it is ignored"
+                ));
+            }
+        }
+        for (name, profile) in self.profiles.iter().flatten() {
+            if profile.profiles.is_some() {
+                problems.push(format!(
+                    "Profile `{name}` defines profiles, which are never applied"
+                ));
+            }
+            for problem in profile.problems() {
+                problems.push(format!("Profile `{name}`: {problem}"));
+            }
+        }
+        problems
+    }
+
+    /// The settings of the profile `name`.
+    pub fn profile(&self, name: &str) -> Option<&Config> {
+        self.profiles.as_ref()?.get(name)
+    }
+
+    /// The names of the profiles this config defines.
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles
+            .iter()
+            .flatten()
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Override `options` with every setting present in this config.
+    pub fn apply(&self, options: &mut Options) {
+        if let Some(type_aliases) = self.type_aliases {
+            options.type_aliases = type_aliases;
+        }
+        if let Some(lenient) = self.lenient {
+            options.lenient = lenient;
+        }
+        if let Some(type_checking) = self.type_checking {
+            options.type_checking = type_checking;
+        }
+        if let Some(target_version) = self.target_version {
+            options.target_version = Some(target_version);
+        }
+        if let Some(star_import_fallback) = self.star_import_fallback {
+            options.star_import_fallback = star_import_fallback;
+        }
+        if let Some(global_declarations) = self.global_declarations {
+            options.global_declarations = global_declarations;
+        }
+        if let Some(warn_conditional) = self.warn_conditional {
+            options.warn_conditional = warn_conditional;
+        }
+        if let Some(export_decorators) = &self.export_decorators {
+            options.export_decorators = export_decorators.clone();
+        }
+        if let Some(decorated_only) = self.decorated_only {
+            options.decorated_only = decorated_only;
+        }
+        if let Some(select) = &self.select {
+            options.rules.select(select);
+        }
+        if let Some(ignore) = &self.ignore {
+            options.rules.ignore(ignore);
+        }
+        for (&rule, &severity) in self.severity.iter().flatten() {
+            options.rules.set_severity(rule, severity);
+        }
+        for (pattern, rules) in self.per_file_ignores.iter().flatten() {
+            options.rules.ignore_for(pattern.clone(), rules.clone());
+        }
+        if let Some(create_init) = self.create_init {
+            options.create_init = create_init;
+        }
+        if let Some(final_newline) = self.final_newline {
+            options.final_newline = final_newline;
+        }
+        if let Some(blank_lines) = self.blank_lines {
+            options.blank_lines = Some(blank_lines);
+        }
+        if let Some(editorconfig) = self.editorconfig {
+            options.editorconfig = editorconfig;
+        }
+        if let Some(block_style) = self.block_style {
+            options.block_style = block_style;
+        }
+        if let Some(line_length) = self.line_length {
+            options.line_length = line_length;
+        }
+        if let Some(placement) = self.placement {
+            options.placement = placement;
+        }
+        if let Some(exports_module) = &self.exports_module {
+            options.exports_module = Some(exports_module.clone());
+        }
+        if let Some(platform_conditional) = self.platform_conditional {
+            options.platform_conditional = platform_conditional;
+        }
+        if let Some(block_hash) = self.block_hash {
+            options.block_hash = block_hash;
+        }
+        if let Some(block_template) = &self.block_template {
+            options.block_template = Some(block_template.clone());
+        }
+        if let Some(block_header) = &self.block_header {
+            options.block_header = Some(block_header.clone());
+        }
+        if let Some(sections) = &self.sections {
+            options.sections = sections.clone();
+        }
+        if let Some(sort) = self.sort {
+            options.sort = sort;
+        }
+        if let Some(exclude) = &self.exclude {
+            options.exclude = exclude.clone();
+        }
+        if let Some(force_exclude) = self.force_exclude {
+            options.force_exclude = force_exclude;
+        }
+        if let Some(default_exclude) = self.default_exclude {
+            options.default_exclude = default_exclude;
+        }
+        if let Some(require_markers) = self.require_markers {
+            options.require_markers = require_markers;
+        }
+        if let Some(include_site_packages) = self.include_site_packages {
+            options.include_site_packages = include_site_packages;
+        }
+        if let Some(adopt) = self.adopt {
+            options.adopt = adopt;
+        }
+        if let Some(fix_lints) = self.fix_lints {
+            options.fix_lints = fix_lints;
+        }
+        if let Some(keep_empty_block) = self.keep_empty_block {
+            options.keep_empty_block = keep_empty_block;
+        }
+    }
+}
+
+/// The keys and values of `section` in the INI file `src`, values continued on indented lines
+/// joined with newlines.
+fn ini_section(src: &str, section: &str) -> Option<Vec<(String, String)>> {
+    let mut entries: Option<Vec<(String, String)>> = None;
+    let mut current = false;
+    for line in src.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(name) = trimmed
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            current = name.trim() == section;
+            if current {
+                entries.get_or_insert_with(Vec::new);
+            }
+            continue;
+        }
+        let Some(entries) = entries.as_mut().filter(|_| current) else {
+            continue;
+        };
+        match entries.last_mut() {
+            Some((_, value)) if line.starts_with([' ', '\t']) => {
+                value.push('\n');
+                value.push_str(trimmed);
+            }
+            _ => {
+                if let Some((key, value)) = trimmed.split_once(['=', ':']) {
+                    entries.push((key.trim().to_string(), value.trim().to_string()));
+                }
+            }
+        }
+    }
+    entries
+}
+
+/// A list of strings separated by commas or lines.
+fn list(value: &str) -> toml::Value {
+    toml::Value::Array(
+        value
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| toml::Value::String(item.to_string()))
+            .collect(),
+    )
+}
+
+/// A boolean, an integer or a string.
+fn scalar(value: &str) -> toml::Value {
+    match value.to_lowercase().as_str() {
+        "true" => toml::Value::Boolean(true),
+        "false" => toml::Value::Boolean(false),
+        _ => match value.parse() {
+            Ok(integer) => toml::Value::Integer(integer),
+            Err(_) => toml::Value::String(value.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::name_parser::Kind;
+
+    #[test]
+    fn missing_tool_table() {
+        let src = "
+[project]
+name = \"foo\"
+";
+        assert_eq!(Config::from_pyproject_str(src).unwrap(), None);
+    }
+
+    #[test]
+    fn black_line_length() {
+        let src = "
+[tool.black]
+line-length = 100
+
+[tool.allways]
+block-style = \"black\"
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        assert_eq!(config.block_style, Some(BlockStyle::Black));
+        assert_eq!(config.line_length, Some(100));
+    }
+
+    #[test]
+    fn other_tools_only() {
+        let src = "
+[tool.black]
+line-length = 100
+";
+        assert_eq!(Config::from_pyproject_str(src).unwrap(), None);
+    }
+
+    #[test]
+    fn setup_cfg() {
+        let src = "
+[metadata]
+name = foo
+
+[allways]
+type_aliases = false
+target-version = 3.11
+blank-lines = 1
+export-decorators = public, export
+per-file-ignores =
+    tests/*: ALW001, ALW002
+    */__init__.py: ALW004
+severity =
+    ALW004 = warning
+";
+        let config = Config::from_setup_cfg_str(src).unwrap().unwrap();
+        assert_eq!(config.type_aliases, Some(false));
+        assert_eq!(config.target_version, Some(PythonVersion::new(3, 11)));
+        assert_eq!(config.blank_lines, Some(1));
+        assert_eq!(
+            config.export_decorators,
+            Some(vec![String::from("public"), String::from("export")])
+        );
+        assert_eq!(
+            config.per_file_ignores.unwrap()["tests/*"],
+            vec![Rule::MissingAll, Rule::StaleAll]
+        );
+        assert_eq!(
+            config.severity.unwrap()[&Rule::UnsortedAll],
+            Severity::Warning
+        );
+
+        assert_eq!(Config::from_setup_cfg_str("[flake8]\n").unwrap(), None);
+        assert!(Config::from_setup_cfg_str("[allways]\nunknown = 1\n").is_err());
+    }
+
+    #[test]
+    fn pyproject_over_setup_cfg() {
+        let root = tempfile::tempdir().unwrap();
+        let sub = root.path().join("sub");
+        std::fs::create_dir(&sub).unwrap();
+        std::fs::write(
+            root.path().join(PYPROJECT),
+            "[tool.allways]\nlenient = true\n",
+        )
+        .unwrap();
+        std::fs::write(root.path().join(SETUP_CFG), "[allways]\nlenient = false\n").unwrap();
+        let config = Config::discover(&sub).unwrap().unwrap();
+        assert_eq!(config.lenient, Some(true));
+
+        std::fs::write(sub.join(SETUP_CFG), "[allways]\ncreate_init = true\n").unwrap();
+        let config = Config::discover(&sub).unwrap().unwrap();
+        assert_eq!((config.lenient, config.create_init), (None, Some(true)));
+    }
+
+    #[test]
+    fn nested_configs() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let tests = root.join("tests");
+        let unit = tests.join("unit");
+        std::fs::create_dir_all(&unit).unwrap();
+        std::fs::write(root.join(PYPROJECT), "[tool.allways]\nlenient = true\n").unwrap();
+        std::fs::write(tests.join(ALLWAYS_TOML), "require-markers = true\n").unwrap();
+        std::fs::write(tests.join(PYPROJECT), "[tool.allways]\nsort = false\n").unwrap();
+
+        let (dir, config) = Config::find(&unit).unwrap().unwrap();
+        assert_eq!((dir, config.require_markers), (tests.clone(), Some(true)));
+        let nested = Config::nested(root, &unit).unwrap();
+        assert_eq!(nested.len(), 1);
+        let (dir, config) = &nested[0];
+        assert_eq!(dir, &tests);
+        // `allways.toml` wins over `pyproject.toml`
+        assert_eq!((config.require_markers, config.sort), (Some(true), None));
+        assert!(Config::nested(&tests, root).unwrap().is_empty());
+
+        let mut options = Options::default();
+        Config::discover(root).unwrap().unwrap().apply(&mut options);
+        for (_, config) in &nested {
+            config.apply(&mut options);
+        }
+        assert!(options.lenient && options.require_markers);
+    }
+
+    #[test]
+    fn profiles() {
+        let src = "
+[tool.allways]
+lenient = true
+
+[tool.allways.profiles.library]
+type-checking = true
+
+[tool.allways.profiles.app]
+require-markers = true
+lenient = false
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        assert_eq!(
+            config.profile_names().collect::<Vec<_>>(),
+            ["app", "library"]
+        );
+        let mut options = Options::default();
+        config.apply(&mut options);
+        config.profile("app").unwrap().apply(&mut options);
+        assert!(!options.lenient && options.require_markers && !options.type_checking);
+        assert!(config.profile("script").is_none());
+    }
+
+    #[test]
+    fn problems() {
+        let src = "
+[tool.allways]
+force-exclude = true
+ignore = [\"ALW001\"]
+severity = { ALW001 = \"warning\", ALW002 = \"warning\" }
+
+[tool.allways.profiles.app.profiles.nested]
+lenient = true
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        assert_eq!(
+            config.problems(),
+            [
+                "`force-exclude` has no effect without `exclude` patterns",
+                "The `severity` of ALW001 has no effect, it is ignored",
+                "Profile `app` defines profiles, which are never applied",
+            ]
+        );
+        let src = "[tool.allways]\nexclude = [\"build\"]\nforce-exclude = true\n";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        assert!(config.problems().is_empty());
+        // a header the markers would be confused with can't even be read
+        let src = "[tool.allways]\nblock-header = \"# allways: start\"\n";
+        assert!(Config::from_pyproject_str(src).is_err());
+        // nor a module that isn't next to `__init__.py`
+        let src = "[tool.allways]\nexports-module = \"../_exports\"\n";
+        assert!(Config::from_pyproject_str(src).is_err());
+    }
+
+    #[test]
+    fn type_aliases() {
+        let src = "
+[tool.allways]
+type-aliases = false
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        let mut options = Options::default();
+        config.apply(&mut options);
+        assert!(!options.type_aliases);
+    }
+
+    #[test]
+    fn target_version() {
+        let src = "
+[tool.allways]
+target-version = \"3.11\"
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        assert_eq!(config.target_version, Some(PythonVersion::new(3, 11)));
+    }
+
+    #[test]
+    fn sections() {
+        let src = "
+[tool.allways.sections.constants]
+include = [\"*_VERSION\"]
+kinds = [\"variable\"]
+
+[tool.allways.sections.api]
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        let sections = config.sections.unwrap();
+        assert_eq!(
+            sections["constants"],
+            Section {
+                include: vec![String::from("*_VERSION")],
+                kinds: vec![Kind::Variable],
+            }
+        );
+        assert!(sections["api"].is_catch_all());
+    }
+
+    #[test]
+    fn invalid_target_version() {
+        let src = "
+[tool.allways]
+target-version = \"latest\"
+";
+        assert!(Config::from_pyproject_str(src).is_err());
+    }
+
+    #[test]
+    fn export_decorators() {
+        let src = "
+[tool.allways]
+export-decorators = [\"public\", \"export\"]
+decorated-only = true
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        let mut options = Options::default();
+        config.apply(&mut options);
+        assert_eq!(
+            options.export_decorators,
+            vec![String::from("public"), String::from("export")]
+        );
+        assert!(options.decorated_only);
+    }
+
+    #[test]
+    fn rules() {
+        let src = "
+[tool.allways]
+select = [\"ALW001\", \"ALW003\", \"ALW004\"]
+ignore = [\"ALW003\"]
+severity = { ALW004 = \"warning\" }
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        let mut options = Options::default();
+        config.apply(&mut options);
+        assert_eq!(
+            options.rules.severity(Rule::MissingAll),
+            Some(Severity::Error)
+        );
+        assert_eq!(options.rules.severity(Rule::StaleAll), None);
+        assert_eq!(options.rules.severity(Rule::UnknownName), None);
+        assert_eq!(
+            options.rules.severity(Rule::UnsortedAll),
+            Some(Severity::Warning)
+        );
+    }
+
+    #[test]
+    fn per_file_ignores() {
+        let src = "
+[tool.allways]
+per-file-ignores = { \"tests/*\" = [\"ALW001\"], \"*/__init__.py\" = [\"ALW004\"] }
+";
+        let config = Config::from_pyproject_str(src).unwrap().unwrap();
+        let mut options = Options::default();
+        config.apply(&mut options);
+        let init = Path::new("pkg/__init__.py");
+        assert_eq!(
+            options.rules.severity_in(Rule::UnsortedAll, Some(init)),
+            None
+        );
+        assert_eq!(
+            options.rules.severity_in(Rule::MissingAll, Some(init)),
+            Some(Severity::Error)
+        );
+    }
+
+    #[test]
+    fn unknown_key() {
+        let src = "
+[tool.allways]
+fooey = 1
+";
+        assert!(Config::from_pyproject_str(src).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::add_all::case_insensitive_cmp;
+use crate::add_all::get_public_names;
+use crate::autofix::dunder_all_lists;
+use crate::name_parser::parse_module;
+use crate::options::Options;
+
+/// How many of the public names of a module its hand-written `__all__` lists, see [`coverage`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coverage {
+    /// The number of public names `__all__` lists.
+    pub listed: usize,
+    /// The public names `__all__` leaves out, sorted ignoring case.
+    pub missing: Vec<String>,
+}
+
+impl Coverage {
+    /// The number of public names found in the module.
+    pub fn total(&self) -> usize {
+        self.listed + self.missing.len()
+    }
+
+    /// The percentage of public names listed, 100 for a module without any.
+    pub fn percentage(&self) -> f64 {
+        match self.total() {
+            0 => 100.0,
+            total => 100.0 * self.listed as f64 / total as f64,
+        }
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1}% ({}/{} public names listed)",
+            self.percentage(),
+            self.listed,
+            self.total()
+        )
+    }
+}
+
+/// The coverage of the public names of `src`, the module at `path`, by its hand-written
+/// `__all__`.
+///
+/// `None` when no list or tuple is assigned to `__all__` outside the allways block, e.g. when
+/// it is built dynamically. Names added with `__all__ += [...]` count as listed, entries that
+/// aren't string literals don't.
+pub fn coverage(src: &str, path: Option<&Path>, options: &Options) -> Result<Option<Coverage>> {
+    let (statements, _) = parse_module(src, options)?;
+    let lists = dunder_all_lists(src, &statements);
+    if lists.iter().all(|list| list.augmented) {
+        return Ok(None);
+    }
+    let listed: Vec<String> = lists
+        .into_iter()
+        .flat_map(|list| list.entries)
+        .filter_map(|entry| entry.name)
+        .collect();
+    let public_names = get_public_names(src, path, options)?;
+    let conditional = public_names
+        .conditional
+        .into_iter()
+        .flat_map(|conditional| conditional.names);
+    let (listed, mut missing): (Vec<_>, Vec<_>) = public_names
+        .names
+        .into_iter()
+        .chain(conditional)
+        .partition(|name| listed.contains(name));
+    missing.sort_by(case_insensitive_cmp);
+    Ok(Some(Coverage {
+        listed: listed.len(),
+        missing,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_names() {
+        let src = "\
+def foo(): ...
+def Bar(): ...
+baz = 1
+_private = 1
+
+__all__ = [\"foo\"]
+__all__ += [\"baz\"]
+";
+        let coverage = coverage(src, None, &Options::default()).unwrap().unwrap();
+        assert_eq!(
+            coverage,
+            Coverage {
+                listed: 2,
+                missing: vec!["Bar".to_string()],
+            }
+        );
+        assert_eq!(coverage.to_string(), "66.7% (2/3 public names listed)");
+    }
+
+    #[test]
+    fn managed_or_dynamic() {
+        let options = Options::default();
+        assert_eq!(coverage("foo = 1\n", None, &options).unwrap(), None);
+        let managed =
+            "foo = 1\n\n\n# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n";
+        assert_eq!(coverage(managed, None, &options).unwrap(), None);
+        let dynamic = "foo = 1\n__all__ = [name for name in dir() if name.islower()]\n";
+        assert_eq!(coverage(dynamic, None, &options).unwrap(), None);
+    }
+
+    #[test]
+    fn adopted() {
+        // the list an allways block would replace is still the one covering the names
+        let options = Options::builder().adopt(true).build();
+        let src = "def foo(): ...\nbar = 1\n\n__all__ = [\"foo\"]\n";
+        assert_eq!(
+            coverage(src, None, &options).unwrap(),
+            Some(Coverage {
+                listed: 1,
+                missing: vec!["bar".to_string()],
+            })
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::check::check;
+use crate::diagnostic::Diagnostic;
+use crate::encoding::read_source;
+use crate::encoding::write_source;
+use crate::fix::fix;
+use crate::fix::FixOutcome;
+use crate::notebook::check_notebook;
+use crate::notebook::is_notebook;
+use crate::options::Options;
+
+/// A line of the daemon protocol, e.g. `{"id": 1, "command": "fix", "path": "pkg/__init__.py"}`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    /// Echoed back in the response.
+    #[serde(default)]
+    id: Value,
+    command: DaemonCommand,
+    path: Option<PathBuf>,
+    /// The source of `path`, read from disk when missing.
+    source: Option<String>,
+    /// Write the fixed source back to `path`.
+    #[serde(default)]
+    write: bool,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum DaemonCommand {
+    Fix,
+    Check,
+    /// Forget every cached result, e.g. after modules star-imported by others changed.
+    Clear,
+    Shutdown,
+}
+
+/// The results computed for a source, reused until it changes.
+struct Cached {
+    src: String,
+    fix: Option<FixOutcome>,
+    check: Option<Vec<Diagnostic>>,
+}
+
+impl Cached {
+    fn new(src: String) -> Self {
+        Self {
+            src,
+            fix: None,
+            check: None,
+        }
+    }
+}
+
+/// Serve fix and check requests, one JSON object per line of `input`, until `input` ends or a
+/// `shutdown` request.
+///
+/// Each response is a line of `output` holding the request `id` and either an `outcome`, the
+/// `diagnostics` or an `error`. Results are cached by path until the source changes.
+pub fn serve_daemon(input: impl BufRead, mut output: impl Write, options: &Options) -> Result<()> {
+    let mut daemon = Daemon {
+        options,
+        cache: HashMap::new(),
+    };
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                let error = format!("Invalid request: {err}");
+                respond(&mut output, json!({ "id": null, "error": error }))?;
+                continue;
+            }
+        };
+        let id = request.id.clone();
+        if let DaemonCommand::Shutdown = request.command {
+            return respond(&mut output, json!({ "id": id }));
+        }
+        let response = match daemon.handle(request) {
+            Ok((key, value)) => json!({ "id": id, key: value }),
+            Err(err) => json!({ "id": id, "error": format!("{err:#}") }),
+        };
+        respond(&mut output, response)?;
+    }
+    Ok(())
+}
+
+struct Daemon<'a> {
+    options: &'a Options,
+    /// Results by path, `None` for sources sent without one.
+    cache: HashMap<Option<PathBuf>, Cached>,
+}
+
+impl Daemon<'_> {
+    fn handle(&mut self, request: Request) -> Result<(&'static str, Value)> {
+        if let DaemonCommand::Clear = request.command {
+            self.cache.clear();
+            return Ok(("cleared", Value::Bool(true)));
+        }
+        let options = self.options;
+        let path = request.path.as_deref();
+        let (src, encoding) = match request.source {
+            Some(src) => (src, None),
+            None => {
+                let path = path.ok_or_else(|| anyhow!("Either a path or a source is needed"))?;
+                let (src, encoding) = read_source(path)?;
+                (src, Some(encoding))
+            }
+        };
+        let cached = self
+            .cache
+            .entry(request.path.clone())
+            .or_insert_with(|| Cached::new(src.clone()));
+        if cached.src != src {
+            *cached = Cached::new(src);
+        }
+        if let DaemonCommand::Check = request.command {
+            if cached.check.is_none() {
+                let diagnostics = match path {
+                    Some(path) if is_notebook(path) => {
+                        check_notebook(&cached.src, Some(path), options)?
+                    }
+                    _ => check(&cached.src, path, options)?,
+                };
+                cached.check = Some(diagnostics);
+            }
+            return Ok(("diagnostics", serde_json::to_value(&cached.check)?));
+        }
+        if cached.fix.is_none() {
+            cached.fix = Some(fix(&cached.src, path, options)?);
+        }
+        let outcome = serde_json::to_value(&cached.fix)?;
+        if request.write && outcome["changed"] == true {
+            let (Some(path), Some(encoding)) = (path, encoding) else {
+                return Err(anyhow!("Only sources read from disk can be written"));
+            };
+            write_source(path, outcome["src"].as_str().unwrap_or_default(), encoding)?;
+        }
+        Ok(("outcome", outcome))
+    }
+}
+
+fn respond(output: &mut impl Write, response: Value) -> Result<()> {
+    writeln!(output, "{response}")?;
+    Ok(output.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serve(requests: &[Value]) -> Vec<Value> {
+        let input = requests
+            .iter()
+            .map(|request| format!("{request}\n"))
+            .collect::<String>();
+        let mut output = Vec::new();
+        serve_daemon(input.as_bytes(), &mut output, &Options::default()).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fix_and_check_requests() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("mod.py");
+        std::fs::write(&path, "def foo(): ...\n").unwrap();
+        let responses = serve(&[
+            json!({ "id": 1, "command": "check", "path": path }),
+            json!({ "id": 2, "command": "fix", "path": path, "write": true }),
+            json!({ "id": 3, "command": "fix", "source": "x = 1\n" }),
+            json!({ "id": 4, "command": "fix" }),
+            json!({ "id": 5, "command": "format" }),
+            json!({ "id": 6, "command": "shutdown" }),
+            json!({ "id": 7, "command": "check", "source": "" }),
+        ]);
+        assert_eq!(responses.len(), 6);
+        assert_eq!(responses[0]["diagnostics"][0]["rule"], "ALW001");
+        assert_eq!(responses[1]["outcome"]["added"], json!(["foo"]));
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("__all__ = [\n    \"foo\",\n]\n# allways: end\n"));
+        assert_eq!(responses[2]["outcome"]["changed"], true);
+        assert_eq!(responses[3]["id"], 4);
+        assert!(responses[3]["error"].is_string());
+        assert_eq!(responses[4]["id"], Value::Null);
+        assert!(responses[4]["error"].is_string());
+        assert_eq!(responses[5], json!({ "id": 6 }));
+    }
+}
@@ -0,0 +1,53 @@
+use crate::rules::Rule;
+
+/// A problem found while processing a source file that doesn't prevent fixing it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    /// 1-based line the diagnostic points at.
+    pub line: usize,
+    pub message: String,
+    /// The lint rule reporting it, `None` for warnings found while computing `__all__`.
+    pub rule: Option<Rule>,
+}
+
+impl Diagnostic {
+    pub(crate) fn at_offset(src: &str, offset: usize, message: String) -> Self {
+        Self {
+            line: line_number(src, offset),
+            message,
+            rule: None,
+        }
+    }
+
+    pub(crate) fn lint(src: &str, offset: usize, rule: Rule, message: String) -> Self {
+        Self {
+            rule: Some(rule),
+            ..Self::at_offset(src, offset, message)
+        }
+    }
+}
+
+/// 1-based line number of the byte `offset` in `src`.
+pub(crate) fn line_number(src: &str, offset: usize) -> usize {
+    let offset = offset.min(src.len());
+    src.as_bytes()[..offset]
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_numbers() {
+        let src = "a = 1\nb = 2\n";
+        assert_eq!(line_number(src, 0), 1);
+        assert_eq!(line_number(src, 5), 1);
+        assert_eq!(line_number(src, 6), 2);
+        assert_eq!(line_number(src, 100), 3);
+    }
+}
@@ -0,0 +1,201 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::options::Options;
+
+const INIT: &str = "__init__.py";
+
+/// Directories skipped while looking for packages, along with every hidden directory, unless
+/// `Options::default_exclude` is turned off: virtual environments, caches and build output.
+pub const DEFAULT_EXCLUDE: [&str; 8] = [
+    ".venv",
+    "venv",
+    ".tox",
+    "node_modules",
+    "build",
+    "dist",
+    "__pycache__",
+    ".mypy_cache",
+];
+
+/// Whether `dir` holds installed packages: a virtual environment, found by its `pyvenv.cfg`, or
+/// a `site-packages` directory.
+pub(crate) fn is_environment(dir: &Path) -> bool {
+    let name = dir.file_name().unwrap_or_default();
+    name == "site-packages" || name == "dist-packages" || dir.join("pyvenv.cfg").is_file()
+}
+
+/// Whether `path` is inside a directory holding installed packages, see [`is_environment`].
+pub fn in_environment(path: &Path) -> bool {
+    let absolute = std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    absolute.ancestors().skip(1).any(is_environment)
+}
+
+/// Whether the directory `name` is skipped by default, see [`DEFAULT_EXCLUDE`].
+pub(crate) fn is_excluded_by_default(name: &str) -> bool {
+    name.starts_with('.') || DEFAULT_EXCLUDE.contains(&name)
+}
+
+/// Whether the directory at `path` is skipped by `options` while looking for python files.
+pub fn is_excluded_dir(path: &Path, options: &Options) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    (options.default_exclude && is_excluded_by_default(&name))
+        || (!options.include_site_packages && is_environment(path))
+        || options.is_excluded(path)
+}
+
+/// A directory of python modules found while walking a directory tree.
+#[derive(Debug, PartialEq)]
+pub struct Package {
+    pub dir: PathBuf,
+    /// PEP 420 namespace packages have no `__init__.py`.
+    pub namespace: bool,
+}
+
+impl Package {
+    pub fn init(&self) -> PathBuf {
+        self.dir.join(INIT)
+    }
+}
+
+/// The packages in `root` and every directory below it.
+///
+/// Directories without an `__init__.py` are walked too, they may be namespace packages with
+/// regular packages inside.
+pub fn packages(root: &Path) -> Result<Vec<Package>> {
+    packages_with(root, &Options::default())
+}
+
+/// [`packages`], skipping the directories `options` excludes.
+pub fn packages_with(root: &Path, options: &Options) -> Result<Vec<Package>> {
+    packages_where(root, &mut |dir| Ok(is_excluded_dir(dir, options)))
+}
+
+/// [`packages`], skipping the directories `excluded` returns `true` for, e.g. by
+/// [`is_excluded_dir`] with the options of the configs that apply to each.
+pub fn packages_where(
+    root: &Path,
+    excluded: &mut dyn FnMut(&Path) -> Result<bool>,
+) -> Result<Vec<Package>> {
+    let mut packages = Vec::new();
+    walk(root, &mut packages, excluded)?;
+    packages.sort_by(|left, right| left.dir.cmp(&right.dir));
+    Ok(packages)
+}
+
+fn walk(
+    dir: &Path,
+    packages: &mut Vec<Package>,
+    excluded: &mut dyn FnMut(&Path) -> Result<bool>,
+) -> Result<()> {
+    let mut has_modules = false;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if !excluded(&path)? {
+                walk(&path, packages, excluded)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == "py") {
+            has_modules = true;
+        }
+    }
+    if has_modules {
+        packages.push(Package {
+            dir: dir.to_path_buf(),
+            namespace: !dir.join(INIT).is_file(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regular_and_namespace_packages() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        for file in [
+            "ns/mod.py",
+            "ns/pkg/__init__.py",
+            "ns/pkg/__pycache__/mod.py",
+            "ns/.hidden/mod.py",
+            "docs/index.md",
+            "venv/lib/site-packages/dep/__init__.py",
+            "build/lib/ns/pkg/__init__.py",
+            "env/pyvenv.cfg",
+            "env/lib/python3.12/site-packages/dep/__init__.py",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        assert_eq!(
+            packages(root).unwrap(),
+            vec![
+                Package {
+                    dir: root.join("ns"),
+                    namespace: true,
+                },
+                Package {
+                    dir: root.join("ns/pkg"),
+                    namespace: false,
+                },
+            ]
+        );
+        let options = Options::builder()
+            .default_exclude(false)
+            .exclude(["build"])
+            .build();
+        let dirs = packages_with(root, &options)
+            .unwrap()
+            .into_iter()
+            .map(|package| package.dir)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dirs,
+            vec![
+                root.join("ns"),
+                root.join("ns/.hidden"),
+                root.join("ns/pkg"),
+                root.join("ns/pkg/__pycache__"),
+            ]
+        );
+        // `venv` has no `pyvenv.cfg`, its `site-packages` directory gives it away
+        let options = Options::builder()
+            .default_exclude(false)
+            .include_site_packages(true)
+            .build();
+        let dirs = packages_with(root, &options).unwrap();
+        assert!(dirs
+            .iter()
+            .any(|package| package.dir.ends_with("site-packages/dep")));
+        assert!(in_environment(&root.join("env/lib/dep/__init__.py")));
+        assert!(!in_environment(&root.join("ns/pkg/__init__.py")));
+
+        // options differing from one directory to the next, as with nested configs
+        let outside = Options::default();
+        let within_ns = Options::builder().default_exclude(false).build();
+        let dirs = packages_where(root, &mut |dir| {
+            let options = if dir.starts_with(root.join("ns")) {
+                &within_ns
+            } else {
+                &outside
+            };
+            Ok(is_excluded_dir(dir, options))
+        })
+        .unwrap();
+        assert!(dirs
+            .iter()
+            .any(|package| package.dir.ends_with("ns/.hidden")));
+        assert!(!dirs
+            .iter()
+            .any(|package| package.dir.ends_with("build/lib/ns/pkg")));
+    }
+}
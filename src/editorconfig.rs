@@ -0,0 +1,251 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::glob::matches;
+use crate::notebook::is_notebook;
+use crate::options::Options;
+
+const EDITORCONFIG: &str = ".editorconfig";
+
+/// `options` for the python module at `path`, its indentation and final newline taken from the
+/// `.editorconfig` files that apply to it.
+///
+/// Notebooks are left alone, their `.editorconfig` settings are about their JSON.
+pub(crate) fn options_for<'a>(
+    path: Option<&Path>,
+    options: &'a Options,
+) -> Result<Cow<'a, Options>> {
+    let Some(path) = path.filter(|path| options.editorconfig && !is_notebook(path)) else {
+        return Ok(Cow::Borrowed(options));
+    };
+    let properties = properties(path)?;
+    if properties.is_empty() {
+        return Ok(Cow::Borrowed(options));
+    }
+    let mut options = options.clone();
+    let size = |key: &str| {
+        properties
+            .get(key)
+            .and_then(|size| size.parse::<usize>().ok())
+    };
+    match properties.get("indent_style").map(String::as_str) {
+        Some("tab") => options.indent = String::from("\t"),
+        Some("space") => {
+            let size = match properties.get("indent_size").map(String::as_str) {
+                Some("tab") => size("tab_width"),
+                _ => size("indent_size"),
+            };
+            options.indent = " ".repeat(size.unwrap_or(4));
+        }
+        _ => {
+            if let Some(size) = size("indent_size") {
+                options.indent = " ".repeat(size);
+            }
+        }
+    }
+    match properties.get("insert_final_newline").map(String::as_str) {
+        Some("true") => options.final_newline = true,
+        Some("false") => options.final_newline = false,
+        _ => {}
+    }
+    Ok(Cow::Owned(options))
+}
+
+/// The properties the `.editorconfig` files of `path` and its ancestors up to the one marked
+/// `root = true` set for it, closer files taking precedence.
+fn properties(path: &Path) -> Result<HashMap<String, String>> {
+    let path = std::env::current_dir()?.join(path);
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let editorconfig = dir.join(EDITORCONFIG);
+        if !editorconfig.is_file() {
+            continue;
+        }
+        let src = std::fs::read_to_string(&editorconfig)
+            .with_context(|| format!("Failed to read {}", editorconfig.display()))?;
+        let file = EditorConfig::parse(&src);
+        let root = file.root;
+        files.push((dir, file));
+        if root {
+            break;
+        }
+    }
+    let mut properties = HashMap::new();
+    for (dir, file) in files.into_iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        for section in &file.sections {
+            if section_matches(&section.glob, &relative) {
+                properties.extend(section.properties.iter().cloned());
+            }
+        }
+    }
+    // `unset` removes a property set by a more distant file
+    properties.retain(|_, value| value != "unset");
+    Ok(properties)
+}
+
+/// A parsed `.editorconfig` file.
+#[derive(Debug, Default, PartialEq)]
+struct EditorConfig {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Section {
+    glob: String,
+    /// Names and values, both lowercase.
+    properties: Vec<(String, String)>,
+}
+
+impl EditorConfig {
+    fn parse(src: &str) -> Self {
+        let mut file = Self::default();
+        for line in src.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if let Some(glob) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                file.sections.push(Section {
+                    glob: glob.to_string(),
+                    properties: Vec::new(),
+                });
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+            match file.sections.last_mut() {
+                Some(section) => section.properties.push((key, value)),
+                None if key == "root" => file.root = value == "true",
+                None => {}
+            }
+        }
+        file
+    }
+}
+
+/// Whether the section `glob` matches the file at `relative`, a `/` separated path relative to
+/// the `.editorconfig` directory.
+///
+/// Globs without a `/` match the file name in any directory. `{a,b}` alternatives are
+/// supported, character classes and numeric ranges are not.
+fn section_matches(glob: &str, relative: &str) -> bool {
+    expand_braces(glob).iter().any(|glob| {
+        if glob.contains('/') {
+            matches(glob.trim_start_matches('/'), relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            matches(glob, name)
+        }
+    })
+}
+
+/// The globs `glob` stands for, e.g. `*.py` and `*.pyi` for `*.{py,pyi}`.
+fn expand_braces(glob: &str) -> Vec<String> {
+    let Some(open) = glob.find('{') else {
+        return vec![glob.to_string()];
+    };
+    let Some(close) = glob[open..].find('}').map(|close| open + close) else {
+        return vec![glob.to_string()];
+    };
+    let alternatives = &glob[open + 1..close];
+    if !alternatives.contains(',') {
+        return vec![glob.to_string()];
+    }
+    alternatives
+        .split(',')
+        .flat_map(|alternative| {
+            expand_braces(&format!(
+                "{}{alternative}{}",
+                &glob[..open],
+                &glob[close + 1..]
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let file = EditorConfig::parse(
+            "\
+root = true
+
+[*]
+indent_style = space
+; a comment
+indent_size = 2
+
+[*.{py,pyi}]
+Indent_Size = 4
+insert_final_newline = TRUE
+",
+        );
+        assert!(file.root);
+        assert_eq!(file.sections.len(), 2);
+        assert_eq!(
+            file.sections[1].properties,
+            vec![
+                (String::from("indent_size"), String::from("4")),
+                (String::from("insert_final_newline"), String::from("true")),
+            ]
+        );
+        assert!(section_matches("*.{py,pyi}", "pkg/foo.pyi"));
+        assert!(section_matches("*", "pkg/foo.py"));
+        assert!(section_matches("pkg/*.py", "pkg/foo.py"));
+        assert!(section_matches("/pkg/**.py", "pkg/sub/foo.py"));
+        assert!(!section_matches("pkg/*.py", "other/pkg/foo.py"));
+        assert!(!section_matches("foo.py", "xfoo.py"));
+    }
+
+    #[test]
+    fn closer_files_take_precedence() {
+        let root = tempfile::tempdir().unwrap();
+        let pkg = root.path().join("pkg");
+        std::fs::create_dir(&pkg).unwrap();
+        std::fs::write(
+            root.path().join(EDITORCONFIG),
+            "root = true\n[*]\nindent_style = tab\ninsert_final_newline = false\n",
+        )
+        .unwrap();
+        std::fs::write(
+            pkg.join(EDITORCONFIG),
+            "[*.py]\nindent_style = space\nindent_size = 2\n",
+        )
+        .unwrap();
+
+        let path = pkg.join("__init__.py");
+        let options = options_for(Some(&path), &Options::default()).unwrap();
+        assert_eq!(options.indent, "  ");
+        assert!(!options.final_newline);
+
+        let path = root.path().join("mod.py");
+        let options = options_for(Some(&path), &Options::default()).unwrap();
+        assert_eq!(options.indent, "\t");
+
+        let disabled = Options::builder().editorconfig(false).build();
+        let options = options_for(Some(&path), &disabled).unwrap();
+        assert_eq!(options.indent, "    ");
+    }
+}
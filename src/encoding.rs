@@ -0,0 +1,290 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Symbolic links followed to the file written, the limit of Linux, so that a loop ends.
+const MAX_LINKS: usize = 40;
+
+/// The text encoding of a python source file, UTF-8 unless declared otherwise by a PEP 263
+/// coding cookie, e.g. `# -*- coding: latin-1 -*-`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark.
+    Utf8Sig,
+    Latin1,
+    Ascii,
+}
+
+impl Encoding {
+    /// The encoding called `name`, normalized the way python's tokenizer does.
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase().replace('_', "-");
+        let is = |prefix: &str| name == prefix || name.starts_with(&format!("{prefix}-"));
+        if is("utf-8") || name == "utf8" {
+            Some(Self::Utf8)
+        } else if is("latin-1")
+            || is("iso-8859-1")
+            || is("iso-latin-1")
+            || matches!(name.as_str(), "latin1" | "l1" | "iso8859-1" | "cp819")
+        {
+            Some(Self::Latin1)
+        } else if matches!(name.as_str(), "ascii" | "us-ascii" | "646") {
+            Some(Self::Ascii)
+        } else {
+            None
+        }
+    }
+
+    /// Decode the python source `bytes`, UTF-8 reusing their buffer rather than copying it.
+    pub fn decode(mut bytes: Vec<u8>) -> Result<(String, Self)> {
+        let bom = bytes.starts_with(BOM);
+        if bom {
+            bytes.drain(..BOM.len());
+        }
+        let encoding = match declared_encoding(&bytes) {
+            Some(name) => {
+                let encoding = Self::from_name(&name)
+                    .ok_or_else(|| anyhow!("Unsupported encoding {name:?}"))?;
+                if bom && encoding != Self::Utf8 {
+                    Err(anyhow!(
+                        "Encoding {name:?} doesn't match the UTF-8 byte order mark"
+                    ))?;
+                }
+                encoding
+            }
+            None => Self::Utf8,
+        };
+        let src = match encoding {
+            Self::Utf8 | Self::Utf8Sig => String::from_utf8(bytes)?,
+            Self::Latin1 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+            Self::Ascii => {
+                if let Some(byte) = bytes.iter().find(|byte| !byte.is_ascii()) {
+                    Err(anyhow!(
+                        "Non-ASCII byte {byte:#04x} in a file declared ASCII"
+                    ))?;
+                }
+                String::from_utf8(bytes)?
+            }
+        };
+        let encoding = if bom { Self::Utf8Sig } else { encoding };
+        Ok((src, encoding))
+    }
+
+    /// Encode `src` to be written back to the file it was decoded from.
+    pub fn encode(self, src: &str) -> Result<Vec<u8>> {
+        let max = match self {
+            Self::Utf8 => return Ok(src.as_bytes().to_vec()),
+            Self::Utf8Sig => return Ok([BOM, src.as_bytes()].concat()),
+            Self::Latin1 => '\u{ff}',
+            Self::Ascii => '\u{7f}',
+        };
+        src.chars()
+            .map(|character| match u8::try_from(character) {
+                Ok(byte) if character <= max => Ok(byte),
+                _ => Err(anyhow!("{character:?} can't be encoded as {self:?}")),
+            })
+            .collect()
+    }
+}
+
+/// Read the python source file at `path`, decoded with its declared encoding.
+///
+/// The file is read into a single buffer that a UTF-8 file is validated in and kept as, so even a
+/// large generated module is only held once.
+pub fn read_source(path: &Path) -> Result<(String, Encoding)> {
+    Encoding::decode(std::fs::read(path)?)
+        .with_context(|| format!("Failed to decode {}", path.display()))
+}
+
+/// Write `src` to `path` with `encoding`.
+///
+/// The file is written next to `path` first and renamed over it once complete, an
+/// interrupted run never leaves it half-written. A symbolic link is followed, the file it points
+/// to being replaced rather than the link.
+pub fn write_source(path: &Path, src: &str, encoding: Encoding) -> Result<()> {
+    // UTF-8 sources are written as they are rather than through an encoded copy
+    let encoded;
+    let (bom, bytes): (&[u8], &[u8]) = match encoding {
+        Encoding::Utf8 => (b"", src.as_bytes()),
+        Encoding::Utf8Sig => (BOM, src.as_bytes()),
+        Encoding::Latin1 | Encoding::Ascii => {
+            encoded = encoding
+                .encode(src)
+                .with_context(|| format!("Failed to encode {}", path.display()))?;
+            (b"", &encoded)
+        }
+    };
+    let target = link_target(path);
+    let name = target
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+    let partial = target.with_file_name(format!(".{}.allways", name.to_string_lossy()));
+    let written = (|| -> Result<()> {
+        let mut file = File::create(&partial)?;
+        file.write_all(bom)?;
+        file.write_all(bytes)?;
+        // the file replaced keeps its permissions
+        if let Ok(metadata) = std::fs::metadata(&target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        // on disk before the rename, a crash can't leave an empty file in place of the source
+        file.sync_all()?;
+        std::fs::rename(&partial, &target)?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    written.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The file the symbolic link `path` points to, through every link in between, or else `path`.
+fn link_target(path: &Path) -> PathBuf {
+    let mut target = path.to_path_buf();
+    // a dangling link can't be canonicalized, its target is created
+    for _ in 0..MAX_LINKS {
+        let Ok(link) = std::fs::read_link(&target) else {
+            break;
+        };
+        target = match target.parent() {
+            Some(dir) => dir.join(link),
+            None => link,
+        };
+    }
+    target
+}
+
+/// The encoding named by a coding cookie on the first two lines, the second only counting
+/// when the first is a comment or blank.
+fn declared_encoding(bytes: &[u8]) -> Option<String> {
+    let mut lines = bytes.split(|&byte| byte == b'\n');
+    let first = lines.next()?;
+    coding_cookie(first).or_else(|| {
+        let first = String::from_utf8_lossy(first);
+        let first = first.trim();
+        if first.is_empty() || first.starts_with('#') {
+            coding_cookie(lines.next()?)
+        } else {
+            None
+        }
+    })
+}
+
+/// The encoding name of a line matching `^[ \t\f]*#.*?coding[:=][ \t]*([-\w.]+)`.
+fn coding_cookie(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    let comment = line
+        .trim_start_matches([' ', '\t', '\x0c'])
+        .strip_prefix('#')?;
+    comment.match_indices("coding").find_map(|(index, _)| {
+        let value = comment[index + "coding".len()..].strip_prefix([':', '='])?;
+        let name = value
+            .trim_start_matches([' ', '\t'])
+            .chars()
+            .take_while(|&character| {
+                character.is_alphanumeric() || matches!(character, '-' | '_' | '.')
+            })
+            .collect::<String>();
+        (!name.is_empty()).then_some(name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coding_cookies() {
+        assert_eq!(
+            coding_cookie(b"# -*- coding: latin-1 -*-"),
+            Some(String::from("latin-1"))
+        );
+        assert_eq!(
+            coding_cookie(b"# vim: set fileencoding=iso-8859-1 :"),
+            Some(String::from("iso-8859-1"))
+        );
+        assert_eq!(coding_cookie(b"x = 1  # coding: latin-1"), None);
+        assert_eq!(coding_cookie(b"# no encoding here"), None);
+        assert_eq!(
+            declared_encoding(b"#!/usr/bin/env python\n# coding=latin-1\n"),
+            Some(String::from("latin-1"))
+        );
+        assert_eq!(declared_encoding(b"import os\n# coding=latin-1\n"), None);
+    }
+
+    #[test]
+    fn latin1_round_trip() {
+        let bytes = b"# -*- coding: latin-1 -*-\nname = '\xe9t\xe9'\n";
+        let (src, encoding) = Encoding::decode(bytes.to_vec()).unwrap();
+        assert_eq!(encoding, Encoding::Latin1);
+        assert_eq!(src, "# -*- coding: latin-1 -*-\nname = 'été'\n");
+        assert_eq!(encoding.encode(&src).unwrap(), bytes.to_vec());
+        assert!(encoding.encode("snowman = '☃'").is_err());
+    }
+
+    #[test]
+    fn utf8() {
+        let (src, encoding) = Encoding::decode("name = 'été'\n".into()).unwrap();
+        assert_eq!((src.as_str(), encoding), ("name = 'été'\n", Encoding::Utf8));
+
+        let bytes = b"\xef\xbb\xbfx = 1\n";
+        let (src, encoding) = Encoding::decode(bytes.to_vec()).unwrap();
+        assert_eq!((src.as_str(), encoding), ("x = 1\n", Encoding::Utf8Sig));
+        assert_eq!(encoding.encode(&src).unwrap(), bytes.to_vec());
+
+        assert!(Encoding::decode(b"x = '\xe9'\n".to_vec()).is_err());
+    }
+
+    #[test]
+    fn large_files() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("generated.py");
+        let src = "name = 'été'\n".repeat(1 << 17);
+        write_source(&path, &src, Encoding::Utf8Sig).unwrap();
+        assert_eq!(read_source(&path).unwrap(), (src, Encoding::Utf8Sig));
+
+        std::fs::write(&path, b"x = '\xe9'\n".repeat(1 << 17)).unwrap();
+        assert!(read_source(&path).is_err());
+    }
+
+    #[test]
+    fn replace_whole_file() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("module.py");
+        std::fs::write(&path, "x = 1\n".repeat(100)).unwrap();
+        write_source(&path, "y = 1\n", Encoding::Utf8).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "y = 1\n");
+        let files = std::fs::read_dir(root.path()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_through_symlink() {
+        let root = tempfile::tempdir().unwrap();
+        let module = root.path().join("src").join("module.py");
+        std::fs::create_dir(module.parent().unwrap()).unwrap();
+        std::fs::write(&module, "x = 1\n").unwrap();
+        let link = root.path().join("module.py");
+        std::os::unix::fs::symlink(Path::new("src").join("module.py"), &link).unwrap();
+        write_source(&link, "y = 1\n", Encoding::Utf8).unwrap();
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&module).unwrap(), "y = 1\n");
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn unsupported_encoding() {
+        assert!(Encoding::decode(b"# coding: shift_jis\n".to_vec()).is_err());
+        assert!(Encoding::decode(b"# coding: ascii\nx = '\xe9'\n".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::add_all::get_public_names;
+use crate::diagnostic::line_number;
+use crate::filter::ExportCandidate;
+use crate::name_parser::Definition;
+use crate::name_parser::Kind;
+use crate::name_parser::NameParser;
+use crate::name_parser::Skip;
+use crate::name_parser::Skipped;
+use crate::options::Options;
+
+/// Why a name is or isn't among the names a module exports, see [`explain`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub name: String,
+    pub exported: bool,
+    pub reason: Reason,
+    /// The kind of statement binding the name, `None` when the module doesn't bind it.
+    pub kind: Option<Kind>,
+    /// 1-based line of the statement binding the name, or leaving it out when it isn't bound.
+    pub line: Option<usize>,
+    /// The source of that line, trimmed.
+    pub source: Option<String>,
+}
+
+/// What decides whether a name is exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// Bound by the module, and public.
+    Public,
+    /// Private, but marked with one of `Options::export_decorators`.
+    Decorated,
+    /// Star-imported, or added to `__all__` before the allways block.
+    Added,
+    /// Starts with an underscore.
+    Private,
+    /// Not marked with an export decorator while `Options::decorated_only` is set.
+    NotDecorated,
+    /// Rejected by `Options::name_filter`.
+    Filtered,
+    /// Removed from `__all__` before the allways block.
+    Removed,
+    /// Only bound in an `if TYPE_CHECKING:` block.
+    TypeChecking,
+    /// Only bound in a branch that never runs on import.
+    Unreachable,
+    /// Deleted by `del`.
+    Deleted,
+    /// Bound by `type X = ...` while type aliases aren't exported.
+    TypeAlias,
+    /// Never bound by the module.
+    Unbound,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Public => "it is public",
+            Reason::Decorated => "it is marked with an export decorator",
+            Reason::Added => "it is star-imported or added to `__all__` before the allways block",
+            Reason::Private => "it starts with an underscore",
+            Reason::NotDecorated => "it isn't marked with an export decorator",
+            Reason::Filtered => "the name filter rejects it",
+            Reason::Removed => "it is removed from `__all__` before the allways block",
+            Reason::TypeChecking => {
+                "it is only bound in an `if TYPE_CHECKING:` block, see --type-checking"
+            }
+            Reason::Unreachable => "it is only bound in a branch that never runs on import",
+            Reason::Deleted => "it is deleted",
+            Reason::TypeAlias => "it is a type alias and type aliases aren't exported",
+            Reason::Unbound => "the module never binds it",
+        })
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let is = if self.exported { "is" } else { "isn't" };
+        writeln!(f, "`{}` {is} exported: {}", self.name, self.reason)?;
+        if let Some(line) = self.line {
+            let kind = match self.kind {
+                Some(Kind::Function) => "function",
+                Some(Kind::Class) => "class",
+                Some(Kind::Variable) => "variable",
+                Some(Kind::TypeAlias) => "type alias",
+                Some(Kind::Import) => "import",
+                Some(Kind::Lazy) => "module `__getattr__`",
+                None => "statement",
+            };
+            let source = self.source.as_deref().unwrap_or_default();
+            writeln!(f, "  {kind} on line {line}: {source}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Explain why `name` is or isn't among the names `src`, the module at `path`, exports: the
+/// statement that binds it, or the one that left it out, and what decided.
+pub fn explain(
+    src: &str,
+    path: Option<&Path>,
+    name: &str,
+    options: &Options,
+) -> Result<Explanation> {
+    let mut explanations = explain_names(src, path, [name], options)?;
+    Ok(explanations.remove(0))
+}
+
+/// Explain each of `names` as [`explain`] does, analysing `src` once.
+pub fn explain_names<'a>(
+    src: &str,
+    path: Option<&Path>,
+    names: impl IntoIterator<Item = &'a str>,
+    options: &Options,
+) -> Result<Vec<Explanation>> {
+    let analysis = Analysis::new(src, path, options)?;
+    Ok(names
+        .into_iter()
+        .map(|name| analysis.explain(src, name, options))
+        .collect())
+}
+
+/// What the analysis of a module found about its names, which explanations are drawn from.
+struct Analysis {
+    public: Vec<String>,
+    parser: NameParser,
+    decorated: HashSet<String>,
+    definitions: HashMap<String, Definition>,
+    skipped: HashMap<String, Skipped>,
+}
+
+impl Analysis {
+    fn new(src: &str, path: Option<&Path>, options: &Options) -> Result<Self> {
+        let public = get_public_names(src, path, options)?.names;
+        let mut parser = NameParser::parse(src, options)?;
+        Ok(Analysis {
+            public,
+            decorated: parser.take_decorated(),
+            definitions: parser.take_definitions(),
+            skipped: parser.take_skipped(),
+            parser,
+        })
+    }
+
+    fn explain(&self, src: &str, name: &str, options: &Options) -> Explanation {
+        let exported = self.public.iter().any(|public| public == name);
+        let bound = self.parser.contains(name);
+        let decorated = self.decorated.contains(name);
+        let definition = self.definitions.get(name).copied().filter(|_| bound);
+        let skipped = self.skipped.get(name).copied();
+
+        let filtered = || {
+            options.name_filter.as_ref().is_some_and(|filter| {
+                !filter.export(&ExportCandidate {
+                    name,
+                    kind: definition.map(|definition| definition.kind),
+                    span: definition.map(|definition| definition.offset..definition.end),
+                })
+            })
+        };
+        let reason = match (bound, exported) {
+            (false, true) => Reason::Added,
+            (false, false) => match skipped.map(|skipped| skipped.reason) {
+                Some(Skip::TypeChecking) => Reason::TypeChecking,
+                Some(Skip::Unreachable) => Reason::Unreachable,
+                Some(Skip::Deleted) => Reason::Deleted,
+                Some(Skip::TypeAlias) => Reason::TypeAlias,
+                None => Reason::Unbound,
+            },
+            (true, true) if !name.starts_with('_') => Reason::Public,
+            (true, true) if decorated => Reason::Decorated,
+            (true, true) => Reason::Added,
+            (true, false) if options.decorated_only && !decorated => Reason::NotDecorated,
+            (true, false) if name.starts_with('_') && !decorated => Reason::Private,
+            (true, false) if filtered() => Reason::Filtered,
+            (true, false) => Reason::Removed,
+        };
+
+        let offset = match (definition, skipped) {
+            (Some(definition), _) => Some(definition.offset),
+            (None, Some(skipped)) if !exported => Some(skipped.offset),
+            _ => None,
+        };
+        let line = offset.map(|offset| line_number(src, offset));
+        let source = line.and_then(|line| src.lines().nth(line - 1).map(|s| s.trim().to_string()));
+        Explanation {
+            name: name.to_string(),
+            exported,
+            reason,
+            kind: definition.map(|definition| definition.kind),
+            line,
+            source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "\
+from typing import TYPE_CHECKING
+if TYPE_CHECKING:
+    from os import PathLike
+
+def foo(): ...
+_bar = 1
+baz = 1
+del baz
+__all__ += ['added']
+";
+
+    fn reason(name: &str, options: &Options) -> (bool, Reason) {
+        let explanation = explain(SRC, None, name, options).unwrap();
+        (explanation.exported, explanation.reason)
+    }
+
+    #[test]
+    fn reasons() {
+        let options = Options::default();
+        assert_eq!(reason("foo", &options), (true, Reason::Public));
+        assert_eq!(reason("added", &options), (true, Reason::Added));
+        assert_eq!(reason("_bar", &options), (false, Reason::Private));
+        assert_eq!(reason("PathLike", &options), (false, Reason::TypeChecking));
+        assert_eq!(reason("baz", &options), (false, Reason::Deleted));
+        assert_eq!(reason("missing", &options), (false, Reason::Unbound));
+        let options = Options::builder()
+            .name_filter(|candidate: &ExportCandidate| candidate.name != "foo")
+            .build();
+        assert_eq!(reason("foo", &options), (false, Reason::Filtered));
+    }
+
+    #[test]
+    fn provenance() {
+        let explanation = explain(SRC, None, "foo", &Options::default()).unwrap();
+        assert_eq!(explanation.kind, Some(Kind::Function));
+        assert_eq!(explanation.line, Some(5));
+        assert_eq!(
+            explanation.to_string(),
+            "`foo` is exported: it is public\n  function on line 5: def foo(): ...\n"
+        );
+
+        let explanation = explain(SRC, None, "baz", &Options::default()).unwrap();
+        assert_eq!(explanation.kind, None);
+        assert_eq!(explanation.line, Some(8));
+        assert_eq!(explanation.source.as_deref(), Some("del baz"));
+    }
+
+    #[test]
+    fn several_names() {
+        let options = Options::default();
+        let explanations = explain_names(SRC, None, ["foo", "_bar", "missing"], &options).unwrap();
+        let reasons = explanations
+            .iter()
+            .map(|explanation| explanation.reason)
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, [Reason::Public, Reason::Private, Reason::Unbound]);
+        assert_eq!(
+            explanations[0],
+            explain(SRC, None, "foo", &options).unwrap()
+        );
+    }
+}
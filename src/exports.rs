@@ -0,0 +1,174 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::add_all::get_file_state;
+use crate::add_all::get_public_names;
+use crate::add_all::line_ending;
+use crate::add_all::with_block;
+use crate::add_all::Block;
+use crate::add_all::FileState;
+use crate::editorconfig;
+use crate::options::ModuleName;
+use crate::options::Options;
+
+const INIT: &str = "__init__.py";
+
+/// First line of generated exports modules, which are only ever deleted when they start with it.
+const HEADER: &str = "# Generated by allways from `__init__.py`, do not edit.";
+
+/// A change to a module `__all__` is generated into, see `Options::exports_module`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportsModule {
+    pub path: PathBuf,
+    /// Its new source, `None` when it is to be deleted.
+    pub src: Option<String>,
+}
+
+/// The module the `__init__.py` at `path` imports `__all__` from, `None` for other modules or
+/// without `Options::exports_module`.
+pub(crate) fn module_name<'a>(path: Option<&Path>, options: &'a Options) -> Option<&'a str> {
+    options
+        .exports_module
+        .as_ref()
+        .map(ModuleName::as_str)
+        .filter(|_| path.is_some_and(is_init))
+}
+
+/// The changes to the exports modules of `src`, the `__init__.py` at `path`, going with the
+/// fix of `src`.
+///
+/// With `Options::exports_module` the module is written with the names the package exports,
+/// or deleted when there are none. A generated module the allways block of `src` imports from
+/// but no longer should is deleted, e.g. once the option is unset.
+pub fn exports_modules(src: &str, path: &Path, options: &Options) -> Result<Vec<ExportsModule>> {
+    if !is_init(path) {
+        return Ok(Vec::new());
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let module = options.exports_module.as_ref().map(ModuleName::as_str);
+    let mut changes = Vec::new();
+    if let Some(imported) = imported_module(src).filter(|imported| Some(*imported) != module) {
+        let path = dir.join(format!("{imported}.py"));
+        if is_generated(&path) {
+            changes.push(ExportsModule { path, src: None });
+        }
+    }
+    let Some(module) = module else {
+        return Ok(changes);
+    };
+    let exports = dir.join(format!("{module}.py"));
+    let public_names = get_public_names(src, Some(path), options)?;
+    if public_names.conflict {
+        return Ok(changes);
+    }
+    let extensions = if options.star_import_fallback {
+        public_names.unresolved
+    } else {
+        Vec::new()
+    };
+    let new_src = if public_names.names.is_empty()
+        && extensions.is_empty()
+        && public_names.conditional.is_empty()
+    {
+        None
+    } else {
+        let options = editorconfig::options_for(Some(&exports), options)?;
+        let header = format!("{HEADER}{}", line_ending(src));
+        let block = Block::Names {
+            names: &public_names.names,
+            dynamic: &[],
+            extensions: &extensions,
+            conditional: &public_names.conditional,
+        };
+        Some(with_block(&header, block, &options))
+    };
+    let old_src = std::fs::read_to_string(&exports).ok();
+    let unchanged = match &new_src {
+        Some(_) => new_src == old_src,
+        None => !is_generated(&exports),
+    };
+    if !unchanged {
+        changes.push(ExportsModule {
+            path: exports,
+            src: new_src,
+        });
+    }
+    Ok(changes)
+}
+
+fn is_init(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == INIT)
+}
+
+/// Whether the file at `path` was generated by allways.
+fn is_generated(path: &Path) -> bool {
+    std::fs::read_to_string(path).is_ok_and(|src| src.starts_with(HEADER))
+}
+
+/// The sibling module the allways block of `src` imports `__all__` from.
+fn imported_module(src: &str) -> Option<&str> {
+    let FileState::YesAll(start, end) = get_file_state(src) else {
+        return None;
+    };
+    src[start..end].lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("from .")?
+            .strip_suffix(" import __all__")
+            .filter(|module| !module.is_empty() && !module.contains(['.', ' ']))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::add_all::do_it_allways;
+
+    #[test]
+    fn generated_module() {
+        let dir = tempfile::tempdir().unwrap();
+        let init = dir.path().join(INIT);
+        let options = Options::builder()
+            .exports_module("_exports".parse().unwrap())
+            .build();
+        let src = "from ._foo import foo\n";
+
+        let fixed = do_it_allways(src, Some(&init), &options)
+            .unwrap()
+            .src
+            .unwrap();
+        assert_eq!(
+            fixed,
+            "from ._foo import foo\n\n\n# allways: start\nfrom ._exports import __all__\n# allways: end\n"
+        );
+        let changes = exports_modules(&fixed, &init, &options).unwrap();
+        let exports = dir.path().join("_exports.py");
+        assert_eq!(
+            changes,
+            vec![ExportsModule {
+                path: exports.clone(),
+                src: Some(format!(
+                    "{HEADER}\n\n\n# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n"
+                )),
+            }]
+        );
+        std::fs::write(&exports, changes[0].src.as_ref().unwrap()).unwrap();
+        assert_eq!(exports_modules(&fixed, &init, &options).unwrap(), vec![]);
+
+        // switching back to an inline `__all__` deletes the generated module
+        let inline = do_it_allways(&fixed, Some(&init), &Options::default())
+            .unwrap()
+            .src
+            .unwrap();
+        assert!(inline.contains("__all__ = ["));
+        assert_eq!(
+            exports_modules(&fixed, &init, &Options::default()).unwrap(),
+            vec![ExportsModule {
+                path: exports,
+                src: None,
+            }]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::ffi::c_char;
+use std::ffi::c_int;
+use std::ffi::CString;
+
+use std::panic::UnwindSafe;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use crate::fix::fix_source;
+use crate::options::Options;
+
+/// The source was fixed, or didn't need to be.
+pub const ALLWAYS_OK: c_int = 0;
+/// A pointer argument is null or the source isn't UTF-8.
+pub const ALLWAYS_INVALID_ARGUMENT: c_int = 1;
+/// The source couldn't be fixed, e.g. it isn't valid python, or allways panicked.
+pub const ALLWAYS_ERROR: c_int = 2;
+
+/// Fix the `len` bytes of UTF-8 python source at `src` with the default options.
+///
+/// On success `*result` points to the fixed source, otherwise to an error message, both
+/// NUL-terminated and to be released with [`allways_free`].
+///
+/// # Safety
+///
+/// `src` must point to `len` readable bytes and `result` to writable memory for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn allways_fix(
+    src: *const u8,
+    len: usize,
+    result: *mut *mut c_char,
+) -> c_int {
+    if src.is_null() || result.is_null() {
+        return ALLWAYS_INVALID_ARGUMENT;
+    }
+    let bytes = std::slice::from_raw_parts(src, len);
+    let (code, message) = match std::str::from_utf8(bytes) {
+        Ok(src) => match catch_panic(|| fix(src)) {
+            Ok(fixed) => (ALLWAYS_OK, fixed),
+            Err(err) => (ALLWAYS_ERROR, format!("{err:#}")),
+        },
+        Err(err) => (ALLWAYS_INVALID_ARGUMENT, err.to_string()),
+    };
+    *result = into_c_string(message);
+    code
+}
+
+/// Release a string returned by [`allways_fix`], doing nothing for null.
+///
+/// # Safety
+///
+/// `string` must come from [`allways_fix`] and not have been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn allways_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+fn fix(src: &str) -> Result<String> {
+    Ok(fix_source(src, &Options::default())?.src)
+}
+
+/// What `f` returns, or an error if it panics, a panic unwinding into C code being undefined
+/// behavior.
+fn catch_panic(f: impl FnOnce() -> Result<String> + UnwindSafe) -> Result<String> {
+    std::panic::catch_unwind(f).unwrap_or_else(|panic| {
+        let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+            (Some(message), _) => *message,
+            (None, Some(message)) => message.as_str(),
+            (None, None) => "no message",
+        };
+        Err(anyhow!("allways panicked: {message}"))
+    })
+}
+
+/// `string` as a C string, truncated at a NUL byte python source can't contain anyway.
+fn into_c_string(mut string: String) -> *mut c_char {
+    if let Some(nul) = string.find('\0') {
+        string.truncate(nul);
+    }
+    CString::new(string).unwrap_or_default().into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::CStr;
+
+    fn call(src: &[u8]) -> (c_int, String) {
+        let mut result = std::ptr::null_mut();
+        unsafe {
+            let code = allways_fix(src.as_ptr(), src.len(), &mut result);
+            let string = CStr::from_ptr(result).to_string_lossy().into_owned();
+            allways_free(result);
+            (code, string)
+        }
+    }
+
+    #[test]
+    fn fix_buffer() {
+        let (code, fixed) = call(b"def foo(): ...\n");
+        assert_eq!(code, ALLWAYS_OK);
+        assert!(fixed.ends_with("__all__ = [\n    \"foo\",\n]\n# allways: end\n"));
+
+        assert_eq!(call(b"def (:\n").0, ALLWAYS_ERROR);
+        assert_eq!(call(b"x = '\xe9'\n").0, ALLWAYS_INVALID_ARGUMENT);
+
+        let mut result = std::ptr::null_mut();
+        let code = unsafe { allways_fix(std::ptr::null(), 0, &mut result) };
+        assert_eq!(code, ALLWAYS_INVALID_ARGUMENT);
+        assert!(result.is_null());
+    }
+
+    #[test]
+    fn panics_caught() {
+        let err = catch_panic(|| panic!("unexpected")).unwrap_err();
+        assert_eq!(err.to_string(), "allways panicked: unexpected");
+    }
+}
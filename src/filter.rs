@@ -0,0 +1,45 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::name_parser::Kind;
+
+/// A public name found in a module, before it makes it into `__all__`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportCandidate<'a> {
+    pub name: &'a str,
+    /// `None` for names the module doesn't bind itself, e.g. star-imported ones.
+    pub kind: Option<Kind>,
+    /// Byte range of the statement binding the name.
+    pub span: Option<Range<usize>>,
+}
+
+/// Decides which public names are exported, for policies the options can't express.
+///
+/// Implemented by closures taking an [`ExportCandidate`]:
+///
+/// ```
+/// use allways::ExportCandidate;
+/// use allways::Options;
+///
+/// let options = Options::builder()
+///     .name_filter(|candidate: &ExportCandidate| !candidate.name.ends_with("_deprecated"))
+///     .build();
+/// ```
+pub trait NameFilter: Send + Sync {
+    fn export(&self, candidate: &ExportCandidate) -> bool;
+}
+
+impl<F> NameFilter for F
+where
+    F: Fn(&ExportCandidate) -> bool + Send + Sync,
+{
+    fn export(&self, candidate: &ExportCandidate) -> bool {
+        self(candidate)
+    }
+}
+
+impl fmt::Debug for dyn NameFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NameFilter")
+    }
+}
@@ -0,0 +1,278 @@
+use std::io::Read;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::add_all::do_it_allways;
+use crate::add_all::get_file_state;
+use crate::add_all::FileState;
+use crate::add_all::Outcome;
+use crate::diagnostic::Diagnostic;
+use crate::encoding::read_source;
+use crate::encoding::Encoding;
+use crate::name_parser::NameParser;
+use crate::notebook::do_it_allways_notebook;
+use crate::notebook::is_notebook;
+use crate::notebook::notebook_python;
+use crate::options::Options;
+
+/// The result of fixing a python module.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixOutcome {
+    /// The fixed source, the original one when there is nothing to change.
+    pub src: String,
+    /// Whether `src` differs from the original source.
+    pub changed: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    pub block: BlockChange,
+    /// Names the new allways block exports that the old one didn't, in `__all__` order.
+    pub added: Vec<String>,
+    /// Names the old allways block exported that the new one doesn't, in `__all__` order.
+    pub removed: Vec<String>,
+    /// The smallest edit turning the original source into `src`, `None` when unchanged.
+    pub edit: Option<Edit>,
+}
+
+/// What happened to the allways block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum BlockChange {
+    Unchanged,
+    Created,
+    Updated,
+    /// The module no longer exports any name.
+    Removed,
+}
+
+/// Replace the bytes `range` of the original source by `content`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub content: String,
+}
+
+/// Insert or update the allways block of the python module `src`.
+///
+/// Star imports can't be expanded without knowing where the module is, see [`fix_file`].
+pub fn fix_source(src: &str, options: &Options) -> Result<FixOutcome> {
+    fix(src, None, options)
+}
+
+/// Insert or update the allways block of the python module or notebook at `path`, without
+/// writing it back.
+pub fn fix_file(path: &Path, options: &Options) -> Result<FixOutcome> {
+    let (src, _) = read_source(path)?;
+    fix(&src, Some(path), options)
+}
+
+/// Fix the python module read from `reader`, writing the whole result to `writer` in the
+/// encoding the source declares.
+///
+/// The outcome describes the decoded source, its edit range counts UTF-8 bytes.
+pub fn fix_from_reader(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    options: &Options,
+) -> Result<FixOutcome> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let (src, encoding) = Encoding::decode(bytes)?;
+    let outcome = fix_source(&src, options)?;
+    // decoding is lossless, an unchanged source encodes back to the bytes read
+    let fixed = if outcome.changed { &outcome.src } else { &src };
+    writer.write_all(&encoding.encode(fixed)?)?;
+    writer.flush()?;
+    Ok(outcome)
+}
+
+pub(crate) fn fix(src: &str, path: Option<&Path>, options: &Options) -> Result<FixOutcome> {
+    let outcome = match path {
+        Some(path) if is_notebook(path) => do_it_allways_notebook(src, Some(path), options)?,
+        _ => do_it_allways(src, path, options)?,
+    };
+    FixOutcome::new(src, path, outcome)
+}
+
+impl FixOutcome {
+    /// The description of `outcome`, the result of fixing the module `src` read from `path`,
+    /// e.g. with [`do_it_allways`].
+    pub fn new(src: &str, path: Option<&Path>, outcome: Outcome) -> Result<Self> {
+        let fixed = outcome.src.unwrap_or_else(|| src.to_string());
+        let notebook = path.is_some_and(is_notebook);
+        let old_names = block_names(src, notebook)?;
+        let new_names = block_names(&fixed, notebook)?;
+        let block = match (&old_names, &new_names) {
+            (None, Some(_)) => BlockChange::Created,
+            (Some(_), None) => BlockChange::Removed,
+            (Some(old), Some(new)) if old != new => BlockChange::Updated,
+            _ => BlockChange::Unchanged,
+        };
+        let (old_names, new_names) = (old_names.unwrap_or_default(), new_names.unwrap_or_default());
+        Ok(FixOutcome {
+            changed: fixed != src,
+            block,
+            added: difference(&new_names, &old_names),
+            removed: difference(&old_names, &new_names),
+            edit: edit(src, &fixed),
+            src: fixed,
+            diagnostics: outcome.diagnostics,
+        })
+    }
+}
+
+/// The names the `__all__` of the allways block of `src` lists, `None` without a block.
+fn block_names(src: &str, notebook: bool) -> Result<Option<Vec<String>>> {
+    let python = if notebook {
+        notebook_python(src)?
+    } else {
+        src.to_string()
+    };
+    let FileState::YesAll(start, end) = get_file_state(&python) else {
+        return Ok(None);
+    };
+    let parser = NameParser::parse(&python[start..end], &Options::default())?;
+    Ok(Some(
+        parser
+            .dunder_all()
+            .map(<[String]>::to_vec)
+            .unwrap_or_default(),
+    ))
+}
+
+fn difference(names: &[String], other: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| !other.contains(name))
+        .cloned()
+        .collect()
+}
+
+/// The edit replacing what lies between the common prefix and suffix of `old` and `new`.
+fn edit(old: &str, new: &str) -> Option<Edit> {
+    if old == new {
+        return None;
+    }
+    let mut prefix = old
+        .bytes()
+        .zip(new.bytes())
+        .take_while(|(left, right)| left == right)
+        .count();
+    while !old.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old
+        .bytes()
+        .rev()
+        .zip(new.bytes().rev())
+        .take(max_suffix)
+        .take_while(|(left, right)| left == right)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) {
+        suffix -= 1;
+    }
+    Some(Edit {
+        range: prefix..old.len() - suffix,
+        content: new[prefix..new.len() - suffix].to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fix_source_outcome() {
+        let options = Options::builder().type_aliases(false).build();
+        let outcome = fix_source("type Alias = int\ndef foo(): ...\n", &options).unwrap();
+        assert!(outcome.changed);
+        assert!(outcome.src.contains("__all__ = [\n    \"foo\",\n]"));
+
+        assert_eq!(outcome.block, BlockChange::Created);
+        assert_eq!(outcome.added, vec!["foo"]);
+
+        let outcome = fix_source(&outcome.src, &options).unwrap();
+        assert!(!outcome.changed);
+        assert_eq!(outcome.block, BlockChange::Unchanged);
+        assert_eq!(outcome.edit, None);
+
+        let outcome = fix_source("_private = 1\n", &options).unwrap();
+        assert!(!outcome.changed);
+        assert_eq!(outcome.src, "_private = 1\n");
+    }
+
+    #[test]
+    fn updated_block() {
+        let src = "def foo(): ...\ndef baz(): ...\n\n\n# allways: start\n__all__ = [\n    \"bar\",\n    \"foo\",\n]\n# allways: end\n";
+        let outcome = fix_source(src, &Options::default()).unwrap();
+        assert_eq!(outcome.block, BlockChange::Updated);
+        assert_eq!(outcome.added, vec!["baz"]);
+        assert_eq!(outcome.removed, vec!["bar"]);
+        let edit = outcome.edit.unwrap();
+        assert_eq!(&src[edit.range], "r");
+        assert_eq!(edit.content, "z");
+
+        let outcome = fix_source(&src.replace("def", "def _"), &Options::default()).unwrap();
+        assert_eq!(outcome.block, BlockChange::Removed);
+        assert_eq!(outcome.removed, vec!["bar", "foo"]);
+    }
+
+    #[test]
+    fn edit_ranges() {
+        assert_eq!(edit("abc", "abc"), None);
+        let edit_of = |old, new| edit(old, new).map(|edit| (edit.range, edit.content));
+        assert_eq!(edit_of("abc", "abxc"), Some((2..2, String::from("x"))));
+        assert_eq!(edit_of("aaa", "aa"), Some((2..3, String::new())));
+        assert_eq!(edit_of("é", "è"), Some((0..2, String::from("è"))));
+    }
+
+    #[test]
+    fn fix_streams() {
+        let src = b"# -*- coding: latin-1 -*-\ncaf\xe9 = 1\n";
+        let mut fixed = Vec::new();
+        let outcome = fix_from_reader(&src[..], &mut fixed, &Options::default()).unwrap();
+        assert!(outcome.changed);
+        assert!(fixed.starts_with(src));
+        assert!(fixed.ends_with(b"__all__ = [\n    \"caf\xe9\",\n]\n# allways: end\n"));
+
+        let mut unchanged = Vec::new();
+        fix_from_reader(&fixed[..], &mut unchanged, &Options::default()).unwrap();
+        assert_eq!(unchanged, fixed);
+    }
+
+    #[test]
+    fn fix_file_expands_star_imports() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("_foo.py"), "def foo(): ...\n").unwrap();
+        let init = root.path().join("__init__.py");
+        std::fs::write(&init, "from ._foo import *\n").unwrap();
+        let outcome = fix_file(&init, &Options::default()).unwrap();
+        assert!(outcome.src.contains("\"foo\""));
+        assert_eq!(
+            std::fs::read_to_string(&init).unwrap(),
+            "from ._foo import *\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_outcome() {
+        let src = "x = 1\n__all__ += names\n";
+        let outcome = fix_source(src, &Options::default()).unwrap();
+        let json = serde_json::to_value(&outcome).unwrap();
+        assert_eq!(json["block"], "created");
+        assert_eq!(json["diagnostics"][0]["line"], 2);
+        assert_eq!(json["diagnostics"][0]["rule"], serde_json::Value::Null);
+        assert_eq!(
+            serde_json::to_value(crate::rules::Rule::StaleAll).unwrap(),
+            "ALW002"
+        );
+        let round_trip = serde_json::from_value::<FixOutcome>(json).unwrap();
+        assert_eq!(round_trip, outcome);
+    }
+}
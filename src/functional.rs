@@ -0,0 +1,83 @@
+use rustpython_parser::ast;
+use rustpython_parser::ast::Expr;
+
+/// Factories whose first argument is the name of the type they create, e.g.
+/// `Point = NamedTuple("Point", ...)`.
+const FACTORIES: &[&str] = &[
+    "namedtuple",
+    "NamedTuple",
+    "TypedDict",
+    "Enum",
+    "IntEnum",
+    "StrEnum",
+    "Flag",
+    "IntFlag",
+    "NewType",
+    "TypeVar",
+    "ParamSpec",
+    "TypeVarTuple",
+];
+
+/// A call to one of the functional type factories and the name it is given.
+#[derive(Debug, PartialEq)]
+pub struct FunctionalType<'a> {
+    pub factory: &'a str,
+    pub name: &'a str,
+}
+
+/// The type created by `value`, if it is a call like `NamedTuple("Point", ...)` or
+/// `enum.Enum("Color", ...)`.
+pub fn functional_type(value: &Expr) -> Option<FunctionalType<'_>> {
+    let Expr::Call(ast::ExprCall { func, args, .. }) = value else {
+        return None;
+    };
+    let factory = match func.as_ref() {
+        Expr::Name(ast::ExprName { id, .. }) => id.as_str(),
+        Expr::Attribute(ast::ExprAttribute { attr, .. }) => attr.as_str(),
+        _ => return None,
+    };
+    if !FACTORIES.contains(&factory) {
+        return None;
+    }
+    match args.first()? {
+        Expr::Constant(ast::ExprConstant {
+            value: ast::Constant::Str(name),
+            ..
+        }) => Some(FunctionalType { factory, name }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustpython_parser::Parse;
+
+    fn functional_type_of(src: &str) -> Option<(String, String)> {
+        let expr = Expr::parse(src, "<test>").unwrap();
+        functional_type(&expr).map(|typ| (typ.factory.to_string(), typ.name.to_string()))
+    }
+
+    #[test]
+    fn factories() {
+        assert_eq!(
+            functional_type_of("NamedTuple('Point', [('x', int)])"),
+            Some((String::from("NamedTuple"), String::from("Point")))
+        );
+        assert_eq!(
+            functional_type_of("enum.Enum('Color', 'RED GREEN')"),
+            Some((String::from("Enum"), String::from("Color")))
+        );
+        assert_eq!(
+            functional_type_of("collections.namedtuple(typename='Point', field_names='x y')"),
+            None
+        );
+    }
+
+    #[test]
+    fn other_calls() {
+        assert_eq!(functional_type_of("dict('a')"), None);
+        assert_eq!(functional_type_of("TypedDict(name, {})"), None);
+    }
+}
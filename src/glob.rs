@@ -0,0 +1,73 @@
+use std::path::Component;
+use std::path::Path;
+
+/// Whether `pattern` matches the end of `path`, e.g. `tests/*.py` matches `proj/tests/foo.py`.
+pub fn matches_path(pattern: &str, path: &Path) -> bool {
+    let components = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    (0..components.len()).any(|start| matches(pattern, &components[start..].join("/")))
+}
+
+/// Shell style matching of `text` against `pattern`: `*` matches anything but `/`, `**` matches
+/// anything and `?` matches a single character other than `/`.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    matches_bytes(pattern.as_bytes(), text.as_bytes())
+}
+
+fn matches_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len()).any(|skip| matches_bytes(rest, &text[skip..]))
+        }
+        [b'*', rest @ ..] => {
+            let segment = text.iter().position(|&byte| byte == b'/');
+            let max = segment.unwrap_or(text.len());
+            (0..=max).any(|skip| matches_bytes(rest, &text[skip..]))
+        }
+        [b'?', rest @ ..] => match text {
+            [byte, text @ ..] if *byte != b'/' => matches_bytes(rest, text),
+            _ => false,
+        },
+        [expected, rest @ ..] => match text {
+            [byte, text @ ..] if byte == expected => matches_bytes(rest, text),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.py", "foo.py"));
+        assert!(!matches("*.py", "pkg/foo.py"));
+        assert!(matches("**/*.py", "pkg/sub/foo.py"));
+        assert!(matches("**/*.py", "foo.py"));
+        assert!(matches("tests/**", "tests/unit/test_foo.py"));
+        assert!(matches("foo?.py", "foo1.py"));
+        assert!(!matches("foo?.py", "foo.py"));
+    }
+
+    #[test]
+    fn path_suffixes() {
+        assert!(matches_path("tests/*", Path::new("proj/tests/test_foo.py")));
+        assert!(matches_path(
+            "*/__init__.py",
+            Path::new("./pkg/__init__.py")
+        ));
+        assert!(matches_path(
+            "__init__.py",
+            Path::new("/abs/pkg/__init__.py")
+        ));
+        assert!(!matches_path("tests/*", Path::new("proj/src/foo.py")));
+    }
+}
@@ -1,3 +1,138 @@
+//! Automatically update `__all__` statements in python modules.
+//!
+//! ```
+//! use allways::fix_source;
+//! use allways::Options;
+//!
+//! let options = Options::builder().type_aliases(false).build();
+//! let outcome = fix_source("def foo():\n    ...\n", &options).unwrap();
+//! assert!(outcome.changed);
+//! assert!(outcome.src.ends_with("# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n"));
+//! ```
+
 mod add_all;
+mod api_diff;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+mod autofix;
+mod cache;
+mod check;
+mod comments;
+mod condition;
+mod config;
+mod coverage;
+#[cfg(feature = "serde")]
+mod daemon;
+mod diagnostic;
+mod discover;
+mod editorconfig;
+mod encoding;
+mod explain;
+mod exports;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod filter;
+mod fix;
+mod functional;
+mod glob;
+mod lsp;
+mod manifest;
+mod module_getattr;
 mod name_parser;
+mod noqa;
+mod notebook;
+mod options;
+mod package;
+mod patch;
+mod provider;
+#[cfg(feature = "python")]
+mod python;
+mod recover;
+mod regions;
+mod repair;
+mod resolve;
+mod rules;
+mod syntax;
+mod walrus;
+#[cfg(feature = "wasm")]
+mod wasm;
+pub use add_all::add_markers;
 pub use add_all::do_it_allways;
+pub use add_all::do_it_allways_with;
+pub use add_all::ConditionalNames;
+pub use add_all::Outcome;
+pub use add_all::PublicNames;
+pub use api_diff::api_diff;
+pub use api_diff::diff_names;
+pub use api_diff::ApiDiff;
+pub use api_diff::ModuleDiff;
+#[cfg(feature = "tokio")]
+pub use asynchronous::fix_dir;
+#[cfg(feature = "tokio")]
+pub use asynchronous::fix_path;
+pub use cache::cache_key;
+pub use cache::default_cache_dir;
+pub use cache::Cache;
+pub use check::check;
+pub use config::Config;
+pub use coverage::coverage;
+pub use coverage::Coverage;
+#[cfg(feature = "serde")]
+pub use daemon::serve_daemon;
+pub use diagnostic::Diagnostic;
+pub use discover::in_environment;
+pub use discover::is_excluded_dir;
+pub use discover::packages;
+pub use discover::packages_where;
+pub use discover::packages_with;
+pub use discover::Package;
+pub use discover::DEFAULT_EXCLUDE;
+pub use encoding::read_source;
+pub use encoding::write_source;
+pub use encoding::Encoding;
+pub use explain::explain;
+pub use explain::explain_names;
+pub use explain::Explanation;
+pub use explain::Reason;
+pub use exports::exports_modules;
+pub use exports::ExportsModule;
+pub use filter::ExportCandidate;
+pub use filter::NameFilter;
+pub use fix::fix_file;
+pub use fix::fix_from_reader;
+pub use fix::fix_source;
+pub use fix::BlockChange;
+pub use fix::Edit;
+pub use fix::FixOutcome;
+pub use lsp::serve_lsp;
+pub use manifest::exported_names;
+pub use manifest::manifest;
+pub use manifest::ExportedName;
+pub use manifest::Manifest;
+pub use manifest::ModuleManifest;
+pub use name_parser::take_parse_time;
+pub use name_parser::Kind;
+pub use name_parser::StarImport;
+pub use notebook::check_notebook;
+pub use notebook::do_it_allways_notebook;
+pub use notebook::is_notebook;
+pub use options::BlockHeader;
+pub use options::BlockStyle;
+pub use options::BlockTemplate;
+pub use options::ModuleName;
+pub use options::Options;
+pub use options::OptionsBuilder;
+pub use options::Placement;
+pub use options::PythonVersion;
+pub use options::Section;
+pub use package::do_it_allways_package;
+pub use package::PackageOptions;
+pub use patch::unified_diff;
+pub use provider::ManifestNames;
+pub use provider::NameProvider;
+pub use provider::SourceNames;
+pub use provider::StubNames;
+pub use repair::repair_block;
+pub use rules::Rule;
+pub use rules::RuleSettings;
+pub use rules::Severity;
@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use rustpython_parser::ast;
+use rustpython_parser::ast::Ranged;
+use rustpython_parser::ast::Stmt;
+use rustpython_parser::ast::Suite;
+use rustpython_parser::Parse;
+use serde_json::json;
+use serde_json::Value;
+
+use crate::check::check;
+use crate::fix::fix;
+use crate::notebook::check_notebook;
+use crate::notebook::is_notebook;
+use crate::options::Options;
+use crate::rules::Severity;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const CODE_ACTION_KIND: &str = "source.fixAll.allways";
+
+/// Serve the Language Server Protocol over `input` and `output` until the client exits.
+///
+/// Open python files get lint diagnostics, and an "Update `__all__`" code action and document
+/// formatting both update their allways block.
+pub fn serve_lsp(input: impl BufRead, output: impl Write, options: &Options) -> Result<()> {
+    let mut server = Server {
+        output,
+        options,
+        documents: HashMap::new(),
+    };
+    server.run(input)
+}
+
+struct Server<'a, W> {
+    output: W,
+    options: &'a Options,
+    /// The open documents by uri.
+    documents: HashMap<String, Document>,
+}
+
+struct Document {
+    text: String,
+    /// Byte ranges of the bodies of module level functions, where editing a line can't change
+    /// the names of the module nor the diagnostics.
+    bodies: Vec<Range<usize>>,
+}
+
+impl Document {
+    fn new(text: String, options: &Options) -> Self {
+        let bodies = function_bodies(&text, options);
+        Self { text, bodies }
+    }
+
+    /// Replace the bytes `range` by `text`, returning whether the module has to be analyzed
+    /// again.
+    fn edit(&mut self, range: Range<usize>, text: &str) -> bool {
+        let local = is_local(&self.text[range.clone()])
+            && is_local(text)
+            && self
+                .bodies
+                .iter()
+                .any(|body| body.start <= range.start && range.end <= body.end);
+        let shift = |offset: usize| {
+            if offset >= range.end {
+                offset - range.len() + text.len()
+            } else {
+                offset
+            }
+        };
+        for body in &mut self.bodies {
+            *body = shift(body.start)..shift(body.end);
+        }
+        self.text.replace_range(range, text);
+        !local
+    }
+}
+
+impl<W: Write> Server<'_, W> {
+    fn run(&mut self, mut input: impl BufRead) -> Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            let method = message["method"].as_str().unwrap_or_default();
+            let params = &message["params"];
+            let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+            let result = match method {
+                "initialize" => Some(json!({
+                    "capabilities": {
+                        "textDocumentSync": 2,
+                        "documentFormattingProvider": true,
+                        "codeActionProvider": { "codeActionKinds": [CODE_ACTION_KIND] },
+                    },
+                    "serverInfo": { "name": "allways", "version": env!("CARGO_PKG_VERSION") },
+                })),
+                "shutdown" => Some(Value::Null),
+                "exit" => return Ok(()),
+                "textDocument/didOpen" => {
+                    let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                    self.update(uri, text.to_string())?;
+                    None
+                }
+                "textDocument/didChange" => {
+                    let changes = params["contentChanges"].as_array();
+                    self.change(uri, changes.map(Vec::as_slice).unwrap_or_default())?;
+                    None
+                }
+                "textDocument/didClose" => {
+                    self.documents.remove(uri);
+                    self.publish(uri, Vec::new())?;
+                    None
+                }
+                "textDocument/formatting" => Some(Value::from(self.edits(uri))),
+                "textDocument/codeAction" => Some(self.code_actions(uri)),
+                _ => None,
+            };
+            // notifications have no id and get no response
+            let Some(id) = message.get("id") else {
+                continue;
+            };
+            let response = match result {
+                Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("Unsupported method {method:?}"),
+                    },
+                }),
+            };
+            write_message(&mut self.output, &response)?;
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> Result<()> {
+        let diagnostics = self.diagnostics(uri, &text);
+        let document = Document::new(text, self.options);
+        self.documents.insert(uri.to_string(), document);
+        self.publish(uri, diagnostics)
+    }
+
+    /// Apply the `changes` of the document `uri`, analyzing it again unless they were all
+    /// limited to a line of a function body.
+    fn change(&mut self, uri: &str, changes: &[Value]) -> Result<()> {
+        let Some(document) = self.documents.get_mut(uri) else {
+            return Ok(());
+        };
+        let mut analyze = false;
+        for change in changes {
+            let text = change["text"].as_str().unwrap_or_default();
+            match change.get("range") {
+                Some(range) => {
+                    let start = offset(&document.text, &range["start"]);
+                    let end = offset(&document.text, &range["end"]).max(start);
+                    analyze |= document.edit(start..end, text);
+                }
+                None => {
+                    document.text = text.to_string();
+                    analyze = true;
+                }
+            }
+        }
+        if analyze {
+            let text = std::mem::take(&mut document.text);
+            self.update(uri, text)?;
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Value>) -> Result<()> {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        });
+        write_message(&mut self.output, &notification)
+    }
+
+    /// Lint diagnostics of the document `uri`, none when it doesn't parse.
+    fn diagnostics(&self, uri: &str, text: &str) -> Vec<Value> {
+        let path = uri_path(uri);
+        let diagnostics = match path.as_deref() {
+            Some(path) if is_notebook(path) => check_notebook(text, Some(path), self.options),
+            path => check(text, path, self.options),
+        };
+        diagnostics
+            .unwrap_or_default()
+            .into_iter()
+            .map(|diagnostic| {
+                let severity = diagnostic
+                    .rule
+                    .and_then(|rule| self.options.rules.severity(rule))
+                    .unwrap_or(Severity::Warning);
+                let severity = if severity == Severity::Error { 1 } else { 2 };
+                let line = diagnostic.line - 1;
+                json!({
+                    "range": {
+                        "start": { "line": line, "character": 0 },
+                        "end": { "line": line + 1, "character": 0 },
+                    },
+                    "severity": severity,
+                    "code": diagnostic.rule.map(|rule| rule.to_string()),
+                    "source": "allways",
+                    "message": diagnostic.message,
+                })
+            })
+            .collect()
+    }
+
+    /// The text edits updating the allways block of the document `uri`.
+    fn edits(&self, uri: &str) -> Vec<Value> {
+        let Some(Document { text, .. }) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let path = uri_path(uri);
+        let Ok(outcome) = fix(text, path.as_deref(), self.options) else {
+            return Vec::new();
+        };
+        outcome
+            .edit
+            .into_iter()
+            .map(|edit| {
+                json!({
+                    "range": {
+                        "start": position(text, edit.range.start),
+                        "end": position(text, edit.range.end),
+                    },
+                    "newText": edit.content,
+                })
+            })
+            .collect()
+    }
+
+    fn code_actions(&self, uri: &str) -> Value {
+        let edits = self.edits(uri);
+        if edits.is_empty() {
+            return json!([]);
+        }
+        json!([{
+            "title": "Update `__all__`",
+            "kind": CODE_ACTION_KIND,
+            "edit": { "changes": { uri: edits } },
+        }])
+    }
+}
+
+/// Read a message framed by a `Content-Length` header, `None` once `input` is exhausted.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+    let length = length.ok_or_else(|| anyhow!("Message without a Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    Ok(output.flush()?)
+}
+
+/// The position of the byte `offset` of `text`, in UTF-16 code units as LSP counts them.
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+/// The byte offset of an LSP `position` of `text`, clamped to the end of its line.
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default() as usize;
+    let column = position["character"].as_u64().unwrap_or_default() as usize;
+    let line_start = text
+        .split_inclusive('\n')
+        .take(line)
+        .map(str::len)
+        .sum::<usize>();
+    let rest = &text[line_start..];
+    let line_text = &rest[..rest.find('\n').unwrap_or(rest.len())];
+    let mut units = 0;
+    for (index, character) in line_text.char_indices() {
+        if units >= column {
+            return line_start + index;
+        }
+        units += character.len_utf16();
+    }
+    line_start + line_text.len()
+}
+
+/// Whether editing `text` within a line of a function body leaves the statements around it
+/// alone: no line breaks, nor brackets, quotes or comments that could swallow what follows.
+fn is_local(text: &str) -> bool {
+    !text.contains([
+        '\n', '\r', '\\', '#', '"', '\'', '(', ')', '[', ']', '{', '}',
+    ])
+}
+
+/// Byte ranges of the bodies of the module level functions of `text`.
+///
+/// `__getattr__` and `__dir__` are left out as they decide lazily loaded names, and so is
+/// every function when they may bind module names by declaring them `global`.
+fn function_bodies(text: &str, options: &Options) -> Vec<Range<usize>> {
+    if options.global_declarations {
+        return Vec::new();
+    }
+    let Ok(statements) = Suite::parse(text, "<lsp>") else {
+        return Vec::new();
+    };
+    statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Stmt::FunctionDef(ast::StmtFunctionDef {
+                name, body, range, ..
+            })
+            | Stmt::AsyncFunctionDef(ast::StmtAsyncFunctionDef {
+                name, body, range, ..
+            }) => {
+                if matches!(name.as_str(), "__getattr__" | "__dir__") {
+                    return None;
+                }
+                let start = usize::from(body.first()?.start());
+                Some(start..usize::from(range.end()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The path of a `file://` uri, needed to expand star imports and compare stubs.
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = match bytes[index] {
+            b'%' => encoded
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // `file:///C:/project/mod.py` on Windows
+    match path.strip_prefix('/') {
+        Some(windows) if windows.get(1..2) == Some(":") => Some(PathBuf::from(windows)),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed(message: Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{body}", body.len())
+    }
+
+    #[test]
+    fn diagnostics_and_formatting() {
+        let uri = "file:///project/my%20mod.py";
+        let document = json!({ "uri": uri });
+        let input = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": { "uri": uri, "languageId": "python", "text": "def foo(): ...\n" },
+                },
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "textDocument/formatting",
+                "params": { "textDocument": document, "options": {} },
+            }),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]
+        .into_iter()
+        .map(framed)
+        .collect::<String>();
+        let mut output = Vec::new();
+        serve_lsp(input.as_bytes(), &mut output, &Options::default()).unwrap();
+
+        let mut output = output.as_slice();
+        let messages =
+            std::iter::from_fn(|| read_message(&mut output).unwrap()).collect::<Vec<_>>();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0]["result"]["serverInfo"]["name"], "allways");
+        assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(messages[1]["params"]["diagnostics"][0]["code"], "ALW001");
+        let edit = &messages[2]["result"][0];
+        assert_eq!(edit["range"]["start"], json!({ "line": 1, "character": 0 }));
+        assert_eq!(
+            edit["newText"],
+            "\n\n# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n"
+        );
+        assert_eq!(messages[3]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(messages[4]["result"], Value::Null);
+    }
+
+    #[test]
+    fn incremental_changes() {
+        let uri = "file:///project/mod.py";
+        let change = |line, start, end, text| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": uri, "version": 2 },
+                    "contentChanges": [{
+                        "range": {
+                            "start": { "line": line, "character": start },
+                            "end": { "line": line, "character": end },
+                        },
+                        "text": text,
+                    }],
+                },
+            })
+        };
+        let input = [
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": { "uri": uri, "text": "def foo():\n    return 1\n" },
+                },
+            }),
+            // within the function body, the diagnostics can't change
+            change(1, 11, 12, "2"),
+            change(2, 0, 0, "bar = 1\n"),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "textDocument/formatting",
+                "params": { "textDocument": { "uri": uri } },
+            }),
+        ]
+        .into_iter()
+        .map(framed)
+        .collect::<String>();
+        let mut output = Vec::new();
+        serve_lsp(input.as_bytes(), &mut output, &Options::default()).unwrap();
+
+        let mut output = output.as_slice();
+        let messages =
+            std::iter::from_fn(|| read_message(&mut output).unwrap()).collect::<Vec<_>>();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(
+            messages[2]["result"][0]["newText"],
+            "\n\n# allways: start\n__all__ = [\n    \"bar\",\n    \"foo\",\n]\n# allways: end\n"
+        );
+    }
+
+    #[test]
+    fn local_edits() {
+        let options = Options::default();
+        let mut document =
+            Document::new(String::from("def foo():\n    x = 1\n\ny = 2\n"), &options);
+        assert_eq!(document.bodies, vec![15..20]);
+        assert!(!document.edit(19..20, "10"));
+        assert_eq!(document.bodies, vec![15..21]);
+        assert!(document.edit(19..21, "(1"));
+        assert!(document.edit(23..23, "z"));
+        assert_eq!(document.text, "def foo():\n    x = (1\n\nzy = 2\n");
+        assert_eq!(offset("é = 1\nx", &json!({ "line": 0, "character": 2 })), 3);
+    }
+
+    #[test]
+    fn positions_and_paths() {
+        assert_eq!(
+            position("é = 1\nx", 7),
+            json!({ "line": 1, "character": 0 })
+        );
+        assert_eq!(
+            position("é = 1\nx", 3),
+            json!({ "line": 0, "character": 2 })
+        );
+        assert_eq!(
+            uri_path("file:///project/my%20mod.py"),
+            Some(PathBuf::from("/project/my mod.py"))
+        );
+        assert_eq!(
+            uri_path("file:///C:/project/mod.py"),
+            Some(PathBuf::from("C:/project/mod.py"))
+        );
+        assert_eq!(uri_path("untitled:Untitled-1"), None);
+    }
+}
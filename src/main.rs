@@ -1,29 +1,1067 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use clap::Parser;
+use clap::Subcommand;
 
+use allways::add_markers;
+use allways::api_diff;
+use allways::cache_key;
+use allways::check;
+use allways::check_notebook;
+use allways::coverage;
+use allways::default_cache_dir;
+use allways::diff_names;
 use allways::do_it_allways;
+use allways::do_it_allways_notebook;
+use allways::do_it_allways_package;
+use allways::do_it_allways_with;
+use allways::explain;
+use allways::explain_names;
+use allways::exported_names;
+use allways::exports_modules;
+use allways::in_environment;
+use allways::is_excluded_dir;
+use allways::is_notebook;
+use allways::manifest;
+use allways::packages_where;
+use allways::read_source;
+use allways::repair_block;
+use allways::serve_daemon;
+use allways::serve_lsp;
+use allways::take_parse_time;
+use allways::unified_diff;
+use allways::write_source;
+use allways::BlockHeader;
+use allways::BlockStyle;
+use allways::BlockTemplate;
+use allways::Cache;
+use allways::Config;
+use allways::Coverage;
+use allways::Diagnostic;
+use allways::Encoding;
+use allways::Explanation;
+use allways::ExportsModule;
+use allways::FixOutcome;
+use allways::Manifest;
+use allways::ModuleName;
+use allways::NameProvider;
+use allways::Options;
+use allways::Outcome;
+use allways::Package;
+use allways::PackageOptions;
+use allways::Placement;
+use allways::PublicNames;
+use allways::PythonVersion;
+use allways::Rule;
+use allways::Severity;
+use allways::SourceNames;
+
+/// The exit code of a run stopped by Ctrl-C, 128 plus the number of SIGINT like shells use.
+const INTERRUPTED_RTC: i32 = 130;
+
+/// Set by the first Ctrl-C, the file being written is finished before stopping.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    // commands writing files one after the other stop between two of them, a second Ctrl-C
+    // exits right away, files are written atomically all the same. A run prompting for each
+    // change keeps the default Ctrl-C, which stops it while waiting for an answer.
+    let prompts = args.interactive || args.confirm;
+    if !prompts
+        && matches!(
+            args.command,
+            None | Some(Command::Repair(_) | Command::AddMarkers(_))
+        )
+    {
+        ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(INTERRUPTED_RTC);
+            }
+        })?;
+    }
+
+    let rtc = match &args.command {
+        Some(Command::Package(package)) => run_package(package)?,
+        Some(Command::Manifest(manifest)) => run_manifest(manifest)?,
+        Some(Command::ApiDiff(api_diff)) => run_api_diff(api_diff)?,
+        Some(Command::Diff(diff)) => run_diff(diff)?,
+        Some(Command::Explain(explain)) => run_explain(explain)?,
+        Some(Command::Coverage(coverage)) => run_coverage(coverage)?,
+        Some(Command::Repair(repair)) => run_repair(repair)?,
+        Some(Command::AddMarkers(add)) => run_add_markers(add)?,
+        Some(Command::Cache(cache)) => run_cache(cache)?,
+        Some(Command::Config(config)) => run_config(config)?,
+        Some(Command::Lsp(lsp)) => run_lsp(lsp)?,
+        Some(Command::Daemon(daemon)) => run_daemon(daemon)?,
+        None => run_files(&args)?,
+    };
 
+    std::process::exit(rtc);
+}
+
+fn run_files(args: &Args) -> Result<i32> {
     check_files(&args.paths)?;
+    let mut file_options = FileOptions::new(&args.options)?;
+    let mut stats = Stats::default();
 
     let mut rtc = 0;
-    for file in &args.paths {
-        let src = std::fs::read_to_string(file)?;
-        if let Some(new_src) = do_it_allways(&src)? {
-            if src != new_src {
-                println!("Updating __all__ statement in {}", file.display());
-                std::fs::write(file, new_src)?;
-                rtc |= 1;
+    let mut confirm = args.confirm;
+    let files = timed(&mut stats.discovery, || {
+        expand_paths(&args.paths, &mut file_options)
+    })?;
+    if args.output.is_some() && (files.len() != 1 || args.paths.iter().any(|path| path.is_dir())) {
+        Err(anyhow!("--output takes a single file"))?;
+    }
+    // files found up to date by previous runs are skipped, unless reviewing or writing elsewhere
+    let cached = !args.no_cache
+        && !args.interactive
+        && !args.confirm
+        && !args.show_source
+        && args.output.is_none()
+        && matches!(args.output_format, OutputFormat::Text);
+    let mut cache = match &args.cache_dir {
+        Some(dir) if cached => Some(Cache::load(dir)),
+        None if cached => default_cache_dir().map(|dir| Cache::load(&dir)),
+        _ => None,
+    };
+    for (done, file) in files.iter().enumerate() {
+        if interrupted(done, files.len()) {
+            rtc = INTERRUPTED_RTC;
+            break;
+        }
+        let start = Instant::now();
+        let options = file_options.get(file)?;
+        // the `__init__.py` of a namespace package is only created when names are found
+        let (src, encoding) = timed(&mut stats.read, || {
+            if file.exists() {
+                read_source(file)
+            } else {
+                Ok((String::new(), Encoding::default()))
+            }
+        })?;
+        let path = Some(file.as_path());
+        let key = match &cache {
+            Some(_) if file.exists() => cache_key(&src, file, args.check, options)?,
+            _ => None,
+        };
+        if let (Some(cache), Some(key)) = (&cache, key) {
+            if cache.is_fresh(file, key) {
+                if args.porcelain {
+                    println!("unchanged\t{}\t\t", display_path(file));
+                }
+                stats.files.push((file.clone(), start.elapsed()));
+                continue;
             }
         }
+        // what fixing the file changes, for `--show-source`
+        let mut change = None;
+        if args.check {
+            let diagnostics = timed(&mut stats.analysis, || {
+                if is_notebook(file) {
+                    check_notebook(&src, path, options)
+                } else {
+                    check(&src, path, options)
+                }
+            })?;
+            if let (Some(cache), Some(key), true) = (&mut cache, key, diagnostics.is_empty()) {
+                cache.insert(file, key);
+            }
+            for diagnostic in diagnostics {
+                if report(file, &diagnostic, options) == Severity::Error {
+                    rtc |= 1;
+                }
+            }
+            if args.verbose || args.show_source {
+                let fixed = timed(&mut stats.analysis, || -> Result<FixOutcome> {
+                    let outcome = if is_notebook(file) {
+                        do_it_allways_notebook(&src, path, options)?
+                    } else {
+                        do_it_allways(&src, path, options)?
+                    };
+                    FixOutcome::new(&src, path, outcome)
+                })?;
+                if args.verbose {
+                    report_drift(file, &fixed);
+                }
+                change = Some(fixed);
+            }
+        } else {
+            let mut outcome = timed(&mut stats.analysis, || {
+                if is_notebook(file) {
+                    do_it_allways_notebook(&src, path, options)
+                } else {
+                    do_it_allways(&src, path, options)
+                }
+            })?;
+            if args.interactive && !review(file, &src, &mut outcome, options)? {
+                break;
+            }
+            if confirm && !confirm_change(file, &src, &mut outcome, &mut confirm)? {
+                break;
+            }
+            if args.show_source {
+                change = Some(FixOutcome::new(&src, path, outcome.clone())?);
+            }
+            // the module `__all__` is generated into goes with the `__init__.py` importing it
+            let exports = match (&outcome.src, &args.output, args.output_format) {
+                (Some(_), None, OutputFormat::Text) if !is_notebook(file) => {
+                    timed(&mut stats.analysis, || exports_modules(&src, file, options))?
+                }
+                _ => Vec::new(),
+            };
+            // the file is up to date once written
+            let fixed_key = match &cache {
+                Some(_)
+                    if outcome.diagnostics.is_empty()
+                        && (outcome.src.is_some() || file.exists()) =>
+                {
+                    let fixed = outcome.src.as_deref().unwrap_or(&src);
+                    cache_key(fixed, file, false, options)?
+                }
+                _ => None,
+            };
+            rtc |= timed(&mut stats.write, || write_exports(&exports, args.porcelain))?;
+            rtc |= timed(&mut stats.write, || {
+                match (&args.output, args.output_format) {
+                    (Some(output), _) => {
+                        write_output(file, output, &src, encoding, outcome, options)
+                    }
+                    (None, OutputFormat::Text) if args.porcelain => {
+                        apply_porcelain(file, &src, encoding, outcome, options)
+                    }
+                    (None, OutputFormat::Text) => apply(file, &src, encoding, outcome, options),
+                    (None, OutputFormat::Patch) => {
+                        print_patch(file, &src, encoding, outcome, options)
+                    }
+                }
+            })?;
+            if let (Some(cache), Some(key)) = (&mut cache, fixed_key) {
+                cache.insert(file, key);
+            }
+        }
+        if let (true, Some(change)) = (args.show_source, &change) {
+            show_source(file, &src, change, options);
+        }
+        stats.files.push((file.clone(), start.elapsed()));
+    }
+    if let Some(mut cache) = cache {
+        // the next run only takes longer without it
+        if let Err(err) = cache.save() {
+            eprintln!("Failed to save the cache: {err:#}");
+        }
     }
+    if let Some(slowest) = args.stats {
+        // files are parsed as they are analyzed, the time parsing them is reported apart
+        stats.parse = take_parse_time();
+        stats.analysis = stats.analysis.saturating_sub(stats.parse);
+        stats.report(slowest);
+    }
+    Ok(rtc)
+}
 
-    std::process::exit(rtc);
+/// Whether Ctrl-C was pressed, reporting how many of the `total` files were `done` when it was.
+fn interrupted(done: usize, total: usize) -> bool {
+    let interrupted = INTERRUPTED.load(Ordering::SeqCst);
+    if interrupted {
+        eprintln!("Interrupted, {done} of {total} files processed");
+    }
+    interrupted
+}
+
+/// Time spent in each phase of a run, reported by `--stats`.
+#[derive(Default)]
+struct Stats {
+    discovery: Duration,
+    read: Duration,
+    /// Parsing, which happens as files are analyzed.
+    parse: Duration,
+    /// Parsing excluded.
+    analysis: Duration,
+    write: Duration,
+    /// Time spent on each file, all phases but discovery.
+    files: Vec<(PathBuf, Duration)>,
+}
+
+impl Stats {
+    /// Print the time of each phase and the `slowest` files to stderr.
+    fn report(mut self, slowest: usize) {
+        let total = self.discovery + self.read + self.parse + self.analysis + self.write;
+        eprintln!("Time per phase:");
+        for (phase, duration) in [
+            ("discovery", self.discovery),
+            ("read", self.read),
+            ("parse", self.parse),
+            ("analysis", self.analysis),
+            ("write", self.write),
+            ("total", total),
+        ] {
+            eprintln!("  {phase:<10} {duration:>10.2?}");
+        }
+        self.files.sort_by(|(_, a), (_, b)| b.cmp(a));
+        eprintln!("Slowest files:");
+        for (file, duration) in self.files.iter().take(slowest) {
+            eprintln!("  {duration:>10.2?}  {}", display_path(file));
+        }
+    }
+}
+
+/// Run `f`, adding the time it took to `duration`.
+fn timed<T>(duration: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let value = f();
+    *duration += start.elapsed();
+    value
+}
+
+fn run_package(args: &PackageArgs) -> Result<i32> {
+    if !args.dir.is_dir() {
+        Err(anyhow!("Package {:?} is not a directory!", args.dir))?;
+    }
+    let options = args.options.options()?;
+    let package_options = PackageOptions {
+        imported_only: args.imported_only,
+        reexports: args.reexports,
+    };
+    let init = args.dir.join("__init__.py");
+    let (src, encoding) = if init.is_file() {
+        read_source(&init)?
+    } else {
+        (String::new(), Encoding::default())
+    };
+    let outcome = do_it_allways_package(&args.dir, &options, &package_options)?;
+    apply(&init, &src, encoding, outcome, &options)
+}
+
+fn run_manifest(args: &ManifestArgs) -> Result<i32> {
+    if !args.package.is_dir() {
+        Err(anyhow!("Package {:?} is not a directory!", args.package))?;
+    }
+    let options = args.options.options()?;
+    let manifest = manifest(&args.package, &options)?;
+    println!("{}", serde_json::to_string_pretty(&manifest)?);
+    Ok(0)
+}
+
+fn run_api_diff(args: &ApiDiffArgs) -> Result<i32> {
+    if !args.package.is_dir() {
+        Err(anyhow!("Package {:?} is not a directory!", args.package))?;
+    }
+    let options = args.options.options()?;
+    let baseline = std::fs::read_to_string(&args.against)
+        .with_context(|| format!("Failed to read {}", args.against.display()))?;
+    let baseline: Manifest = serde_json::from_str(&baseline)
+        .with_context(|| format!("Invalid manifest {}", args.against.display()))?;
+    let diff = api_diff(&baseline, &manifest(&args.package, &options)?);
+    match args.format {
+        Format::Text => print!("{diff}"),
+        Format::Markdown => print!("{}", diff.markdown()),
+    }
+    Ok(i32::from(diff.is_breaking()))
+}
+
+fn run_diff(args: &DiffArgs) -> Result<i32> {
+    check_files(&[args.old.clone(), args.new.clone()])?;
+    let options = args.options.options()?;
+    let diff = diff_names(
+        &args.new.display().to_string(),
+        &exported_names(&args.old, &options)?,
+        &exported_names(&args.new, &options)?,
+    );
+    print!("{diff}");
+    Ok(i32::from(!diff.is_empty()))
+}
+
+fn run_explain(args: &ExplainArgs) -> Result<i32> {
+    check_files(std::slice::from_ref(&args.file))?;
+    let options = args.options.options()?;
+    let (src, _) = read_source(&args.file)?;
+    let explanation = explain(&src, Some(&args.file), &args.name, &options)?;
+    print!("{explanation}");
+    Ok(0)
+}
+
+fn run_coverage(args: &CoverageArgs) -> Result<i32> {
+    check_files(&args.paths)?;
+    let mut file_options = FileOptions::new(&args.options)?;
+    let mut total = Coverage::default();
+    let mut audited = 0;
+    for file in expand_modules(&args.paths, &mut file_options)? {
+        let (src, _) = read_source(&file)?;
+        let options = file_options.get(&file)?;
+        let Some(coverage) = coverage(&src, Some(&file), options)? else {
+            continue;
+        };
+        println!("{}: {coverage}", display_path(&file));
+        for name in &coverage.missing {
+            println!("  missing: {name}");
+        }
+        audited += 1;
+        total.listed += coverage.listed;
+        total.missing.extend(coverage.missing);
+    }
+    if audited > 1 {
+        println!("Total: {total}");
+    }
+    Ok(i32::from(!total.missing.is_empty()))
+}
+
+fn run_repair(args: &RepairArgs) -> Result<i32> {
+    check_files(&args.paths)?;
+    let mut file_options = FileOptions::new(&args.options)?;
+    let mut rtc = 0;
+    let files = expand_paths(&args.paths, &mut file_options)?;
+    let total = files.len();
+    for (done, file) in files.into_iter().enumerate() {
+        if interrupted(done, total) {
+            return Ok(INTERRUPTED_RTC);
+        }
+        if !file.exists() || is_notebook(&file) {
+            continue;
+        }
+        let (src, encoding) = read_source(&file)?;
+        let options = file_options.get(&file)?;
+        let Some(repaired) = repair_block(&src) else {
+            continue;
+        };
+        // the names of the block are brought up to date right away
+        let outcome = do_it_allways(&repaired, Some(&file), options)?;
+        for diagnostic in &outcome.diagnostics {
+            report(&file, diagnostic, options);
+        }
+        println!("Repairing allways block in {}", display_path(&file));
+        write_source(&file, &outcome.src.unwrap_or(repaired), encoding)?;
+        rtc = 1;
+    }
+    Ok(rtc)
+}
+
+fn run_add_markers(args: &AddMarkersArgs) -> Result<i32> {
+    check_files(&args.paths)?;
+    let mut file_options = FileOptions::new(&args.options)?;
+    let mut rtc = 0;
+    let files = expand_paths(&args.paths, &mut file_options)?;
+    let total = files.len();
+    for (done, file) in files.into_iter().enumerate() {
+        if interrupted(done, total) {
+            return Ok(INTERRUPTED_RTC);
+        }
+        if !file.exists() || is_notebook(&file) {
+            continue;
+        }
+        let (src, encoding) = read_source(&file)?;
+        let options = file_options.get(&file)?;
+        let Some(new_src) = add_markers(&src, Some(&file), options)? else {
+            continue;
+        };
+        println!("Adding allways markers to {}", display_path(&file));
+        write_source(&file, &new_src, encoding)?;
+        rtc = 1;
+    }
+    Ok(rtc)
+}
+
+/// Every python module of the packages below the directories of `paths`, and the files of
+/// `paths`, leaving out excluded ones like [`expand_paths`].
+fn expand_modules(paths: &[PathBuf], file_options: &mut FileOptions) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let options = file_options.get(path)?;
+        if !path.is_dir() {
+            if !options.force_exclude || !options.is_excluded(path) {
+                files.push(long_path(path));
+            }
+            continue;
+        }
+        for package in walk_packages(path, file_options)? {
+            let mut modules = Vec::new();
+            for entry in std::fs::read_dir(&package.dir)? {
+                let module = entry?.path();
+                let python = module.extension().is_some_and(|ext| ext == "py");
+                if python && module.is_file() && !file_options.get(&module)?.is_excluded(&module) {
+                    modules.push(module);
+                }
+            }
+            modules.sort();
+            files.extend(modules);
+        }
+    }
+    Ok(files)
+}
+
+fn run_cache(args: &CacheArgs) -> Result<i32> {
+    let dir = args
+        .cache_dir
+        .clone()
+        .or_else(default_cache_dir)
+        .ok_or_else(|| anyhow!("No cache directory found, pass --cache-dir"))?;
+    match args.action {
+        CacheAction::Info => {
+            let cache = Cache::load(&dir);
+            println!("Cache: {}", display_path(&Cache::file(&dir)));
+            println!("Files up to date: {}", cache.len());
+        }
+        CacheAction::Clear => {
+            // only the files allways wrote, the directory may be shared
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+                Err(err) => return Err(err.into()),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if name.starts_with("allways-") && name.ends_with(".cache") {
+                    std::fs::remove_file(&path)?;
+                    println!("Removing {}", display_path(&path));
+                }
+            }
+        }
+    }
+    Ok(0)
+}
+
+fn run_config(args: &ConfigArgs) -> Result<i32> {
+    match &args.action {
+        ConfigAction::Check(check) => run_config_check(check),
+        ConfigAction::Show(show) => run_config_show(show),
+    }
+}
+
+fn run_config_show(args: &ConfigShowArgs) -> Result<i32> {
+    check_files(std::slice::from_ref(&args.file))?;
+    let mut file_options = FileOptions::new(&args.options)?;
+    let file = long_path(&args.file);
+    let dirs = file_options.config_dirs(&file)?;
+    if dirs.is_empty() {
+        println!("# no config applies, the defaults are used");
+    }
+    for dir in &dirs {
+        println!("# config of {}", display_path(dir));
+    }
+    if let Some(profile) = &args.options.profile {
+        println!("# profile {profile}");
+    }
+    // `.editorconfig` settings apply last
+    let options = file_options.get(&file)?.for_path(&file)?;
+    println!("{options:#?}");
+    Ok(0)
+}
+
+fn run_config_check(args: &ConfigCheckArgs) -> Result<i32> {
+    check_files(&args.files)?;
+    let files = if args.files.is_empty() {
+        match Config::find_files(&std::env::current_dir()?) {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("error: {err:#}");
+                return Ok(1);
+            }
+        }
+    } else {
+        args.files.clone()
+    };
+    if files.is_empty() {
+        println!("No config found");
+        return Ok(0);
+    }
+    let mut rtc = 0;
+    for file in &files {
+        let name = display_path(file);
+        match Config::from_file(file) {
+            Ok(Some(config)) => {
+                let problems = config.problems();
+                for problem in &problems {
+                    eprintln!("{name}: warning: {problem}");
+                }
+                if problems.is_empty() {
+                    println!("{name}: ok");
+                } else {
+                    rtc = 1;
+                }
+            }
+            Ok(None) => println!("{name}: no allways config"),
+            Err(err) => {
+                eprintln!("{name}: error: {err:#}");
+                rtc = 1;
+            }
+        }
+    }
+    Ok(rtc)
+}
+
+fn run_lsp(args: &LspArgs) -> Result<i32> {
+    let options = args.options.options()?;
+    serve_lsp(std::io::stdin().lock(), std::io::stdout().lock(), &options)?;
+    Ok(0)
+}
+
+fn run_daemon(args: &DaemonArgs) -> Result<i32> {
+    let options = args.options.options()?;
+    serve_daemon(std::io::stdin().lock(), std::io::stdout().lock(), &options)?;
+    Ok(0)
+}
+
+/// Report the diagnostics of `outcome` and write its source to `file` with `encoding` if it
+/// changed, returning the exit code.
+fn apply(
+    file: &Path,
+    src: &str,
+    encoding: Encoding,
+    outcome: Outcome,
+    options: &Options,
+) -> Result<i32> {
+    for diagnostic in &outcome.diagnostics {
+        report(file, diagnostic, options);
+    }
+    match outcome.src {
+        Some(new_src) if new_src != src => {
+            println!("Updating __all__ statement in {}", display_path(file));
+            write_source(file, &new_src, encoding)?;
+            Ok(1)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// [`apply`] for `--porcelain`, printing a `<status>\t<path>\t<added>\t<removed>` line for
+/// `file` whether it changes or not, the names added and removed separated by commas.
+///
+/// The format of the line must not change, scripts parse it.
+fn apply_porcelain(
+    file: &Path,
+    src: &str,
+    encoding: Encoding,
+    outcome: Outcome,
+    options: &Options,
+) -> Result<i32> {
+    let change = FixOutcome::new(src, Some(file), outcome)?;
+    for diagnostic in &change.diagnostics {
+        report(file, diagnostic, options);
+    }
+    let (status, added, removed) = if change.changed {
+        write_source(file, &change.src, encoding)?;
+        ("updated", change.added.join(","), change.removed.join(","))
+    } else {
+        ("unchanged", String::new(), String::new())
+    };
+    println!("{status}\t{}\t{added}\t{removed}", display_path(file));
+    Ok(i32::from(status == "updated"))
+}
+
+/// Write or delete the modules `__all__` is generated into, returning the exit code.
+///
+/// With `porcelain`, the changes are printed as [`apply_porcelain`] does.
+fn write_exports(exports: &[ExportsModule], porcelain: bool) -> Result<i32> {
+    for change in exports {
+        let path = display_path(&change.path);
+        match &change.src {
+            Some(src) => {
+                if porcelain {
+                    println!("updated\t{path}\t\t");
+                } else {
+                    println!("Updating {path}");
+                }
+                write_source(&change.path, src, Encoding::Utf8)?;
+            }
+            None => {
+                if porcelain {
+                    println!("removed\t{path}\t\t");
+                } else {
+                    println!("Removing {path}");
+                }
+                std::fs::remove_file(&change.path)?;
+            }
+        }
+    }
+    Ok(i32::from(!exports.is_empty()))
+}
+
+/// Write the source `outcome` fixes `file` into to `output` instead, stdout for `-`, returning
+/// the exit code. The source is written even when unchanged.
+fn write_output(
+    file: &Path,
+    output: &Path,
+    src: &str,
+    encoding: Encoding,
+    outcome: Outcome,
+    options: &Options,
+) -> Result<i32> {
+    for diagnostic in &outcome.diagnostics {
+        report(file, diagnostic, options);
+    }
+    let new_src = outcome.src.as_deref().unwrap_or(src);
+    if output == Path::new("-") {
+        std::io::stdout()
+            .lock()
+            .write_all(&encoding.encode(new_src)?)?;
+    } else {
+        if let Some(dir) = output.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        write_source(output, new_src, encoding)?;
+    }
+    Ok(i32::from(new_src != src))
+}
+
+/// Print the change `outcome` makes to `file` as a patch `git apply` takes instead of writing
+/// it, returning the exit code.
+fn print_patch(
+    file: &Path,
+    src: &str,
+    encoding: Encoding,
+    outcome: Outcome,
+    options: &Options,
+) -> Result<i32> {
+    for diagnostic in &outcome.diagnostics {
+        report(file, diagnostic, options);
+    }
+    let Some(new_src) = outcome.src else {
+        return Ok(0);
+    };
+    let file_name = PathBuf::from(display_path(file));
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| file_name.strip_prefix(dir).ok())
+        .unwrap_or(&file_name);
+    // `git apply` rejects `a/./pkg/__init__.py`
+    let name = relative
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    // the `__init__.py` of a namespace package is created
+    let old_name = if file.exists() {
+        format!("a/{name}")
+    } else {
+        String::from("/dev/null")
+    };
+    // the patch has to hold the bytes of the file, its byte order mark included
+    let (old, new) = match encoding {
+        Encoding::Utf8Sig => (format!("\u{feff}{src}"), format!("\u{feff}{new_src}")),
+        _ => (src.to_string(), new_src),
+    };
+    let Some(diff) = unified_diff(&old, &new, &old_name, &format!("b/{name}")) else {
+        return Ok(0);
+    };
+    let bytes = match encoding {
+        Encoding::Latin1 => encoding.encode(&diff)?,
+        _ => diff.into_bytes(),
+    };
+    std::io::stdout().lock().write_all(&bytes)?;
+    Ok(1)
+}
+
+/// Print the line binding each name `change` adds to `__all__` of `file`, and the line leaving
+/// out or the reason for dropping each name it removes. Names that can't be explained are
+/// printed alone.
+fn show_source(file: &Path, src: &str, change: &FixOutcome, options: &Options) {
+    let signed = change
+        .added
+        .iter()
+        .map(|name| ("+", name))
+        .chain(change.removed.iter().map(|name| ("-", name)))
+        .collect::<Vec<_>>();
+    // the lines of a notebook's code cells aren't lines of the file
+    let explanations = if is_notebook(file) {
+        Vec::new()
+    } else {
+        let names = signed.iter().map(|(_, name)| name.as_str());
+        explain_names(src, Some(file), names, options).unwrap_or_else(|err| {
+            eprintln!(
+                "Failed to explain the names of {}: {err:#}",
+                display_path(file)
+            );
+            Vec::new()
+        })
+    };
+    for (index, (sign, name)) in signed.into_iter().enumerate() {
+        match explanations.get(index) {
+            Some(Explanation {
+                line: Some(line),
+                source: Some(source),
+                ..
+            }) => println!("  {sign} {name}: {}:{line}: {source}", display_path(file)),
+            Some(explanation) => println!("  {sign} {name}: {}", explanation.reason),
+            None => println!("  {sign} {name}"),
+        }
+    }
+}
+
+/// Print the names fixing `file`, the `change`, would add to and remove from `__all__` to
+/// stderr, for `--check --verbose`.
+fn report_drift(file: &Path, change: &FixOutcome) {
+    if change.added.is_empty() && change.removed.is_empty() {
+        return;
+    }
+    eprintln!("{}: `__all__` would change:", display_path(file));
+    for name in &change.added {
+        eprintln!("  + {name}");
+    }
+    for name in &change.removed {
+        eprintln!("  - {name}");
+    }
+}
+
+/// Print the diff of the change `outcome` makes to `file` and ask whether to write it, for
+/// `--confirm`. Returns `false` once asked to quit, `outcome` then changing nothing, and stops
+/// `confirm`ing once asked to write every change.
+fn confirm_change(
+    file: &Path,
+    src: &str,
+    outcome: &mut Outcome,
+    confirm: &mut bool,
+) -> Result<bool> {
+    let name = display_path(file);
+    let Some(diff) = outcome
+        .src
+        .as_deref()
+        .and_then(|new_src| unified_diff(src, new_src, &name, &name))
+    else {
+        return Ok(true);
+    };
+    print!("{diff}");
+    loop {
+        match ask(&format!("Write {name}? [y]es, [n]o, [a]ll, [q]uit:"))?.as_deref() {
+            Some("y") => return Ok(true),
+            Some("n") => {
+                outcome.src = None;
+                return Ok(true);
+            }
+            Some("a") => {
+                *confirm = false;
+                return Ok(true);
+            }
+            Some("q") | None => {
+                outcome.src = None;
+                return Ok(false);
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Show the change `outcome` makes to `file` and ask whether to make it, all of it or only
+/// some of its names, for `--interactive`. Returns `false` once asked to quit, `outcome` then
+/// changing nothing.
+fn review(file: &Path, src: &str, outcome: &mut Outcome, options: &Options) -> Result<bool> {
+    if outcome
+        .src
+        .as_deref()
+        .map_or(true, |new_src| new_src == src)
+    {
+        return Ok(true);
+    }
+    let change = FixOutcome::new(src, Some(file), outcome.clone())?;
+    let name = display_path(file);
+    if let Some(diff) = unified_diff(src, &change.src, &name, &name) {
+        print!("{diff}");
+    }
+    let per_name = !change.added.is_empty() || !change.removed.is_empty();
+    // the names of a notebook's block can't be chosen, it is taken or left whole
+    let editable = per_name && !is_notebook(file);
+    let prompt = if editable {
+        "Apply? [y]es, [n]o, [e]dit per name, [q]uit:"
+    } else {
+        "Apply? [y]es, [n]o, [q]uit:"
+    };
+    loop {
+        match ask(prompt)?.as_deref() {
+            Some("y") => return Ok(true),
+            Some("n") => {
+                outcome.src = None;
+                return Ok(true);
+            }
+            Some("e") if editable => {
+                let mut reviewed = Reviewed::default();
+                for name in &change.added {
+                    if !confirm(&format!("Add `{name}`?"))? {
+                        reviewed.kept_out.push(name.clone());
+                    }
+                }
+                for name in &change.removed {
+                    if !confirm(&format!("Remove `{name}`?"))? {
+                        reviewed.kept.push(name.clone());
+                    }
+                }
+                outcome.src = do_it_allways_with(&reviewed, src, Some(file), options)?.src;
+                return Ok(true);
+            }
+            Some("q") | None => {
+                outcome.src = None;
+                return Ok(false);
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Print `prompt` to stderr and read the first letter of the answer, lowercase, `None` at the
+/// end of stdin.
+fn ask(prompt: &str) -> Result<Option<String>> {
+    eprint!("{prompt} ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(
+        answer
+            .trim()
+            .chars()
+            .take(1)
+            .flat_map(char::to_lowercase)
+            .collect(),
+    ))
+}
+
+/// Ask a yes or no question until answered, no at the end of stdin.
+fn confirm(question: &str) -> Result<bool> {
+    loop {
+        match ask(&format!("{question} [y/n]"))?.as_deref() {
+            Some("y") => return Ok(true),
+            Some("n") | None => return Ok(false),
+            Some(_) => {}
+        }
+    }
+}
+
+/// The public names of a module with the changes to its `__all__` declined in `--interactive`
+/// review undone.
+#[derive(Default)]
+struct Reviewed {
+    /// New names left out of `__all__`.
+    kept_out: Vec<String>,
+    /// Names `__all__` keeps although the module no longer exports them.
+    kept: Vec<String>,
+}
+
+impl NameProvider for Reviewed {
+    fn public_names(
+        &self,
+        src: &str,
+        path: Option<&Path>,
+        options: &Options,
+    ) -> Result<PublicNames> {
+        let mut public_names = SourceNames.public_names(src, path, options)?;
+        public_names
+            .names
+            .retain(|name| !self.kept_out.contains(name));
+        public_names.names.extend(self.kept.iter().cloned());
+        // sorted ignoring case, the way allways sorts them
+        public_names
+            .names
+            .sort_by_key(|name| (name.to_lowercase(), name.clone()));
+        Ok(public_names)
+    }
+}
+
+/// Print `diagnostic` to stderr and return its severity.
+fn report(file: &Path, diagnostic: &Diagnostic, options: &Options) -> Severity {
+    let severity = diagnostic
+        .rule
+        .and_then(|rule| options.rules.severity(rule))
+        .unwrap_or(Severity::Warning);
+    let code = diagnostic
+        .rule
+        .map(|rule| format!("[{rule}]"))
+        .unwrap_or_default();
+    eprintln!(
+        "{}:{}: {severity}{code}: {}",
+        display_path(file),
+        diagnostic.line,
+        diagnostic.message
+    );
+    severity
+}
+
+/// Files given on the command line, and the `__init__.py` of every package in directories.
+///
+/// Namespace packages are only included with `--create-init`.
+fn expand_paths(paths: &[PathBuf], file_options: &mut FileOptions) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let options = file_options.get(path)?;
+        // installed packages are never meant to be rewritten
+        if !options.include_site_packages && in_environment(path) {
+            eprintln!(
+                "Skipping {}, it is inside a virtual environment or site-packages, see \
+                 --include-site-packages",
+                display_path(path)
+            );
+            continue;
+        }
+        if !path.is_dir() {
+            // pre-commit passes every file explicitly, excluded ones too
+            if !options.force_exclude || !options.is_excluded(path) {
+                files.push(long_path(path));
+            }
+            continue;
+        }
+        for package in walk_packages(path, file_options)? {
+            let init = package.init();
+            let options = file_options.get(&init)?;
+            if (!package.namespace || options.create_init) && !options.is_excluded(&init) {
+                files.push(init);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The packages below the directory `path`, whether a directory is walked decided by the
+/// options of the directory it is in, nested configs included.
+fn walk_packages(path: &Path, file_options: &mut FileOptions) -> Result<Vec<Package>> {
+    packages_where(&long_path(path), &mut |dir| {
+        Ok(is_excluded_dir(dir, file_options.get(dir)?))
+    })
+}
+
+/// `path` in the form Windows accepts beyond 260 characters: absolute, normalized and prefixed
+/// with `\\?\`. Files are read and written, and directories walked, through it.
+#[cfg(windows)]
+fn long_path(path: &Path) -> PathBuf {
+    // `\\?\` turns off the normalization of `..` and `/`, `canonicalize` does it instead
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(not(windows))]
+fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// `path` as printed: with `/` separators whatever the platform, and relative to the current
+/// directory again when [`long_path`] made it absolute.
+fn display_path(path: &Path) -> String {
+    let display = path.display().to_string();
+    let Some(verbatim) = display.strip_prefix(r"\\?\") else {
+        return display.replace(std::path::MAIN_SEPARATOR, "/");
+    };
+    let absolute = match verbatim.strip_prefix(r"UNC\") {
+        Some(share) => format!(r"\\{share}"),
+        None => verbatim.to_string(),
+    };
+    let absolute = Path::new(&absolute);
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| absolute.strip_prefix(dir).ok())
+        .unwrap_or(absolute)
+        .display()
+        .to_string()
+        .replace(std::path::MAIN_SEPARATOR, "/")
 }
 
 fn check_files(paths: &[PathBuf]) -> Result<()> {
@@ -38,8 +1076,634 @@ fn check_files(paths: &[PathBuf]) -> Result<()> {
 /// Automatically update `__all__` statements in python libraries.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
-    /// Any number of python files.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Python files or notebooks, or directories to update the `__init__.py` of every package in.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Report lint diagnostics instead of updating files, exiting non-zero on errors.
+    #[arg(long)]
+    pub check: bool,
+
+    /// With `--check`, list the names each out-of-date file would gain and lose.
+    #[arg(short, long, requires = "check")]
+    pub verbose: bool,
+
+    /// Review the change to each file, accepting, skipping or editing it name by name.
+    #[arg(long, conflicts_with = "check")]
+    pub interactive: bool,
+
+    /// Print the diff of each file and ask before writing it.
+    #[arg(long, conflicts_with_all = ["check", "interactive"])]
+    pub confirm: bool,
+
+    /// Print the line binding each name added to or removed from `__all__`.
+    #[arg(long)]
+    pub show_source: bool,
+
+    /// Write the result for the single file given to this path, or stdout for `-`, leaving the
+    /// file as is.
+    #[arg(short, long, value_name = "PATH", conflicts_with_all = ["check", "output_format"])]
+    pub output: Option<PathBuf>,
+
+    /// Print a patch of every change to stdout instead of writing files.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        conflicts_with_all = ["check", "interactive", "confirm", "show_source"]
+    )]
+    pub output_format: OutputFormat,
+
+    /// Print the time spent in each phase and the N slowest files (10 by default) to stderr.
+    #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "10")]
+    pub stats: Option<usize>,
+
+    /// Print one `<status>\t<path>\t<added>\t<removed>` line per file, in a format that stays
+    /// the same across versions, for scripts.
+    #[arg(
+        long,
+        conflicts_with_all = ["check", "interactive", "confirm", "show_source", "output", "output_format"]
+    )]
+    pub porcelain: bool,
+
+    /// Where to remember the files found up to date, skipped by the next runs [default: the
+    /// `allways` directory of `XDG_CACHE_HOME` or of the platform cache directory]
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Neither skip the files found up to date by previous runs nor remember them.
+    #[arg(long, conflicts_with = "cache_dir")]
+    pub no_cache: bool,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum OutputFormat {
+    /// Write the changes to the files.
+    Text,
+    /// Print a patch for `git apply`, writing nothing.
+    Patch,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Export the public names of every module in a package from its `__init__.py`.
+    Package(PackageArgs),
+    /// Print the public names of every module in a package as JSON.
+    Manifest(ManifestArgs),
+    /// Compare the public names of a package with a manifest, exiting non-zero on removals.
+    ApiDiff(ApiDiffArgs),
+    /// Print the public names added and removed between two versions of a python file.
+    Diff(DiffArgs),
+    /// Explain why a name is or isn't exported by a python file.
+    Explain(ExplainArgs),
+    /// Report how many public names the hand-written `__all__` of each module lists, and which
+    /// it misses, exiting non-zero when any are.
+    Coverage(CoverageArgs),
+    /// Normalize malformed allways markers, e.g. a missing end marker, and update the block.
+    Repair(RepairArgs),
+    /// Insert empty allways markers where new blocks go, without generating any names.
+    AddMarkers(AddMarkersArgs),
+    /// Inspect or clear the cache of files found up to date.
+    Cache(CacheArgs),
+    /// Validate or inspect the configuration.
+    Config(ConfigArgs),
+    /// Serve the Language Server Protocol over stdin and stdout.
+    Lsp(LspArgs),
+    /// Serve fix and check requests, one JSON object per line of stdin, caching results.
+    Daemon(DaemonArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PackageArgs {
+    /// The package directory.
+    pub dir: PathBuf,
+
+    /// Export only the names `__init__.py` imports from the package's modules.
+    #[arg(long)]
+    pub imported_only: bool,
+
+    /// Import every exported name into `__init__.py` within a managed region.
+    #[arg(long, conflicts_with = "imported_only")]
+    pub reexports: bool,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ManifestArgs {
+    /// The package directory.
+    pub package: PathBuf,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ApiDiffArgs {
+    /// The package directory.
+    pub package: PathBuf,
+
+    /// A manifest previously written by `allways manifest`.
+    #[arg(long, value_name = "MANIFEST")]
+    pub against: PathBuf,
+
+    /// Print one line per change, or a changelog section grouped by module.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Text,
+    Markdown,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// The old version of the file.
+    pub old: PathBuf,
+
+    /// The new version of the file, named in the output.
+    pub new: PathBuf,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ExplainArgs {
+    /// The python file.
+    pub file: PathBuf,
+
+    /// The name to explain.
+    pub name: String,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CoverageArgs {
+    /// Python files, or directories to audit every module of every package in.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RepairArgs {
+    /// Python files, or directories to repair the `__init__.py` of every package in.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AddMarkersArgs {
+    /// Python files, or directories to add markers to the `__init__.py` of every package in.
     #[arg(required = true)]
     pub paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub action: CacheAction,
+
+    /// The cache directory, as passed to `--cache-dir`.
+    #[arg(long, value_name = "DIR", global = true)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum CacheAction {
+    /// Print where the cache is and how many files it holds.
+    Info,
+    /// Delete the cache files of every version of allways.
+    Clear,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Validate config files without processing any python file, exiting non-zero on problems.
+    Check(ConfigCheckArgs),
+    /// Print the options that apply to a python file, after config discovery, nested configs,
+    /// the profile, the flags and `.editorconfig`.
+    Show(ConfigShowArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConfigCheckArgs {
+    /// `allways.toml`, `pyproject.toml` or `setup.cfg` files, by default those of the config
+    /// the current directory uses.
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConfigShowArgs {
+    /// The python file.
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LspArgs {
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DaemonArgs {
+    #[command(flatten)]
+    pub options: OptionArgs,
+}
+
+/// Flags overriding the `[tool.allways]` configuration.
+#[derive(clap::Args, Debug)]
+pub struct OptionArgs {
+    /// Enable only these rules, e.g. `ALW001,ALW002`.
+    #[arg(long, value_delimiter = ',', value_name = "RULES")]
+    pub select: Vec<Rule>,
+
+    /// Disable these rules.
+    #[arg(long, value_delimiter = ',', value_name = "RULES")]
+    pub ignore: Vec<Rule>,
+
+    /// Report these rules as warnings, which don't fail `--check`.
+    #[arg(long, value_delimiter = ',', value_name = "RULES")]
+    pub warn: Vec<Rule>,
+
+    /// Do not export names bound by `type X = ...` statements.
+    #[arg(long)]
+    pub no_type_aliases: bool,
+
+    /// Skip top-level statements that fail to parse, reporting them as warnings.
+    #[arg(long)]
+    pub lenient: bool,
+
+    /// Export names only bound inside `if TYPE_CHECKING:` blocks.
+    #[arg(long)]
+    pub type_checking: bool,
+
+    /// Minimum supported python version (e.g. 3.11), used to skip dead `sys.version_info` branches
+    /// and reject syntax it doesn't accept.
+    #[arg(long)]
+    pub target_version: Option<PythonVersion>,
+
+    /// Extend `__all__` with the `__all__` of star-imported modules that can't be found on disk.
+    #[arg(long)]
+    pub star_import_fallback: bool,
+
+    /// Export names functions declare `global` and then bind.
+    #[arg(long)]
+    pub global_declarations: bool,
+
+    /// Warn about public names only bound on some paths through an `if` or `try` statement.
+    #[arg(long)]
+    pub warn_conditional: bool,
+
+    /// Decorator marking functions and classes for export, e.g. `public` for `@public`.
+    #[arg(long = "export-decorator", value_name = "NAME")]
+    pub export_decorators: Vec<String>,
+
+    /// Export only functions and classes marked with an `--export-decorator`.
+    #[arg(long)]
+    pub decorated_only: bool,
+
+    /// Create a missing `__init__.py` for PEP 420 namespace packages instead of skipping them.
+    #[arg(long)]
+    pub create_init: bool,
+
+    /// Don't add a newline to the end of files without one.
+    #[arg(long)]
+    pub no_final_newline: bool,
+
+    /// Blank lines before a new allways block, by default as many as the file uses.
+    #[arg(long, value_name = "N")]
+    pub blank_lines: Option<usize>,
+
+    /// Ignore `.editorconfig` files.
+    #[arg(long)]
+    pub no_editorconfig: bool,
+
+    /// Lay `__all__` out one name per line (`allways`) or the way black would (`black`).
+    #[arg(long, value_name = "STYLE")]
+    pub block_style: Option<BlockStyle>,
+
+    /// The longest line `--block-style black` puts `__all__` on.
+    #[arg(long, value_name = "N")]
+    pub line_length: Option<usize>,
+
+    /// Insert new blocks at the `end` of modules or `after-imports`.
+    #[arg(long)]
+    pub placement: Option<Placement>,
+
+    /// Generate `__all__` of packages into this sibling module of `__init__.py`, which only
+    /// imports it, e.g. `_allways_exports`.
+    #[arg(long, value_name = "MODULE")]
+    pub exports_module: Option<ModuleName>,
+
+    /// Export names only bound on some platforms, e.g. under `if sys.platform == "win32":`, in
+    /// matching `if` statements of the allways block.
+    #[arg(long)]
+    pub platform_conditional: bool,
+
+    /// Embed a hash of the allways block in it, `--check` reports blocks edited by hand since.
+    #[arg(long)]
+    pub block_hash: bool,
+
+    /// The layout of the allways block, with `{start_marker}`, `{header}`, `{names}` and
+    /// `{end_marker}` lines standing for its parts.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub block_template: Option<BlockTemplate>,
+
+    /// Comment lines starting the allways block, e.g. `This list is generated by allways`.
+    #[arg(long, value_name = "TEXT")]
+    pub block_header: Option<BlockHeader>,
+
+    /// Keep the order of the names of existing blocks, appending new names instead of sorting.
+    #[arg(long)]
+    pub no_sort: bool,
+
+    /// Skip files and directories matching these glob patterns while looking for packages.
+    #[arg(long, value_delimiter = ',', value_name = "PATTERNS")]
+    pub exclude: Vec<String>,
+
+    /// Apply the exclude patterns to files passed explicitly too, e.g. by pre-commit.
+    #[arg(long)]
+    pub force_exclude: bool,
+
+    /// Walk hidden directories, virtual environments, caches and build output too.
+    #[arg(long)]
+    pub no_default_exclude: bool,
+
+    /// Leave files without allways markers untouched instead of adding a block, reporting them as ALW001.
+    #[arg(long)]
+    pub require_markers: bool,
+
+    /// Walk and modify virtual environments and site-packages directories too.
+    #[arg(long)]
+    pub include_site_packages: bool,
+
+    /// Apply the settings of this profile of the config over the others.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Replace a hand-written `__all__` list by an allways block, keeping its entries that aren't string literals.
+    #[arg(long)]
+    pub adopt: bool,
+
+    /// Fix the problems of lint rules with an automatic fix, e.g. ALW010, in the files allways updates.
+    #[arg(long)]
+    pub fix_lints: bool,
+
+    /// Keep the markers of the allways block of a module without public names instead of removing the
+    /// block
+    #[arg(long)]
+    pub keep_empty_block: bool,
+}
+
+impl OptionArgs {
+    /// Resolve options from the config of the current directory, overridden by command line
+    /// flags.
+    fn options(&self) -> Result<Options> {
+        let current_dir = std::env::current_dir()?;
+        match Config::find(&current_dir)? {
+            Some((root, config)) => self.with_configs(&root, [&config]),
+            None => self.with_configs(&current_dir, []),
+        }
+    }
+
+    /// Resolve options from `configs`, each overriding the ones before it, and command line
+    /// flags overriding them all, for the project in `root`.
+    fn with_configs<'c>(
+        &self,
+        root: &Path,
+        configs: impl IntoIterator<Item = &'c Config>,
+    ) -> Result<Options> {
+        let mut options = Options::default();
+        options.root = Some(root.to_path_buf());
+        let mut profiles = Vec::new();
+        let mut profile_found = false;
+        for config in configs {
+            config.apply(&mut options);
+            let Some(name) = &self.profile else {
+                continue;
+            };
+            if let Some(profile) = config.profile(name) {
+                profile.apply(&mut options);
+                profile_found = true;
+            }
+            profiles.extend(config.profile_names().map(String::from));
+        }
+        if let (Some(name), false) = (&self.profile, profile_found) {
+            let defined = if profiles.is_empty() {
+                String::from("no profile is defined")
+            } else {
+                format!("the profiles are: {}", profiles.join(", "))
+            };
+            Err(anyhow!("Unknown profile {name:?}, {defined}"))?;
+        }
+        if self.no_type_aliases {
+            options.type_aliases = false;
+        }
+        if self.lenient {
+            options.lenient = true;
+        }
+        if self.type_checking {
+            options.type_checking = true;
+        }
+        if let Some(target_version) = self.target_version {
+            options.target_version = Some(target_version);
+        }
+        if self.star_import_fallback {
+            options.star_import_fallback = true;
+        }
+        if self.global_declarations {
+            options.global_declarations = true;
+        }
+        if self.warn_conditional {
+            options.warn_conditional = true;
+        }
+        if !self.export_decorators.is_empty() {
+            options.export_decorators = self.export_decorators.clone();
+        }
+        if self.decorated_only {
+            options.decorated_only = true;
+        }
+        if !self.select.is_empty() {
+            options.rules.select(&self.select);
+        }
+        options.rules.ignore(&self.ignore);
+        for &rule in &self.warn {
+            options.rules.set_severity(rule, Severity::Warning);
+        }
+        if self.create_init {
+            options.create_init = true;
+        }
+        if self.no_final_newline {
+            options.final_newline = false;
+        }
+        if self.no_editorconfig {
+            options.editorconfig = false;
+        }
+        if let Some(block_style) = self.block_style {
+            options.block_style = block_style;
+        }
+        if let Some(line_length) = self.line_length {
+            options.line_length = line_length;
+        }
+        if let Some(placement) = self.placement {
+            options.placement = placement;
+        }
+        if let Some(exports_module) = &self.exports_module {
+            options.exports_module = Some(exports_module.clone());
+        }
+        if self.platform_conditional {
+            options.platform_conditional = true;
+        }
+        if self.block_hash {
+            options.block_hash = true;
+        }
+        if let Some(block_template) = &self.block_template {
+            options.block_template = Some(block_template.clone());
+        }
+        if let Some(block_header) = &self.block_header {
+            options.block_header = Some(block_header.clone());
+        }
+        if self.no_sort {
+            options.sort = false;
+        }
+        if !self.exclude.is_empty() {
+            options.exclude = self.exclude.clone();
+        }
+        if self.force_exclude {
+            options.force_exclude = true;
+        }
+        if self.no_default_exclude {
+            options.default_exclude = false;
+        }
+        if self.require_markers {
+            options.require_markers = true;
+        }
+        if self.include_site_packages {
+            options.include_site_packages = true;
+        }
+        if self.adopt {
+            options.adopt = true;
+        }
+        if self.fix_lints {
+            options.fix_lints = true;
+        }
+        if self.keep_empty_block {
+            options.keep_empty_block = true;
+        }
+        if let Some(blank_lines) = self.blank_lines {
+            options.blank_lines = Some(blank_lines);
+        }
+        Ok(options)
+    }
+}
+
+/// The options of each file: the root config, the closest to the current directory,
+/// overridden by the configs of the directories between it and the file, and command line
+/// flags overriding them all.
+struct FileOptions<'a> {
+    args: &'a OptionArgs,
+    /// The options of files without configs of their own directories.
+    base: Options,
+    /// The directory of the root config, the current directory without one.
+    root: PathBuf,
+    root_config: Option<Config>,
+    /// The options of the files of each directory, `None` for `base`.
+    dirs: HashMap<PathBuf, Option<Options>>,
+}
+
+impl<'a> FileOptions<'a> {
+    fn new(args: &'a OptionArgs) -> Result<Self> {
+        let current_dir = std::env::current_dir()?;
+        let (root, root_config) = match Config::find(&current_dir)? {
+            Some((root, config)) => (root, Some(config)),
+            None => (current_dir, None),
+        };
+        // files are compared to it as `long_path` makes them
+        let root = long_path(&root);
+        Ok(Self {
+            args,
+            base: args.with_configs(&root, root_config.iter())?,
+            root,
+            root_config,
+            dirs: HashMap::new(),
+        })
+    }
+
+    /// The options of `file`.
+    fn get(&mut self, file: &Path) -> Result<&Options> {
+        let Some(dir) = file_dir(file)? else {
+            return Ok(&self.base);
+        };
+        if !self.dirs.contains_key(&dir) {
+            let nested = Config::nested(&self.root, &dir)?;
+            let options = if nested.is_empty() {
+                None
+            } else {
+                let nested = nested.iter().map(|(_, config)| config);
+                Some(
+                    self.args
+                        .with_configs(&self.root, self.root_config.iter().chain(nested))?,
+                )
+            };
+            self.dirs.insert(dir.clone(), options);
+        }
+        Ok(self.dirs[&dir].as_ref().unwrap_or(&self.base))
+    }
+
+    /// The directories of the configs that apply to `file`, outermost first.
+    fn config_dirs(&self, file: &Path) -> Result<Vec<PathBuf>> {
+        let nested = match file_dir(file)? {
+            Some(dir) => Config::nested(&self.root, &dir)?,
+            None => Vec::new(),
+        };
+        let root = self.root_config.as_ref().map(|_| self.root.clone());
+        Ok(root
+            .into_iter()
+            .chain(nested.into_iter().map(|(dir, _)| dir))
+            .collect())
+    }
+}
+
+/// The absolute path of the directory of `file`, `None` when it has none.
+fn file_dir(file: &Path) -> Result<Option<PathBuf>> {
+    Ok(match file.parent() {
+        Some(dir) if dir.is_absolute() => Some(dir.to_path_buf()),
+        Some(dir) => Some(std::env::current_dir()?.join(dir)),
+        None => None,
+    })
 }
@@ -0,0 +1,188 @@
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::add_all::case_insensitive_cmp;
+use crate::diagnostic::line_number;
+use crate::discover::is_excluded_dir;
+use crate::encoding::read_source;
+use crate::name_parser::Kind;
+use crate::name_parser::NameParser;
+use crate::options::Options;
+use crate::resolve::module_names;
+
+const INIT: &str = "__init__";
+
+/// The public API of a package: every module in it and the names it exports.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+    pub modules: Vec<ModuleManifest>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModuleManifest {
+    /// Dotted module name, e.g. `pkg.sub._foo`.
+    pub module: String,
+    /// Path of the module relative to the directory containing the package, `/`-separated.
+    pub path: String,
+    pub names: Vec<ExportedName>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportedName {
+    pub name: String,
+    /// `None` for names the module doesn't bind itself, e.g. star-imported ones.
+    pub kind: Option<Kind>,
+    /// 1-based line of the statement binding the name.
+    pub line: Option<usize>,
+}
+
+/// The manifest of the package `dir` and every package below it.
+///
+/// Modules are sorted by name and so are their names, ignoring case.
+pub fn manifest(dir: &Path, options: &Options) -> Result<Manifest> {
+    let dir = package_dir(dir)?;
+    let root = dir.parent().unwrap_or(&dir);
+    let mut paths = Vec::new();
+    collect_modules(&dir, &mut paths, options)?;
+    let mut modules = paths
+        .iter()
+        .map(|path| module_manifest(root, path, options))
+        .collect::<Result<Vec<_>>>()?;
+    modules.sort_by(|left, right| left.module.cmp(&right.module));
+    Ok(Manifest { modules })
+}
+
+/// `dir` made absolute, without `.` and `..` components, so that its last one names the package,
+/// e.g. that of the current directory for `.`.
+fn package_dir(dir: &Path) -> Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in std::env::current_dir()?.join(dir).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    Ok(normalized)
+}
+
+fn module_manifest(root: &Path, path: &Path, options: &Options) -> Result<ModuleManifest> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut parts = relative
+        .with_extension("")
+        .iter()
+        .map(|part| part.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    if parts.len() > 1 && parts.last().is_some_and(|part| part == INIT) {
+        parts.pop();
+    }
+    Ok(ModuleManifest {
+        module: parts.join("."),
+        path: relative
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        names: exported_names(path, options)?,
+    })
+}
+
+/// The names the module at `path` exports, sorted ignoring case.
+pub fn exported_names(path: &Path, options: &Options) -> Result<Vec<ExportedName>> {
+    let (src, _) = read_source(path)?;
+    let definitions = NameParser::parse(&src, options)?.take_definitions();
+    let mut names = module_names(path, options)?;
+    names.sort_by(case_insensitive_cmp);
+    names.dedup();
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let definition = definitions.get(&name);
+            ExportedName {
+                kind: definition.map(|definition| definition.kind),
+                line: definition.map(|definition| line_number(&src, definition.offset)),
+                name,
+            }
+        })
+        .collect())
+}
+
+/// The python files in `dir` and the directories below it `options` doesn't exclude.
+fn collect_modules(dir: &Path, modules: &mut Vec<PathBuf>, options: &Options) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if !is_excluded_dir(&path, options) {
+                collect_modules(&path, modules, options)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == "py") {
+            modules.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_manifest() {
+        let root = tempfile::tempdir().unwrap();
+        let pkg = root.path().join("pkg");
+        std::fs::create_dir_all(pkg.join("sub")).unwrap();
+        std::fs::write(
+            pkg.join("__init__.py"),
+            "from ._foo import foo\n__all__ = ['foo', 'missing']\n",
+        )
+        .unwrap();
+        std::fs::write(pkg.join("_foo.py"), "def foo(): ...\n\nclass Bar: ...\n").unwrap();
+        std::fs::write(pkg.join("sub").join("__init__.py"), "VALUE = 1\n").unwrap();
+
+        let manifest = manifest(&pkg, &Options::default()).unwrap();
+        let name = |name: &str, kind, line| ExportedName {
+            name: String::from(name),
+            kind,
+            line,
+        };
+        assert_eq!(
+            manifest.modules,
+            vec![
+                ModuleManifest {
+                    module: String::from("pkg"),
+                    path: String::from("pkg/__init__.py"),
+                    names: vec![
+                        name("foo", Some(Kind::Import), Some(1)),
+                        name("missing", None, None),
+                    ],
+                },
+                ModuleManifest {
+                    module: String::from("pkg._foo"),
+                    path: String::from("pkg/_foo.py"),
+                    names: vec![
+                        name("Bar", Some(Kind::Class), Some(3)),
+                        name("foo", Some(Kind::Function), Some(1)),
+                    ],
+                },
+                ModuleManifest {
+                    module: String::from("pkg.sub"),
+                    path: String::from("pkg/sub/__init__.py"),
+                    names: vec![name("VALUE", Some(Kind::Variable), Some(1))],
+                },
+            ]
+        );
+
+        // `.` and `..` stand for the directory they lead to
+        for dir in [pkg.join("."), pkg.join("sub").join("..")] {
+            assert_eq!(manifest(&dir, &Options::default()).unwrap(), manifest);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use rustpython_parser::ast;
+use rustpython_parser::ast::CmpOp;
+use rustpython_parser::ast::Expr;
+use rustpython_parser::ast::Stmt;
+
+/// Names a module level `__getattr__` (PEP 562) provides lazily.
+///
+/// Recognizes tests of the requested name against string literals or collections of them,
+/// e.g. `if name in _LAZY:`, `if name in ("a", "b"):` or `if name == "a":`, where `_LAZY`
+/// may be any module level literal from `collections`. `None` when no such test is found.
+pub fn lazy_names(
+    getattr: &ast::StmtFunctionDef,
+    collections: &HashMap<String, Vec<String>>,
+) -> Option<Vec<String>> {
+    let args = &getattr.args;
+    let param = args.posonlyargs.iter().chain(&args.args).next()?;
+    let mut names = Vec::new();
+    collect_tested_names(
+        &getattr.body,
+        param.def.arg.as_str(),
+        collections,
+        &mut names,
+    );
+    (!names.is_empty()).then_some(names)
+}
+
+/// The strings of a list, tuple or set literal, or the keys of a dict literal.
+pub fn string_collection(expr: &Expr) -> Option<Vec<String>> {
+    match expr {
+        Expr::List(ast::ExprList { elts, .. })
+        | Expr::Tuple(ast::ExprTuple { elts, .. })
+        | Expr::Set(ast::ExprSet { elts, .. }) => elts.iter().map(string).collect(),
+        Expr::Dict(ast::ExprDict { keys, .. }) => {
+            keys.iter().map(|key| string(key.as_ref()?)).collect()
+        }
+        _ => None,
+    }
+}
+
+fn collect_tested_names(
+    body: &[Stmt],
+    param: &str,
+    collections: &HashMap<String, Vec<String>>,
+    names: &mut Vec<String>,
+) {
+    for statement in body {
+        match statement {
+            Stmt::If(ast::StmtIf {
+                test, body, orelse, ..
+            }) => {
+                names.extend(tested_names(test, param, collections));
+                collect_tested_names(body, param, collections, names);
+                collect_tested_names(orelse, param, collections, names);
+            }
+            Stmt::Try(ast::StmtTry {
+                body,
+                orelse,
+                finalbody,
+                ..
+            }) => {
+                collect_tested_names(body, param, collections, names);
+                collect_tested_names(orelse, param, collections, names);
+                collect_tested_names(finalbody, param, collections, names);
+            }
+            Stmt::With(ast::StmtWith { body, .. }) => {
+                collect_tested_names(body, param, collections, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn tested_names(
+    test: &Expr,
+    param: &str,
+    collections: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    match test {
+        Expr::Compare(ast::ExprCompare {
+            left,
+            ops,
+            comparators,
+            ..
+        }) => {
+            let ([op], [right]) = (ops.as_slice(), comparators.as_slice()) else {
+                return Vec::new();
+            };
+            let Expr::Name(ast::ExprName { id, .. }) = left.as_ref() else {
+                return Vec::new();
+            };
+            if id.as_str() != param {
+                return Vec::new();
+            }
+            match op {
+                CmpOp::In => collection(right, collections).unwrap_or_default(),
+                CmpOp::Eq => string(right).into_iter().collect(),
+                _ => Vec::new(),
+            }
+        }
+        Expr::BoolOp(ast::ExprBoolOp {
+            op: ast::BoolOp::Or,
+            values,
+            ..
+        }) => values
+            .iter()
+            .flat_map(|value| tested_names(value, param, collections))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A literal collection of strings, a module level one referred to by name, or its `.keys()`.
+fn collection(expr: &Expr, collections: &HashMap<String, Vec<String>>) -> Option<Vec<String>> {
+    match expr {
+        Expr::Name(ast::ExprName { id, .. }) => collections.get(id.as_str()).cloned(),
+        Expr::Call(ast::ExprCall { func, args, .. }) if args.is_empty() => match func.as_ref() {
+            Expr::Attribute(ast::ExprAttribute { value, attr, .. }) if attr.as_str() == "keys" => {
+                collection(value, collections)
+            }
+            _ => None,
+        },
+        _ => string_collection(expr),
+    }
+}
+
+fn string(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Constant(ast::ExprConstant {
+            value: ast::Constant::Str(value),
+            ..
+        }) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustpython_parser::ast::Suite;
+    use rustpython_parser::Parse;
+
+    fn lazy_names_of(src: &str) -> Option<Vec<String>> {
+        let statements = Suite::parse(src, "<test>").unwrap();
+        let mut collections = HashMap::new();
+        let mut getattr = None;
+        for statement in statements {
+            match statement {
+                Stmt::Assign(ast::StmtAssign { targets, value, .. }) => {
+                    if let [Expr::Name(ast::ExprName { id, .. })] = targets.as_slice() {
+                        collections.insert(id.to_string(), string_collection(&value).unwrap());
+                    }
+                }
+                Stmt::FunctionDef(def) => getattr = Some(def),
+                _ => {}
+            }
+        }
+        lazy_names(&getattr.unwrap(), &collections)
+    }
+
+    #[test]
+    fn lazy_dict() {
+        let src = "
+_LAZY = {'foo': '._foo', 'Bar': '._bar'}
+
+def __getattr__(name):
+    if name in _LAZY:
+        return importlib.import_module(_LAZY[name], __name__)
+    raise AttributeError(name)
+";
+        assert_eq!(
+            lazy_names_of(src),
+            Some(vec![String::from("foo"), String::from("Bar")])
+        );
+    }
+
+    #[test]
+    fn lazy_dict_keys() {
+        let src = "
+_LAZY = {'foo': '._foo'}
+
+def __getattr__(attr):
+    if attr in _LAZY.keys():
+        ...
+";
+        assert_eq!(lazy_names_of(src), Some(vec![String::from("foo")]));
+    }
+
+    #[test]
+    fn inline_literals() {
+        let src = "
+def __getattr__(name):
+    if name == 'baz' or name in ('qux', 'quux'):
+        ...
+    elif name in {'corge'}:
+        ...
+";
+        assert_eq!(
+            lazy_names_of(src),
+            Some(vec![
+                String::from("baz"),
+                String::from("qux"),
+                String::from("quux"),
+                String::from("corge"),
+            ])
+        );
+    }
+
+    #[test]
+    fn untrackable() {
+        let src = "
+def __getattr__(name):
+    return globals()[name.lower()]
+";
+        assert_eq!(lazy_names_of(src), None);
+    }
+}
@@ -1,182 +1,1176 @@
+use std::cell::Cell;
 use std::collections::hash_set::IntoIter;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::iter::Map;
+use std::ops::Range;
+use std::rc::Rc;
 use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
 
+use anyhow::anyhow;
 use anyhow::Error;
 use anyhow::Result;
 
-use rustpython_parser::ast::Expression;
-use rustpython_parser::ast::ExpressionType;
-use rustpython_parser::ast::ImportSymbol;
-use rustpython_parser::ast::Program;
-use rustpython_parser::ast::Statement;
-use rustpython_parser::ast::StatementType;
+use rustpython_parser::ast;
+use rustpython_parser::ast::Alias;
+use rustpython_parser::ast::ExceptHandler;
+use rustpython_parser::ast::Expr;
+use rustpython_parser::ast::Ranged;
+use rustpython_parser::ast::Stmt;
+use rustpython_parser::ast::Suite;
 use rustpython_parser::ast::WithItem;
-use rustpython_parser::parser::parse_program;
+use rustpython_parser::Parse;
+use serde::Deserialize;
+
+use crate::condition;
+use crate::diagnostic::line_number;
+use crate::diagnostic::Diagnostic;
+use crate::functional;
+use crate::module_getattr;
+use crate::options::Options;
+use crate::options::PythonVersion;
+use crate::recover::parse_recovering;
+use crate::syntax;
+use crate::walrus;
+
+/// A bound name, shared by every collection recording it rather than copied into each.
+type Name = Rc<str>;
 
 pub struct NameParser {
-    names: HashSet<String>,
+    names: HashSet<Name>,
+    /// Shared with the parsers of nested function bodies.
+    options: Rc<Options>,
+    diagnostics: Vec<Diagnostic>,
+    star_imports: Vec<StarImport>,
+    imports: Vec<ImportFrom>,
+    dunder_all: Option<Vec<String>>,
+    dunder_all_statements: Vec<DunderAllStatement>,
+    /// Literal collections of strings assigned to names, e.g. `_LAZY = {"foo": "._foo"}`.
+    collections: HashMap<String, Vec<String>>,
+    getattrs: Vec<ast::StmtFunctionDef>,
+    /// Every name bound so far, in order, to tell which names a branch binds.
+    bindings: Vec<Name>,
+    conditionals: HashMap<Name, Conditional>,
+    /// Functions and classes marked for export by one of `Options::export_decorators`.
+    decorated: HashSet<String>,
+    /// Warnings at byte offsets, turned into diagnostics once the whole source is parsed.
+    warnings: Vec<(usize, String)>,
+    /// Where each name was last bound.
+    definitions: HashMap<Name, Definition>,
+    /// The statement currently being added, recorded for every name it binds.
+    definition: Definition,
+    /// Names left out of `names` and the statement that left each out last.
+    skipped: HashMap<Name, Skipped>,
+    /// Names only bound on some platforms, only kept with `Options::platform_conditional`.
+    platform_names: HashMap<String, PlatformCondition>,
+    /// Star imports only run on some platforms, by byte offset.
+    platform_star_imports: HashMap<usize, PlatformCondition>,
+}
+
+/// Tests on the platform that all hold, each the byte range of an `if` test and whether it is
+/// negated, e.g. for the `else` branch.
+pub type PlatformCondition = Vec<(Range<usize>, bool)>;
+
+/// What kind of statement binds a name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    Function,
+    Class,
+    Variable,
+    TypeAlias,
+    Import,
+    /// Provided by a module level `__getattr__`.
+    Lazy,
+}
+
+/// The statement binding a name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Definition {
+    pub kind: Kind,
+    /// Byte offset of the statement.
+    pub offset: usize,
+    /// Byte offset just past the end of the statement.
+    pub end: usize,
+}
+
+impl Definition {
+    fn of(statement: &Stmt) -> Self {
+        let kind = match statement {
+            Stmt::FunctionDef(_) | Stmt::AsyncFunctionDef(_) => Kind::Function,
+            Stmt::ClassDef(_) => Kind::Class,
+            Stmt::TypeAlias(_) => Kind::TypeAlias,
+            Stmt::Import(_) | Stmt::ImportFrom(_) => Kind::Import,
+            _ => Kind::Variable,
+        };
+        Self {
+            kind,
+            offset: usize::from(statement.start()),
+            end: usize::from(statement.end()),
+        }
+    }
+}
+
+/// Why a binding of a name was left out of the module's names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Skip {
+    /// Bound in an `if TYPE_CHECKING:` block.
+    TypeChecking,
+    /// Bound in a branch that never runs on import, e.g. `if __name__ == "__main__":`.
+    Unreachable,
+    /// Deleted by `del`.
+    Deleted,
+    /// Bound by `type X = ...`, or by `X: TypeAlias = ...` before python 3.12, while type
+    /// aliases aren't exported.
+    TypeAlias,
+}
+
+/// A name left out of the module's names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Skipped {
+    pub reason: Skip,
+    /// Byte offset of the statement leaving it out.
+    pub offset: usize,
+}
+
+/// A `from <module> import *` statement, whose names can only be known by reading `module`.
+#[derive(Debug, PartialEq)]
+pub struct StarImport {
+    pub module: Option<String>,
+    /// Number of leading dots of a relative import.
+    pub level: u32,
+    /// Byte offset of the import statement.
+    pub offset: usize,
+}
+
+/// A `from <module> import <names>` statement outside of a `try` block.
+#[derive(Debug, PartialEq)]
+pub struct ImportFrom {
+    pub module: Option<String>,
+    /// Number of leading dots of a relative import.
+    pub level: u32,
+    /// The imported names, not their aliases.
+    pub names: Vec<String>,
+    /// Byte offset of the import statement.
+    pub offset: usize,
+}
+
+/// A statement assigning or modifying `__all__`, e.g. `__all__.append("foo")`.
+#[derive(Debug, PartialEq)]
+pub struct DunderAllStatement {
+    /// Byte offset of the statement.
+    pub offset: usize,
+    pub kind: DunderAllKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DunderAllKind {
+    Assign,
+    /// Names added by `+=`, `.append(...)`, `.extend(...)` or `.insert(...)`, `None` when
+    /// they aren't string literals.
+    Add(Option<Vec<String>>),
+    /// Names removed by `.remove(...)`, `None` when not a string literal.
+    Remove(Option<Vec<String>>),
+}
+
+/// A name bound on only some paths through an `if` or `try` statement.
+struct Conditional {
+    /// Byte offset of the statement.
+    offset: usize,
+    condition: Condition,
+}
+
+#[derive(Clone)]
+enum Condition {
+    /// Bound only if the test spanning these byte offsets is true, or false when `negated`.
+    If { test: (usize, usize), negated: bool },
+    /// Bound only if the `try` body raises, when `raises`, or only if it doesn't.
+    Try { raises: bool },
 }
 
 impl NameParser {
     fn new() -> Self {
+        Self::with_options(Rc::default())
+    }
+
+    fn with_options(options: Rc<Options>) -> Self {
         Self {
             names: HashSet::new(),
+            options,
+            diagnostics: Vec::new(),
+            star_imports: Vec::new(),
+            imports: Vec::new(),
+            dunder_all: None,
+            dunder_all_statements: Vec::new(),
+            collections: HashMap::new(),
+            getattrs: Vec::new(),
+            bindings: Vec::new(),
+            conditionals: HashMap::new(),
+            decorated: HashSet::new(),
+            warnings: Vec::new(),
+            definitions: HashMap::new(),
+            definition: Definition {
+                kind: Kind::Variable,
+                offset: 0,
+                end: 0,
+            },
+            skipped: HashMap::new(),
+            platform_names: HashMap::new(),
+            platform_star_imports: HashMap::new(),
         }
     }
+
+    pub fn parse(src: &str, options: &Options) -> Result<Self> {
+        let (statements, diagnostics) = parse_module(src, options)?;
+        Self::from_statements(src, statements, diagnostics, options)
+    }
+
+    /// The parser of `src`, already parsed into `statements` by [`parse_module`] with the
+    /// `diagnostics` it returned.
+    pub(crate) fn from_statements(
+        src: &str,
+        statements: Suite,
+        diagnostics: Vec<Diagnostic>,
+        options: &Options,
+    ) -> Result<Self> {
+        let mut parser = NameParser::with_options(Rc::new(options.clone()));
+        parser.diagnostics = diagnostics;
+        let statements = match options.target_version {
+            Some(target) => parser.without_newer_syntax(src, statements, target)?,
+            None => statements,
+        };
+        parser.add_statements(statements);
+        for (offset, message) in std::mem::take(&mut parser.warnings) {
+            let diagnostic = Diagnostic::at_offset(src, offset, message);
+            parser.diagnostics.push(diagnostic);
+        }
+        parser.add_lazy_names(src);
+        parser.add_conditional_diagnostics(src);
+        Ok(parser)
+    }
+
+    /// `statements` without the ones using syntax python `target` doesn't accept, which are
+    /// an error unless `Options::lenient` is set.
+    fn without_newer_syntax(
+        &mut self,
+        src: &str,
+        statements: Vec<Stmt>,
+        target: PythonVersion,
+    ) -> Result<Vec<Stmt>> {
+        let mut supported = Vec::with_capacity(statements.len());
+        for statement in statements {
+            let Some(newer) = syntax::newer_syntax(&statement, target) else {
+                supported.push(statement);
+                continue;
+            };
+            let message = format!("{newer}, the target version is {target}");
+            if !self.options.lenient {
+                let line = line_number(src, newer.offset);
+                return Err(anyhow!("Line {line}: {message}"));
+            }
+            self.diagnostics.push(Diagnostic::at_offset(
+                src,
+                newer.offset,
+                format!("Ignoring statement using newer syntax: {message}"),
+            ));
+        }
+        Ok(supported)
+    }
+
+    /// Names provided by module level `__getattr__` functions, which can only be seen
+    /// once every collection they may refer to has been assigned.
+    fn add_lazy_names(&mut self, src: &str) {
+        for getattr in std::mem::take(&mut self.getattrs) {
+            self.definition = Definition {
+                kind: Kind::Lazy,
+                offset: usize::from(getattr.range.start()),
+                end: usize::from(getattr.range.end()),
+            };
+            match module_getattr::lazy_names(&getattr, &self.collections) {
+                Some(names) => self.insert_many(names.into_iter().map(Name::from)),
+                None => self.diagnostics.push(Diagnostic::at_offset(
+                    src,
+                    usize::from(getattr.range.start()),
+                    String::from(
+                        "Attributes provided by module level `__getattr__` can't be tracked",
+                    ),
+                )),
+            }
+        }
+    }
+
+    fn add_conditional_diagnostics(&mut self, src: &str) {
+        let mut conditionals = std::mem::take(&mut self.conditionals)
+            .into_iter()
+            .filter(|(name, _)| !name.starts_with('_') && self.names.contains(name))
+            .collect::<Vec<_>>();
+        conditionals.sort_by(|(left, a), (right, b)| (a.offset, left).cmp(&(b.offset, right)));
+        for (name, conditional) in conditionals {
+            let condition = match conditional.condition {
+                Condition::If {
+                    test: (start, end),
+                    negated,
+                } => {
+                    let not = if negated { "not " } else { "" };
+                    format!("{not}`{}`", &src[start..end])
+                }
+                Condition::Try { raises } => {
+                    let raises = if raises { "raises" } else { "doesn't raise" };
+                    format!("the `try` block {raises}")
+                }
+            };
+            self.diagnostics.push(Diagnostic::at_offset(
+                src,
+                conditional.offset,
+                format!("`{name}` is only defined if {condition}"),
+            ));
+        }
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn take_decorated(&mut self) -> HashSet<String> {
+        std::mem::take(&mut self.decorated)
+    }
+
+    /// Functions and classes marked for export by one of `Options::export_decorators`.
+    pub(crate) fn decorated(&self) -> &HashSet<String> {
+        &self.decorated
+    }
+
+    pub(crate) fn star_imports(&self) -> &[StarImport] {
+        &self.star_imports
+    }
+
+    pub(crate) fn dunder_all_statements(&self) -> &[DunderAllStatement] {
+        &self.dunder_all_statements
+    }
+
+    pub fn take_dunder_all_statements(&mut self) -> Vec<DunderAllStatement> {
+        std::mem::take(&mut self.dunder_all_statements)
+    }
+
+    pub fn take_imports(&mut self) -> Vec<ImportFrom> {
+        std::mem::take(&mut self.imports)
+    }
+
+    pub fn take_star_imports(&mut self) -> Vec<StarImport> {
+        std::mem::take(&mut self.star_imports)
+    }
+
+    /// Where each bound name was last bound, including names bound only to be deleted.
+    pub fn take_definitions(&mut self) -> HashMap<String, Definition> {
+        owned_keys(std::mem::take(&mut self.definitions))
+    }
+
+    /// Names bound somewhere but left out of the module's names, and why.
+    pub fn take_skipped(&mut self) -> HashMap<String, Skipped> {
+        owned_keys(std::mem::take(&mut self.skipped))
+    }
+
+    /// Names bound only if a test on the platform holds, see `Options::platform_conditional`.
+    pub fn take_platform_names(&mut self) -> HashMap<String, PlatformCondition> {
+        std::mem::take(&mut self.platform_names)
+    }
+
+    /// Star imports run only if a test on the platform holds, by byte offset.
+    pub fn take_platform_star_imports(&mut self) -> HashMap<usize, PlatformCondition> {
+        std::mem::take(&mut self.platform_star_imports)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    /// The module's `__all__`, if it is assigned a literal list of strings.
+    pub fn dunder_all(&self) -> Option<&[String]> {
+        self.dunder_all.as_deref()
+    }
+}
+
+/// `map` with names as keys, each copied into a `String` of its own.
+fn owned_keys<V>(map: HashMap<Name, V>) -> HashMap<String, V> {
+    map.into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
 }
 
 impl IntoIterator for NameParser {
     type Item = String;
-    type IntoIter = IntoIter<Self::Item>;
+    type IntoIter = Map<IntoIter<Name>, fn(Name) -> String>;
     fn into_iter(self) -> Self::IntoIter {
-        self.names.into_iter()
+        let owned: fn(Name) -> String = |name| name.to_string();
+        self.names.into_iter().map(owned)
     }
 }
 
 impl FromStr for NameParser {
     type Err = Error;
     fn from_str(src: &str) -> Result<Self, Self::Err> {
-        let program = parse_program(src)?;
-        Ok(program.into())
-    }
-}
-
-impl From<Program> for NameParser {
-    fn from(program: Program) -> Self {
-        program.statements.into()
+        NameParser::parse(src, &Options::default())
     }
 }
 
-impl From<Vec<Statement>> for NameParser {
-    fn from(statements: Vec<Statement>) -> Self {
-        let mut parser = NameParser::new();
-        parser.add_statements(statements);
-        parser
+impl NameParser {
+    /// Binds `name`, the identifier of a statement being added, copied once and then shared.
+    fn bind(&mut self, name: &ast::Identifier) {
+        self.insert(Name::from(name.as_str()));
     }
-}
 
-impl NameParser {
-    fn insert(&mut self, name: String) {
+    fn insert(&mut self, name: Name) {
+        self.conditionals.remove(&name);
+        if self.options.platform_conditional {
+            self.platform_names.remove(&*name);
+        }
+        // rebinding a name, the common case, reuses its key
+        match self.definitions.get_mut(&name) {
+            Some(definition) => *definition = self.definition,
+            None => {
+                self.definitions.insert(Rc::clone(&name), self.definition);
+            }
+        }
+        self.bindings.push(Rc::clone(&name));
         self.names.insert(name);
     }
 
-    fn remove(&mut self, name: &String) {
+    fn remove(&mut self, name: &Name) {
         self.names.remove(name);
     }
 
-    fn insert_many(&mut self, names: impl Iterator<Item = String>) {
-        self.names.extend(names);
-    }
-
-    fn remove_many(&mut self, names: impl Iterator<Item = String>) {
+    fn insert_many(&mut self, names: impl Iterator<Item = Name>) {
         for name in names {
-            self.remove(&name);
+            self.insert(name);
         }
     }
 
+    fn skip(&mut self, name: Name, reason: Skip) {
+        let offset = self.definition.offset;
+        self.skipped.insert(name, Skipped { reason, offset });
+    }
+
     fn take_from(&mut self, other: Self) {
-        self.insert_many(other.into_iter());
+        self.insert_many(other.names.into_iter());
     }
 
     fn remove_from(&mut self, other: Self) {
-        self.remove_many(other.into_iter());
+        for name in other.names {
+            self.remove(&name);
+            self.skip(name, Skip::Deleted);
+        }
+    }
+
+    fn skip_from(&mut self, other: Self, reason: Skip) {
+        for name in other.names {
+            self.skip(name, reason);
+        }
     }
 }
 
 impl NameParser {
-    fn add_statements(&mut self, statements: Vec<Statement>) {
+    /// Returns `true` when the statements never complete normally, any that follow are
+    /// unreachable and skipped.
+    fn add_statements(&mut self, statements: Vec<Stmt>) -> bool {
         for statement in statements {
-            self.add_statement(statement);
+            if self.add_statement(statement) {
+                return true;
+            }
         }
+        false
     }
 
-    fn add_statement(&mut self, statement: Statement) {
-        match statement.node {
-            StatementType::FunctionDef {
-                is_async: _, name, ..
+    fn add_statement(&mut self, statement: Stmt) -> bool {
+        self.definition = Definition::of(&statement);
+        match statement {
+            Stmt::FunctionDef(def) if def.name.as_str() == "__getattr__" => {
+                self.bind(&def.name);
+                self.getattrs.push(def);
             }
-            | StatementType::ClassDef { name, .. } => {
-                self.insert(name);
+            Stmt::FunctionDef(ast::StmtFunctionDef {
+                name,
+                body,
+                decorator_list,
+                ..
+            })
+            | Stmt::AsyncFunctionDef(ast::StmtAsyncFunctionDef {
+                name,
+                body,
+                decorator_list,
+                ..
+            }) => {
+                self.add_decorated(name.as_str(), &decorator_list);
+                self.bind(&name);
+                if self.options.global_declarations {
+                    self.definition.kind = Kind::Variable;
+                    self.add_global_assignments(body);
+                }
+            }
+            Stmt::ClassDef(ast::StmtClassDef {
+                name,
+                decorator_list,
+                ..
+            }) => {
+                self.add_decorated(name.as_str(), &decorator_list);
+                self.bind(&name);
             }
-            StatementType::Delete { targets } => {
+            Stmt::Delete(ast::StmtDelete { targets, .. }) => {
                 self.remove_from(targets.into());
             }
-            StatementType::Assign { targets, .. } => {
+            Stmt::Assign(ast::StmtAssign {
+                targets,
+                value,
+                range,
+                ..
+            }) => {
+                self.add_named_expressions(&value);
+                if let [Expr::Name(ast::ExprName { id, .. })] = targets.as_slice() {
+                    self.check_functional_type(id.as_str(), &value, usize::from(range.start()));
+                    if id.as_str() == "__all__" {
+                        self.dunder_all = string_list(&value);
+                        self.dunder_all_statements.push(DunderAllStatement {
+                            offset: usize::from(range.start()),
+                            kind: DunderAllKind::Assign,
+                        });
+                    }
+                    if let Some(collection) = module_getattr::string_collection(&value) {
+                        self.collections.insert(id.to_string(), collection);
+                    }
+                }
                 self.take_from(targets.into());
             }
-            StatementType::AugAssign { target, .. } | StatementType::AnnAssign { target, .. } => {
+            Stmt::AugAssign(ast::StmtAugAssign {
+                target,
+                op,
+                value,
+                range,
+            }) => {
+                self.add_named_expressions(&value);
+                if is_dunder_all(&target) && op == ast::Operator::Add {
+                    self.dunder_all_statements.push(DunderAllStatement {
+                        offset: usize::from(range.start()),
+                        kind: DunderAllKind::Add(string_list(&value)),
+                    });
+                }
                 self.take_from((*target).into());
             }
-            StatementType::For {
-                is_async: _,
+            Stmt::AnnAssign(ast::StmtAnnAssign {
                 target,
-                iter: _,
-                body,
-                orelse,
-            } => {
-                self.take_from((*target).into());
-                self.add_statements(body);
-                if let Some(body) = orelse {
-                    self.add_statements(body);
+                annotation,
+                value,
+                ..
+            }) => {
+                if let Some(value) = value {
+                    self.add_named_expressions(&value);
+                }
+                // before `type` statements, `X: TypeAlias = ...` is how aliases are spelled
+                let alias = is_type_alias_annotation(&annotation)
+                    && self
+                        .options
+                        .target_version
+                        .is_some_and(|target| target < PythonVersion::new(3, 12));
+                if alias {
+                    self.definition.kind = Kind::TypeAlias;
+                }
+                if alias && !self.options.type_aliases {
+                    self.skip_from((*target).into(), Skip::TypeAlias);
+                } else {
+                    self.take_from((*target).into());
+                }
+            }
+            Stmt::TypeAlias(ast::StmtTypeAlias { name, .. }) => {
+                if self.options.type_aliases {
+                    self.take_from((*name).into());
+                } else {
+                    self.skip_from((*name).into(), Skip::TypeAlias);
                 }
             }
-            StatementType::While {
-                test: target,
+            Stmt::For(ast::StmtFor {
+                target,
+                iter,
+                body,
+                orelse,
+                ..
+            })
+            | Stmt::AsyncFor(ast::StmtAsyncFor {
+                target,
+                iter,
                 body,
                 orelse,
+                ..
+            }) => {
+                self.add_named_expressions(&iter);
+                self.take_from((*target).into());
+                self.add_loop_body(body);
+                self.add_statements(orelse);
+            }
+            Stmt::While(ast::StmtWhile {
+                test, body, orelse, ..
+            }) => {
+                let value = condition::evaluate(&test, &self.options);
+                self.add_named_expressions(&test);
+                match value {
+                    Some(true) => {
+                        self.add_statements(body);
+                    }
+                    Some(false) => self.skip_statements(body, Skip::Unreachable),
+                    None => self.add_loop_body(body),
+                }
+                self.add_statements(orelse);
             }
-            | StatementType::If {
-                test: target,
+            Stmt::If(ast::StmtIf {
+                test,
                 body,
                 orelse,
-            } => {
-                if let ExpressionType::NamedExpression { left, .. } = target.node {
-                    self.take_from((*left).into());
+                range,
+            }) => {
+                let value = condition::evaluate(&test, &self.options);
+                let guard = type_checking_guard(&test);
+                let platform = self.options.platform_conditional
+                    && value.is_none()
+                    && guard.is_none()
+                    && condition::is_platform_test(&test);
+                self.add_named_expressions(&test);
+                let run_body =
+                    value != Some(false) && (self.options.type_checking || guard != Some(true));
+                let run_orelse =
+                    value != Some(true) && (self.options.type_checking || guard != Some(false));
+                // a branch that isn't run is skipped for being dead or a `TYPE_CHECKING` one
+                let skip = if value.is_some() {
+                    Skip::Unreachable
+                } else {
+                    Skip::TypeChecking
+                };
+                // both branches start from the same names, a name is only gone once
+                // deleted on every path
+                let before = self.names.clone();
+                let body_star_imports = self.star_imports.len();
+                let body_terminates = if run_body {
+                    self.add_statements(body)
+                } else {
+                    self.skip_statements(body, skip);
+                    false
+                };
+                let body_names = std::mem::replace(&mut self.names, before);
+                let orelse_star_imports = self.star_imports.len();
+                let orelse_terminates = if run_orelse {
+                    self.add_statements(orelse)
+                } else {
+                    self.skip_statements(orelse, skip);
+                    false
+                };
+                let orelse_names = std::mem::take(&mut self.names);
+                if platform && !body_terminates && !orelse_terminates {
+                    let test = usize::from(test.start())..usize::from(test.end());
+                    // outer tests go first, conditions of nested `if` statements are extended
+                    for name in body_names.symmetric_difference(&orelse_names) {
+                        let negated = !body_names.contains(name);
+                        let condition = self.platform_names.entry(name.to_string()).or_default();
+                        condition.insert(0, (test.clone(), negated));
+                    }
+                    for (index, star_import) in
+                        self.star_imports[body_star_imports..].iter().enumerate()
+                    {
+                        let negated = body_star_imports + index >= orelse_star_imports;
+                        let condition = self
+                            .platform_star_imports
+                            .entry(star_import.offset)
+                            .or_default();
+                        condition.insert(0, (test.clone(), negated));
+                    }
                 }
-                self.add_statements(body);
-                if let Some(body) = orelse {
-                    self.add_statements(body);
+                let tracked = self.options.warn_conditional
+                    && value.is_none()
+                    && guard.is_none()
+                    && !platform;
+                if tracked && !body_terminates && !orelse_terminates {
+                    let offset = usize::from(range.start());
+                    let test = (usize::from(test.start()), usize::from(test.end()));
+                    for name in body_names.symmetric_difference(&orelse_names) {
+                        let negated = !body_names.contains(name);
+                        let conditional = Conditional {
+                            offset,
+                            condition: Condition::If { test, negated },
+                        };
+                        self.conditionals.insert(name.clone(), conditional);
+                    }
                 }
+                self.names = match value {
+                    Some(true) => body_names,
+                    Some(false) => orelse_names,
+                    None => &body_names | &orelse_names,
+                };
+                return match value {
+                    Some(true) => body_terminates,
+                    Some(false) => orelse_terminates,
+                    None => body_terminates && orelse_terminates,
+                };
             }
-            StatementType::With {
-                is_async: _,
-                items,
-                body,
-            } => {
+            Stmt::With(ast::StmtWith { items, body, .. })
+            | Stmt::AsyncWith(ast::StmtAsyncWith { items, body, .. }) => {
+                for item in &items {
+                    self.add_named_expressions(&item.context_expr);
+                }
                 self.take_from(items.into());
                 self.add_statements(body);
             }
-            StatementType::Try {
+            Stmt::Try(ast::StmtTry {
                 body,
                 handlers,
                 orelse,
                 finalbody,
-            } => {
-                self.add_statements(body);
-                for handler in handlers {
-                    self.add_statements(handler.body);
+                range,
+            })
+            | Stmt::TryStar(ast::StmtTryStar {
+                body,
+                handlers,
+                orelse,
+                finalbody,
+                range,
+            }) => {
+                let before = self.snapshot_if(!handlers.is_empty());
+                let body_start = self.bindings.len();
+                let imports = self.imports.len();
+                let mut terminates = self.add_statements(body);
+                if !handlers.is_empty() {
+                    // imports guarded by a handler are expected to fail at times
+                    self.imports.truncate(imports);
                 }
-                if let Some(body) = orelse {
-                    self.add_statements(body);
+                let mut success_names = self.bindings[body_start..].to_vec();
+                let mut handlers_terminate = true;
+                let mut handler_names: Option<Vec<Name>> = None;
+                let mut any_handler_names = Vec::new();
+                for ExceptHandler::ExceptHandler(handler) in handlers {
+                    let handler_start = self.bindings.len();
+                    if self.add_handler_statements(handler.body) {
+                        continue;
+                    }
+                    handlers_terminate = false;
+                    let names = self.bindings[handler_start..].to_vec();
+                    handler_names = Some(match handler_names {
+                        Some(common) => common
+                            .into_iter()
+                            .filter(|name| names.contains(name))
+                            .collect(),
+                        None => names.clone(),
+                    });
+                    any_handler_names.extend(names);
                 }
-                if let Some(body) = finalbody {
-                    self.add_statements(body);
+                let orelse_start = self.bindings.len();
+                terminates |= self.add_statements(orelse);
+                success_names.extend_from_slice(&self.bindings[orelse_start..]);
+                if let Some(before) = before.filter(|_| !terminates && !handlers_terminate) {
+                    let offset = usize::from(range.start());
+                    let handler_names = handler_names.unwrap_or_default();
+                    let condition = |raises| Condition::Try { raises };
+                    self.add_conditionals(
+                        &before,
+                        &success_names,
+                        &handler_names,
+                        offset,
+                        condition(false),
+                    );
+                    self.add_conditionals(
+                        &before,
+                        &any_handler_names,
+                        &success_names,
+                        offset,
+                        condition(true),
+                    );
                 }
+                let finally_terminates = self.add_statements(finalbody);
+                return (terminates && handlers_terminate) || finally_terminates;
+            }
+            Stmt::Import(ast::StmtImport { names: symbols, .. }) => {
+                self.take_from(symbols.into());
             }
-            StatementType::Import { names: symbols }
-            | StatementType::ImportFrom {
-                level: _,
-                module: _,
+            Stmt::ImportFrom(ast::StmtImportFrom {
+                module,
                 names: symbols,
-            } => self.take_from(symbols.into()),
+                level,
+                range,
+                ..
+            }) => {
+                let module = module.map(|module| module.to_string());
+                let level = level.map_or(0, |level| level.to_u32());
+                if level == 0 && module.as_deref() == Some("__future__") {
+                    // compiler directives, not names
+                    return false;
+                }
+                if symbols.iter().any(|symbol| symbol.name.as_str() == "*") {
+                    self.star_imports.push(StarImport {
+                        module,
+                        level,
+                        offset: usize::from(range.start()),
+                    });
+                } else {
+                    self.imports.push(ImportFrom {
+                        module,
+                        level,
+                        names: symbols
+                            .iter()
+                            .map(|symbol| symbol.name.to_string())
+                            .collect(),
+                        offset: usize::from(range.start()),
+                    });
+                }
+                self.take_from(symbols.into());
+            }
+            Stmt::Raise(ast::StmtRaise { exc, cause, .. }) => {
+                for expr in exc.iter().chain(&cause) {
+                    self.add_named_expressions(expr);
+                }
+                return true;
+            }
+            Stmt::Assert(ast::StmtAssert { test, msg, .. }) => {
+                self.add_named_expressions(&test);
+                if let Some(msg) = msg {
+                    self.add_named_expressions(&msg);
+                }
+                return condition::evaluate(&test, &self.options) == Some(false);
+            }
+            Stmt::Expr(ast::StmtExpr { value, range }) => {
+                self.add_named_expressions(&value);
+                if let Some(kind) = dunder_all_method_call(&value) {
+                    self.dunder_all_statements.push(DunderAllStatement {
+                        offset: usize::from(range.start()),
+                        kind,
+                    });
+                }
+                return is_exit_call(&value);
+            }
             _ => {}
         }
+        false
+    }
+}
+
+impl NameParser {
+    /// Names bound in a handler that always raises (e.g. `except ImportError: raise ...`)
+    /// only exist while the import is failing, so they are rolled back.
+    fn add_handler_statements(&mut self, statements: Vec<Stmt>) -> bool {
+        let names = self.names.clone();
+        let star_imports = self.star_imports.len();
+        let bindings = self.bindings.len();
+        let terminates = self.add_statements(statements);
+        if terminates {
+            self.names = names;
+            self.star_imports.truncate(star_imports);
+            self.bindings.truncate(bindings);
+        }
+        terminates
+    }
+
+    /// Record the names `statements` would bind as skipped for `reason`.
+    fn skip_statements(&mut self, statements: Vec<Stmt>, reason: Skip) {
+        if statements.is_empty() {
+            return;
+        }
+        let mut branch = NameParser::with_options(Rc::clone(&self.options));
+        branch.add_statements(statements);
+        self.skipped.extend(branch.skipped);
+        for (name, definition) in branch.definitions {
+            self.skipped.insert(
+                name,
+                Skipped {
+                    reason,
+                    offset: definition.offset,
+                },
+            );
+        }
+    }
+
+    /// Names a function body declares `global` and then binds.
+    fn add_global_assignments(&mut self, body: Vec<Stmt>) {
+        let mut globals = HashSet::new();
+        global_names(&body, &mut globals);
+        if globals.is_empty() {
+            return;
+        }
+        let mut local = NameParser::with_options(Rc::clone(&self.options));
+        local.add_statements(body);
+        let bound = local.names.into_iter();
+        self.insert_many(bound.filter(|name| globals.contains(&**name)));
+    }
+
+    fn add_decorated(&mut self, name: &str, decorator_list: &[Expr]) {
+        let exports = &self.options.export_decorators;
+        if decorator_list
+            .iter()
+            .any(|decorator| is_export_decorator(decorator, exports))
+        {
+            self.decorated.insert(name.to_string());
+        }
+    }
+
+    /// `Point = NamedTuple("Pointt", ...)` breaks pickling and introspection of `Point`.
+    fn check_functional_type(&mut self, target: &str, value: &Expr, offset: usize) {
+        let Some(typ) = functional::functional_type(value) else {
+            return;
+        };
+        if typ.name != target {
+            let message = format!(
+                "`{target}` is assigned `{}({:?}, ...)`, the names should match",
+                typ.factory, typ.name
+            );
+            self.warnings.push((offset, message));
+        }
+    }
+
+    /// A loop body may never run, so names it deletes are kept.
+    fn add_loop_body(&mut self, body: Vec<Stmt>) {
+        let before = self.names.clone();
+        self.add_statements(body);
+        self.names.extend(before);
+    }
+
+    /// The names bound before a branching statement, when conditional names are tracked.
+    fn snapshot_if(&self, branches: bool) -> Option<HashSet<Name>> {
+        (branches && self.options.warn_conditional).then(|| self.names.clone())
+    }
+
+    /// Names in `bound` but not in `other` are conditional, unless they were bound before.
+    ///
+    /// Used for `try` statements, whose handlers may run after only part of the body.
+    fn add_conditionals(
+        &mut self,
+        before: &HashSet<Name>,
+        bound: &[Name],
+        other: &[Name],
+        offset: usize,
+        condition: Condition,
+    ) {
+        for name in bound {
+            if !before.contains(name) && !other.contains(name) {
+                let conditional = Conditional {
+                    offset,
+                    condition: condition.clone(),
+                };
+                self.conditionals.insert(name.clone(), conditional);
+            }
+        }
+    }
+
+    fn add_named_expressions(&mut self, expr: &Expr) {
+        let mut names = Vec::new();
+        walrus::named_expression_targets(expr, &mut names);
+        self.insert_many(names.into_iter());
+    }
+}
+
+/// Names declared `global` in a function body, not counting nested scopes.
+fn global_names(statements: &[Stmt], names: &mut HashSet<String>) {
+    for statement in statements {
+        match statement {
+            Stmt::Global(ast::StmtGlobal { names: globals, .. }) => {
+                names.extend(globals.iter().map(|name| name.to_string()));
+            }
+            Stmt::If(ast::StmtIf { body, orelse, .. })
+            | Stmt::While(ast::StmtWhile { body, orelse, .. })
+            | Stmt::For(ast::StmtFor { body, orelse, .. })
+            | Stmt::AsyncFor(ast::StmtAsyncFor { body, orelse, .. }) => {
+                global_names(body, names);
+                global_names(orelse, names);
+            }
+            Stmt::With(ast::StmtWith { body, .. })
+            | Stmt::AsyncWith(ast::StmtAsyncWith { body, .. }) => {
+                global_names(body, names);
+            }
+            Stmt::Try(ast::StmtTry {
+                body,
+                handlers,
+                orelse,
+                finalbody,
+                ..
+            })
+            | Stmt::TryStar(ast::StmtTryStar {
+                body,
+                handlers,
+                orelse,
+                finalbody,
+                ..
+            }) => {
+                global_names(body, names);
+                for ExceptHandler::ExceptHandler(handler) in handlers {
+                    global_names(&handler.body, names);
+                }
+                global_names(orelse, names);
+                global_names(finalbody, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The strings of a list or tuple literal made up only of string literals.
+fn string_list(expr: &Expr) -> Option<Vec<String>> {
+    let elts = match expr {
+        Expr::List(ast::ExprList { elts, .. }) | Expr::Tuple(ast::ExprTuple { elts, .. }) => elts,
+        _ => return None,
+    };
+    elts.iter()
+        .map(|elt| match elt {
+            Expr::Constant(ast::ExprConstant {
+                value: ast::Constant::Str(value),
+                ..
+            }) => Some(value.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn is_dunder_all(expr: &Expr) -> bool {
+    matches!(expr, Expr::Name(ast::ExprName { id, .. }) if id.as_str() == "__all__")
+}
+
+/// The value `statement` assigns to `__all__`, with whether it is added to it with `+=`.
+pub(crate) fn dunder_all_value(statement: &Stmt) -> Option<(&Expr, bool)> {
+    match statement {
+        Stmt::Assign(ast::StmtAssign { targets, value, .. }) => match targets.as_slice() {
+            [target] if is_dunder_all(target) => Some((&**value, false)),
+            _ => None,
+        },
+        Stmt::AugAssign(ast::StmtAugAssign {
+            target,
+            op: ast::Operator::Add,
+            value,
+            ..
+        }) if is_dunder_all(target) => Some((&**value, true)),
+        _ => None,
+    }
+}
+
+thread_local! {
+    /// The time spent parsing modules on this thread, see [`take_parse_time`].
+    static PARSE_TIME: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// The time spent parsing modules on this thread since the last call, e.g. to tell it apart
+/// from the rest of the analysis when profiling.
+pub fn take_parse_time() -> Duration {
+    PARSE_TIME.with(Cell::take)
+}
+
+/// Run `parse`, adding the time it takes to [`take_parse_time`].
+pub(crate) fn timed_parse<T>(parse: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let parsed = parse();
+    PARSE_TIME.with(|time| time.set(time.get() + start.elapsed()));
+    parsed
+}
+
+/// The statements of `src`, skipping the ones that fail to parse with `Options::lenient`, and
+/// a diagnostic for each of those.
+pub(crate) fn parse_module(src: &str, options: &Options) -> Result<(Suite, Vec<Diagnostic>)> {
+    timed_parse(|| {
+        if options.lenient {
+            Ok(parse_recovering(src))
+        } else {
+            Ok((Suite::parse(src, "<embedded>")?, Vec::new()))
+        }
+    })
+}
+
+/// `TypeAlias`, `typing.TypeAlias` or `typing_extensions.TypeAlias`.
+fn is_type_alias_annotation(annotation: &Expr) -> bool {
+    match annotation {
+        Expr::Name(ast::ExprName { id, .. }) => id.as_str() == "TypeAlias",
+        Expr::Attribute(ast::ExprAttribute { value, attr, .. }) => {
+            attr.as_str() == "TypeAlias"
+                && matches!(
+                    value.as_ref(),
+                    Expr::Name(ast::ExprName { id, .. })
+                        if matches!(id.as_str(), "typing" | "typing_extensions")
+                )
+        }
+        _ => false,
+    }
+}
+
+/// `__all__.append("foo")`, `__all__.extend(["foo"])`, `__all__.insert(0, "foo")` or
+/// `__all__.remove("foo")`.
+fn dunder_all_method_call(expr: &Expr) -> Option<DunderAllKind> {
+    let Expr::Call(ast::ExprCall { func, args, .. }) = expr else {
+        return None;
+    };
+    let Expr::Attribute(ast::ExprAttribute { value, attr, .. }) = func.as_ref() else {
+        return None;
+    };
+    if !is_dunder_all(value) {
+        return None;
+    }
+    let string = |arg: Option<&Expr>| match arg {
+        Some(Expr::Constant(ast::ExprConstant {
+            value: ast::Constant::Str(value),
+            ..
+        })) => Some(vec![value.clone()]),
+        _ => None,
+    };
+    match attr.as_str() {
+        "append" | "insert" => Some(DunderAllKind::Add(string(args.last()))),
+        "extend" => Some(DunderAllKind::Add(args.first().and_then(string_list))),
+        "remove" => Some(DunderAllKind::Remove(string(args.first()))),
+        _ => None,
+    }
+}
+
+/// Calls that never return: `sys.exit(...)`, `os._exit(...)`, `exit()` and `quit()`.
+fn is_exit_call(expr: &Expr) -> bool {
+    let Expr::Call(ast::ExprCall { func, .. }) = expr else {
+        return false;
+    };
+    match func.as_ref() {
+        Expr::Name(ast::ExprName { id, .. }) => matches!(id.as_str(), "exit" | "quit"),
+        Expr::Attribute(ast::ExprAttribute { value, attr, .. }) => {
+            let Expr::Name(ast::ExprName { id: module, .. }) = value.as_ref() else {
+                return false;
+            };
+            matches!(
+                (module.as_str(), attr.as_str()),
+                ("sys", "exit") | ("os", "_exit")
+            )
+        }
+        _ => false,
+    }
+}
+
+/// `@public`, `@atpublic.public` or `@public()` when `public` is one of `exports`.
+fn is_export_decorator(decorator: &Expr, exports: &[String]) -> bool {
+    let name = match decorator {
+        Expr::Call(ast::ExprCall { func, .. }) => return is_export_decorator(func, exports),
+        Expr::Name(ast::ExprName { id, .. }) => id.as_str(),
+        Expr::Attribute(ast::ExprAttribute { attr, .. }) => attr.as_str(),
+        _ => return false,
+    };
+    exports.iter().any(|export| export == name)
+}
+
+/// `Some(true)` for `if TYPE_CHECKING:`, `Some(false)` for `if not TYPE_CHECKING:`.
+fn type_checking_guard(test: &Expr) -> Option<bool> {
+    match test {
+        Expr::Name(ast::ExprName { id, .. }) => (id.as_str() == "TYPE_CHECKING").then_some(true),
+        Expr::Attribute(ast::ExprAttribute { value, attr, .. }) => {
+            let is_typing_module = matches!(
+                value.as_ref(),
+                Expr::Name(ast::ExprName { id, .. })
+                    if matches!(id.as_str(), "typing" | "typing_extensions")
+            );
+            (is_typing_module && attr.as_str() == "TYPE_CHECKING").then_some(true)
+        }
+        Expr::UnaryOp(ast::ExprUnaryOp {
+            op: ast::UnaryOp::Not,
+            operand,
+            ..
+        }) => type_checking_guard(operand).map(|guard| !guard),
+        _ => None,
     }
 }
 
-impl From<Vec<Expression>> for NameParser {
-    fn from(expressions: Vec<Expression>) -> Self {
+impl From<Vec<Expr>> for NameParser {
+    fn from(expressions: Vec<Expr>) -> Self {
         let mut parser = NameParser::new();
         for expression in expressions {
             parser.take_from(expression.into());
@@ -185,15 +1179,19 @@ impl From<Vec<Expression>> for NameParser {
     }
 }
 
-impl From<Expression> for NameParser {
-    fn from(expression: Expression) -> Self {
-        match expression.node {
-            ExpressionType::Identifier { name } => {
+impl From<Expr> for NameParser {
+    fn from(expression: Expr) -> Self {
+        match expression {
+            Expr::Name(ast::ExprName { id, .. }) => {
                 let mut parser = NameParser::new();
-                parser.insert(name);
+                parser.bind(&id);
                 parser
             }
-            ExpressionType::Tuple { elements } => NameParser::from(elements),
+            Expr::Tuple(ast::ExprTuple { elts, .. }) | Expr::List(ast::ExprList { elts, .. }) => {
+                NameParser::from(elts)
+            }
+            Expr::Starred(ast::ExprStarred { value, .. }) => NameParser::from(*value),
+            // `obj.attr = ...` and `d["k"] = ...` mutate existing objects, they bind no names
             _ => NameParser::new(),
         }
     }
@@ -203,22 +1201,22 @@ impl From<Vec<WithItem>> for NameParser {
     fn from(items: Vec<WithItem>) -> Self {
         let mut parser = NameParser::new();
         for vars in items.into_iter().filter_map(|item| item.optional_vars) {
-            parser.take_from(vars.into());
+            parser.take_from((*vars).into());
         }
         parser
     }
 }
 
-impl From<Vec<ImportSymbol>> for NameParser {
-    fn from(symbols: Vec<ImportSymbol>) -> Self {
+impl From<Vec<Alias>> for NameParser {
+    fn from(symbols: Vec<Alias>) -> Self {
         let mut parser = NameParser::new();
         for symbol in symbols {
-            let name = symbol.alias.unwrap_or(symbol.symbol);
-            if name == "*" {
+            let name = symbol.asname.unwrap_or(symbol.name);
+            if name.as_str() == "*" {
                 // star imports can be ignored
                 continue;
             }
-            parser.insert(name);
+            parser.bind(&name);
         }
         parser
     }
@@ -228,11 +1226,15 @@ impl From<Vec<ImportSymbol>> for NameParser {
 mod tests {
     use super::*;
 
-    type Names = HashSet<String>;
+    type Names = HashSet<Name>;
 
     fn assert_src_parses_to_expected(src: &str, expected_names: Vec<&str>) {
-        let parsed_names = src.parse::<NameParser>().unwrap().names;
-        let expected_names = Names::from_iter(expected_names.into_iter().map(|s| s.to_string()));
+        assert_src_parses_to_expected_with(src, &Options::default(), expected_names);
+    }
+
+    fn assert_src_parses_to_expected_with(src: &str, options: &Options, expected_names: Vec<&str>) {
+        let parsed_names = NameParser::parse(src, options).unwrap().names;
+        let expected_names = Names::from_iter(expected_names.into_iter().map(Name::from));
         assert_eq!(parsed_names, expected_names);
     }
 
@@ -271,132 +1273,609 @@ import sys as foo
     }
 
     #[test]
-    fn basic_from_import() {
+    fn basic_from_import() {
+        let src = "
+from foo import bar
+";
+        assert_src_parses_to_expected(src, vec!["bar"]);
+    }
+
+    #[test]
+    fn from_import_multi() {
+        let src = "
+from foo import (
+    bar,
+    baz,
+)
+";
+        assert_src_parses_to_expected(src, vec!["bar", "baz"]);
+    }
+
+    #[test]
+    fn from_import_alias() {
+        let src = "
+from foo import bar as baz
+";
+        assert_src_parses_to_expected(src, vec!["baz"]);
+    }
+
+    #[test]
+    fn future_import() {
+        let src = "
+from __future__ import annotations, division
+";
+        assert_src_parses_to_expected(src, vec![]);
+    }
+
+    #[test]
+    fn basic_function() {
+        let src = "
+def foo(x: int) -> str | None:
+    z = 3
+";
+        assert_src_parses_to_expected(src, vec!["foo"]);
+    }
+
+    #[test]
+    fn async_function() {
+        let src = "
+async def foo():
+    ...
+";
+        assert_src_parses_to_expected(src, vec!["foo"]);
+    }
+
+    #[test]
+    fn generic_function() {
+        let src = "
+def first[T](x: list[T]) -> T:
+    ...
+";
+        assert_src_parses_to_expected(src, vec!["first"]);
+    }
+
+    #[test]
+    fn generic_class() {
+        let src = "
+class Stack[T, *Ts, **P]:
+    items: list[T]
+";
+        assert_src_parses_to_expected(src, vec!["Stack"]);
+    }
+
+    #[test]
+    fn module_getattr_lazy_names() {
+        let src = "
+def __getattr__(name):
+    if name in _LAZY:
+        ...
+
+_LAZY = {'foo': '._foo', 'Bar': '._bar'}
+";
+        assert_src_parses_to_expected(src, vec!["__getattr__", "_LAZY", "foo", "Bar"]);
+    }
+
+    #[test]
+    fn module_getattr_untrackable() {
+        let src = "
+def __getattr__(name):
+    return globals()[name.lower()]
+";
+        let mut parser = src.parse::<NameParser>().unwrap();
+        let diagnostics = parser.take_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn global_declarations() {
+        let src = "
+def setup():
+    global cache, counter
+    if cache is None:
+        cache = {}
+    local = 1
+
+    def inner():
+        global nested
+";
+        let options = Options {
+            global_declarations: true,
+            ..Default::default()
+        };
+        assert_src_parses_to_expected_with(src, &options, vec!["setup", "cache"]);
+        assert_src_parses_to_expected(src, vec!["setup"]);
+    }
+
+    #[test]
+    fn basic_class() {
+        let src = "
+class Foo(Bar, Baz):
+    x = 10
+    def bar(self, y: int) -> list[int]:
+        ...
+";
+        assert_src_parses_to_expected(src, vec!["Foo"]);
+    }
+
+    #[test]
+    fn basic_assignment() {
+        let src = "
+x = 1
+";
+        assert_src_parses_to_expected(src, vec!["x"]);
+    }
+
+    #[test]
+    fn multi_target_assignment() {
+        let src = "
+foo = bar = baz = 1
+";
+        assert_src_parses_to_expected(src, vec!["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn unpack_assignment() {
+        let src = "
+foo, bar = 1, 2
+";
+        assert_src_parses_to_expected(src, vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn augmented_assignment() {
+        let src = "
+foo += 1
+";
+        assert_src_parses_to_expected(src, vec!["foo"]);
+    }
+
+    #[test]
+    fn annotated_assignment() {
+        let src = "
+foo: int = 1
+";
+        assert_src_parses_to_expected(src, vec!["foo"]);
+    }
+
+    #[test]
+    fn type_alias() {
+        let src = "
+type Alias = int | str
+";
+        assert_src_parses_to_expected(src, vec!["Alias"]);
+    }
+
+    #[test]
+    fn generic_type_alias() {
+        let src = "
+type Pair[T] = tuple[T, T]
+";
+        assert_src_parses_to_expected(src, vec!["Pair"]);
+    }
+
+    #[test]
+    fn type_alias_excluded() {
+        let src = "
+type Alias = int | str
+";
+        let options = Options {
+            type_aliases: false,
+            ..Default::default()
+        };
+        assert_src_parses_to_expected_with(src, &options, vec![]);
+    }
+
+    #[test]
+    fn lenient_skips_invalid_statements() {
+        let src = "
+a = 1
+b = = 2
+c = 3
+";
+        let options = Options {
+            lenient: true,
+            ..Default::default()
+        };
+        assert_src_parses_to_expected_with(src, &options, vec!["a", "c"]);
+        assert!(src.parse::<NameParser>().is_err());
+    }
+
+    #[test]
+    fn basic_if() {
+        let src = "
+if bar:
+    A = 1
+    def fooey():
+        ...
+";
+        assert_src_parses_to_expected(src, vec!["A", "fooey"]);
+    }
+
+    #[test]
+    fn elif_else() {
+        let src = "
+if bar:
+    A = 1
+elif fooey:
+    B = 2
+else:
+    C = 3
+";
+        assert_src_parses_to_expected(src, vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn walrus_if() {
+        let src = "
+if (foo := bar()):
+    ...
+";
+        assert_src_parses_to_expected(src, vec!["foo"]);
+    }
+
+    #[test]
+    fn type_checking_excluded() {
+        let src = "
+from typing import TYPE_CHECKING
+import typing
+
+if TYPE_CHECKING:
+    from foo import A
+else:
+    B = 1
+
+if typing.TYPE_CHECKING:
+    from foo import C
+
+if not TYPE_CHECKING:
+    D = 1
+else:
+    from foo import E
+";
+        assert_src_parses_to_expected(src, vec!["TYPE_CHECKING", "typing", "B", "D"]);
+    }
+
+    #[test]
+    fn type_checking_included() {
+        let src = "
+if TYPE_CHECKING:
+    from foo import A
+else:
+    B = 1
+";
+        let options = Options {
+            type_checking: true,
+            ..Default::default()
+        };
+        assert_src_parses_to_expected_with(src, &options, vec!["A", "B"]);
+    }
+
+    #[test]
+    fn version_info_branches() {
+        let src = "
+if sys.version_info >= (3, 11):
+    from tomllib import loads
+else:
+    from tomli import loads, dumps
+
+if sys.version_info < (3, 9):
+    from backports import cache
+";
+        let options = Options {
+            target_version: Some(PythonVersion::new(3, 11)),
+            ..Default::default()
+        };
+        assert_src_parses_to_expected_with(src, &options, vec!["loads"]);
+        assert_src_parses_to_expected(src, vec!["loads", "dumps", "cache"]);
+    }
+
+    #[test]
+    fn newer_syntax_than_target() {
+        let src = "
+def f[T](x: T) -> T: ...
+y = 1
+";
+        let options = Options::builder()
+            .target_version(PythonVersion::new(3, 11))
+            .build();
+        let err = NameParser::parse(src, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Line 2: type parameter lists require python 3.12, the target version is 3.11"
+        );
+        let options = Options::builder()
+            .target_version(PythonVersion::new(3, 11))
+            .lenient(true)
+            .build();
+        let mut parser = NameParser::parse(src, &options).unwrap();
+        assert_eq!(parser.names, Names::from([Name::from("y")]));
+        assert_eq!(parser.take_diagnostics().len(), 1);
+        assert_src_parses_to_expected(src, vec!["f", "y"]);
+    }
+
+    #[test]
+    fn type_alias_annotation() {
         let src = "
-from foo import bar
+from typing import TypeAlias
+Vector: TypeAlias = list[float]
 ";
-        assert_src_parses_to_expected(src, vec!["bar"]);
+        let options = Options::builder()
+            .target_version(PythonVersion::new(3, 9))
+            .type_aliases(false)
+            .build();
+        assert_src_parses_to_expected_with(src, &options, vec!["TypeAlias"]);
+        let mut parser = NameParser::parse(src, &options).unwrap();
+        let skipped = parser.take_skipped().remove("Vector");
+        assert_eq!(skipped.map(|s| s.reason), Some(Skip::TypeAlias));
+
+        // an assignment from python 3.12 on, where aliases are `type` statements
+        let options = Options::builder()
+            .target_version(PythonVersion::new(3, 12))
+            .type_aliases(false)
+            .build();
+        assert_src_parses_to_expected_with(src, &options, vec!["TypeAlias", "Vector"]);
     }
 
     #[test]
-    fn from_import_multi() {
+    fn main_guard_excluded() {
         let src = "
-from foo import (
-    bar,
-    baz,
-)
+def main():
+    ...
+
+if __name__ == \"__main__\":
+    args = parse_args()
+    main()
 ";
-        assert_src_parses_to_expected(src, vec!["bar", "baz"]);
+        assert_src_parses_to_expected(src, vec!["main"]);
     }
 
     #[test]
-    fn from_import_alias() {
+    fn reversed_main_guard_excluded() {
         let src = "
-from foo import bar as baz
+if '__main__' == __name__:
+    args = parse_args()
+else:
+    imported = True
 ";
-        assert_src_parses_to_expected(src, vec!["baz"]);
+        assert_src_parses_to_expected(src, vec!["imported"]);
     }
 
     #[test]
-    fn basic_function() {
+    fn walrus_in_comprehension() {
         let src = "
-def foo(x: int) -> str | None:
-    z = 3
+values = [y := f(x), y**2]
+total = sum(n for x in data if (n := g(x)))
+squares = [i * i for i in range(10)]
 ";
-        assert_src_parses_to_expected(src, vec!["foo"]);
+        assert_src_parses_to_expected(src, vec!["values", "y", "total", "n", "squares"]);
     }
 
     #[test]
-    fn async_function() {
+    fn walrus_nested_in_if() {
         let src = "
-async def foo():
+if any((found := pattern.search(line)) for line in lines):
     ...
 ";
-        assert_src_parses_to_expected(src, vec!["foo"]);
+        assert_src_parses_to_expected(src, vec!["found"]);
     }
 
     #[test]
-    fn basic_class() {
+    fn walrus_in_expression_statements() {
         let src = "
-class Foo(Bar, Baz):
-    x = 10
-    def bar(self, y: int) -> list[int]:
-        ...
+print(x := compute())
+flag = (a := f()) and (b := g())
+for item in (items := load()):
+    ...
+with open(path := 'data.txt') as f:
+    ...
+assert (checked := validate())
 ";
-        assert_src_parses_to_expected(src, vec!["Foo"]);
+        assert_src_parses_to_expected(
+            src,
+            vec![
+                "x", "flag", "a", "b", "item", "items", "path", "f", "checked",
+            ],
+        );
     }
 
     #[test]
-    fn basic_assignment() {
+    fn starred_unpacking() {
         let src = "
-x = 1
+first, *rest = items
+[head, [*middle, tail]] = nested
+for (key, *values) in rows:
+    ...
 ";
-        assert_src_parses_to_expected(src, vec!["x"]);
+        assert_src_parses_to_expected(
+            src,
+            vec!["first", "rest", "head", "middle", "tail", "key", "values"],
+        );
     }
 
     #[test]
-    fn multi_target_assignment() {
+    fn attribute_and_subscript_targets() {
         let src = "
-foo = bar = baz = 1
+obj.attr = 1
+d['k'] = 2
+obj.count += 1
+d['k']: int = 3
+a, obj.b = 4, 5
+[c, d['e'], *obj.rest] = values
+for obj.attr in xs:
+    ...
+for f, d[0] in pairs:
+    ...
+with ctx() as obj.handle:
+    ...
 ";
-        assert_src_parses_to_expected(src, vec!["foo", "bar", "baz"]);
+        assert_src_parses_to_expected(src, vec!["a", "c", "f"]);
     }
 
     #[test]
-    fn unpack_assignment() {
+    fn conditional_names_warned() {
         let src = "
-foo, bar = 1, 2
+import sys
+
+if sys.platform == 'win32':
+    def only_windows():
+        ...
+    both = 1
+else:
+    both = 2
+    _private = 3
+
+try:
+    import numpy
+except ImportError:
+    pass
+
+try:
+    import yaml
+except ImportError:
+    yaml = None
 ";
-        assert_src_parses_to_expected(src, vec!["foo", "bar"]);
+        let options = Options {
+            warn_conditional: true,
+            ..Default::default()
+        };
+        let mut parser = NameParser::parse(src, &options).unwrap();
+        assert_eq!(
+            parser.take_diagnostics(),
+            vec![
+                Diagnostic {
+                    line: 4,
+                    message: String::from(
+                        "`only_windows` is only defined if `sys.platform == 'win32'`"
+                    ),
+                    rule: None,
+                },
+                Diagnostic {
+                    line: 12,
+                    message: String::from(
+                        "`numpy` is only defined if the `try` block doesn't raise"
+                    ),
+                    rule: None,
+                },
+            ]
+        );
+        assert!(NameParser::parse(src, &Options::default())
+            .unwrap()
+            .take_diagnostics()
+            .is_empty());
     }
 
     #[test]
-    fn augmented_assignment() {
-        let src = "
-foo += 1
-";
-        assert_src_parses_to_expected(src, vec!["foo"]);
+    fn parse_time() {
+        take_parse_time();
+        parse_module("import os\n", &Options::default()).unwrap();
+        assert!(take_parse_time() > Duration::ZERO);
+        assert_eq!(take_parse_time(), Duration::ZERO);
     }
 
     #[test]
-    fn annotated_assignment() {
+    fn bindings_recorded() {
         let src = "
-foo: int = 1
+import os
+
+try:
+    import yaml
+except ImportError:
+    yaml = None
+
+try:
+    import toml
+except ImportError:
+    fallback = True
+    raise
 ";
-        assert_src_parses_to_expected(src, vec!["foo"]);
+        // whether or not conditional names are warned about, and without the names of a
+        // handler that raises
+        for warn_conditional in [false, true] {
+            let options = Options {
+                warn_conditional,
+                ..Default::default()
+            };
+            let parser = NameParser::parse(src, &options).unwrap();
+            assert_eq!(
+                parser.bindings,
+                ["os", "yaml", "yaml", "toml"].map(Name::from)
+            );
+        }
     }
 
     #[test]
-    fn basic_if() {
+    fn conditional_names_redefined() {
         let src = "
+if foo:
+    a = 1
+else:
+    raise ImportError
 if bar:
-    A = 1
-    def fooey():
-        ...
+    b = 1
+b = 2
 ";
-        assert_src_parses_to_expected(src, vec!["A", "fooey"]);
+        let options = Options {
+            warn_conditional: true,
+            ..Default::default()
+        };
+        let mut parser = NameParser::parse(src, &options).unwrap();
+        assert!(parser.take_diagnostics().is_empty());
     }
 
     #[test]
-    fn elif_else() {
+    fn export_decorated() {
         let src = "
-if bar:
-    A = 1
-elif fooey:
-    B = 2
-else:
-    C = 3
+from atpublic import public
+
+@public
+def foo():
+    ...
+
+@atpublic.public()
+class _Bar:
+    ...
+
+@other
+def baz():
+    ...
 ";
-        assert_src_parses_to_expected(src, vec!["A", "B", "C"]);
+        let options = Options {
+            export_decorators: vec![String::from("public")],
+            ..Default::default()
+        };
+        let mut parser = NameParser::parse(src, &options).unwrap();
+        assert_eq!(
+            parser.take_decorated(),
+            HashSet::from([String::from("foo"), String::from("_Bar")])
+        );
+        assert!(NameParser::parse(src, &Options::default())
+            .unwrap()
+            .take_decorated()
+            .is_empty());
     }
 
     #[test]
-    fn walrus_if() {
+    fn functional_type_name_mismatch() {
         let src = "
-if (foo := bar()):
-    ...
+Point = NamedTuple('Point', [('x', int)])
+Color = Enum('Colour', 'RED GREEN')
 ";
-        assert_src_parses_to_expected(src, vec!["foo"]);
+        let mut parser = NameParser::parse(src, &Options::default()).unwrap();
+        assert_eq!(
+            parser.take_diagnostics(),
+            vec![Diagnostic {
+                line: 3,
+                message: String::from(
+                    "`Color` is assigned `Enum(\"Colour\", ...)`, the names should match"
+                ),
+                rule: None,
+            }]
+        );
+        assert_eq!(
+            parser.into_iter().collect::<Names>(),
+            Names::from([String::from("Point"), String::from("Color")])
+        );
     }
 
     #[test]
@@ -471,6 +1950,45 @@ del y
         assert_src_parses_to_expected(src, vec!["x"]);
     }
 
+    #[test]
+    fn del_in_one_branch_kept() {
+        let src = "
+x = 1
+y = 2
+z = 3
+if foo:
+    del x
+    del y
+else:
+    del y
+for item in items:
+    del z
+";
+        assert_src_parses_to_expected(src, vec!["x", "z", "item"]);
+    }
+
+    #[test]
+    fn del_in_one_branch_warned() {
+        let src = "
+x = 1
+if foo:
+    del x
+";
+        let options = Options {
+            warn_conditional: true,
+            ..Default::default()
+        };
+        let mut parser = NameParser::parse(src, &options).unwrap();
+        assert_eq!(
+            parser.take_diagnostics(),
+            vec![Diagnostic {
+                line: 3,
+                message: String::from("`x` is only defined if not `foo`"),
+                rule: None,
+            }]
+        );
+    }
+
     #[test]
     fn reassigned_after_del() {
         let src = "
@@ -521,6 +2039,90 @@ except ValueError as e:
         assert_src_parses_to_expected(src, vec![]);
     }
 
+    #[test]
+    fn basic_try_except_star_else_finally() {
+        let src = "
+try:
+    intry = 1
+except* ValueError:
+    inexcept = 2
+except* (TypeError, AttributeError):
+    inexcept2 = 2.5
+else:
+    inelse = 3
+finally:
+    infinally = 4
+";
+        assert_src_parses_to_expected(
+            src,
+            vec!["intry", "inexcept", "inexcept2", "inelse", "infinally"],
+        );
+    }
+
+    #[test]
+    fn ignore_try_except_star_capture() {
+        let src = "
+try:
+    foo()
+except* ValueError as eg:
+    ...
+";
+        assert_src_parses_to_expected(src, vec![]);
+    }
+
+    #[test]
+    fn optional_dependency_alternatives() {
+        let src = "
+try:
+    import ujson as json
+except ImportError:
+    import json
+
+try:
+    from ._speedups import fast
+except ImportError:
+    from ._fallback import fast
+";
+        assert_src_parses_to_expected(src, vec!["json", "fast"]);
+    }
+
+    #[test]
+    fn optional_dependency_sentinel() {
+        let src = "
+try:
+    import numpy
+except ImportError:
+    numpy = None
+";
+        assert_src_parses_to_expected(src, vec!["numpy"]);
+    }
+
+    #[test]
+    fn required_dependency_failure_path() {
+        let src = "
+try:
+    import numpy
+except ImportError as e:
+    msg = 'numpy is required'
+    raise ImportError(msg) from e
+";
+        assert_src_parses_to_expected(src, vec!["numpy"]);
+    }
+
+    #[test]
+    fn failure_path_keeps_previous_names() {
+        let src = "
+msg = 'hello'
+try:
+    import numpy
+except ImportError:
+    msg = 'numpy is required'
+    del numpy_version
+    raise
+";
+        assert_src_parses_to_expected(src, vec!["msg", "numpy"]);
+    }
+
     #[test]
     fn comprehension_does_not_leak() {
         let src = "
@@ -565,18 +2167,168 @@ MYCONSTANT = 1
     }
 
     #[test]
-    fn star_import_not_supported_yet() {
-        // TODO: Need to extend __all__ with submodule's __all__
-        // e.g. __all__ += submodule.__all__
+    fn star_import_recorded_separately() {
         let src = "
 from submodule import *
+from .. import *
 ";
         assert_src_parses_to_expected(src, vec![]);
+
+        let star_imports = src.parse::<NameParser>().unwrap().take_star_imports();
+        assert_eq!(
+            star_imports,
+            vec![
+                StarImport {
+                    module: Some(String::from("submodule")),
+                    level: 0,
+                    offset: 1,
+                },
+                StarImport {
+                    module: None,
+                    level: 2,
+                    offset: 25,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn dunder_all_statements() {
+        let src = "
+__all__ = ['a']
+__all__ += ['b']
+__all__.append('c')
+__all__.extend(other.__all__)
+__all__.remove('a')
+";
+        let mut parser = NameParser::parse(src, &Options::default()).unwrap();
+        let kinds = parser
+            .take_dunder_all_statements()
+            .into_iter()
+            .map(|statement| statement.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                DunderAllKind::Assign,
+                DunderAllKind::Add(Some(vec![String::from("b")])),
+                DunderAllKind::Add(Some(vec![String::from("c")])),
+                DunderAllKind::Add(None),
+                DunderAllKind::Remove(Some(vec![String::from("a")])),
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_recorded() {
+        let src = "
+from .utils import helper as _helper, other
+try:
+    from ._speedups import fast
+except ImportError:
+    fast = None
+";
+        let mut parser = NameParser::parse(src, &Options::default()).unwrap();
+        assert_eq!(
+            parser.take_imports(),
+            vec![ImportFrom {
+                module: Some(String::from("utils")),
+                level: 1,
+                names: vec![String::from("helper"), String::from("other")],
+                offset: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn definitions_recorded() {
+        let src = "
+import os
+class Foo: ...
+if os.name == 'nt':
+    def bar(): ...
+type Alias = int
+x = 1
+";
+        let mut parser = NameParser::parse(src, &Options::default()).unwrap();
+        let definitions = parser.take_definitions();
+        let definition = |name: &str| definitions.get(name).copied();
+        assert_eq!(
+            definition("os"),
+            Some(Definition {
+                kind: Kind::Import,
+                offset: 1,
+                end: 10,
+            })
+        );
+        assert_eq!(
+            definition("Foo"),
+            Some(Definition {
+                kind: Kind::Class,
+                offset: 11,
+                end: 25,
+            })
+        );
+        assert_eq!(definition("bar").map(|d| d.kind), Some(Kind::Function));
+        assert_eq!(definition("Alias").map(|d| d.kind), Some(Kind::TypeAlias));
+        assert_eq!(definition("x").map(|d| d.kind), Some(Kind::Variable));
     }
 
     #[test]
-    fn truthy_falsey_checks_not_supported_yet() {
-        // TODO: Some simple cases could be detected and ignored
+    fn skipped_recorded() {
+        let src = "
+from typing import TYPE_CHECKING
+if TYPE_CHECKING:
+    from os import PathLike
+if __name__ == '__main__':
+    main = 1
+gone = 1
+del gone
+type Alias = int
+";
+        let options = Options::builder().type_aliases(false).build();
+        let mut parser = NameParser::parse(src, &options).unwrap();
+        let skipped = parser.take_skipped();
+        let skipped = |name: &str| skipped.get(name).copied();
+        assert_eq!(
+            skipped("PathLike"),
+            Some(Skipped {
+                reason: Skip::TypeChecking,
+                offset: src.find("from os").unwrap(),
+            })
+        );
+        assert_eq!(skipped("main").map(|s| s.reason), Some(Skip::Unreachable));
+        assert_eq!(
+            skipped("gone"),
+            Some(Skipped {
+                reason: Skip::Deleted,
+                offset: src.find("del").unwrap(),
+            })
+        );
+        assert_eq!(skipped("Alias").map(|s| s.reason), Some(Skip::TypeAlias));
+        assert_eq!(skipped("TYPE_CHECKING"), None);
+        assert!(!parser.contains("gone"));
+    }
+
+    #[test]
+    fn literal_dunder_all() {
+        let src = "
+__all__ = ['foo', \"bar\"]
+";
+        let parser = src.parse::<NameParser>().unwrap();
+        assert_eq!(
+            parser.dunder_all(),
+            Some(&[String::from("foo"), String::from("bar")][..])
+        );
+
+        let src = "
+__all__ = ['foo'] + other.__all__
+";
+        assert_eq!(src.parse::<NameParser>().unwrap().dunder_all(), None);
+    }
+
+    #[test]
+    fn truthy_falsey_checks() {
         let src = "
 if True:
     ...
@@ -586,7 +2338,36 @@ else:
 if False:
     y = 2
 ";
-        assert_src_parses_to_expected(src, vec!["x", "y"]);
+        assert_src_parses_to_expected(src, vec![]);
+    }
+
+    #[test]
+    fn constant_int_and_not_checks() {
+        let src = "
+if 0:
+    a = 1
+elif not 1:
+    b = 2
+else:
+    c = 3
+
+if not False:
+    d = 4
+else:
+    e = 5
+";
+        assert_src_parses_to_expected(src, vec!["c", "d"]);
+    }
+
+    #[test]
+    fn while_false_body_skipped() {
+        let src = "
+while False:
+    a = 1
+else:
+    b = 2
+";
+        assert_src_parses_to_expected(src, vec!["b"]);
     }
 
     #[test]
@@ -603,12 +2384,67 @@ except TypeError:
     }
 
     #[test]
-    fn unreachable_code_not_supported_yet() {
-        // TODO: Could see that we raise before assignment
+    fn unreachable_after_assert_false() {
         let src = "
 assert False
 x = 1
 ";
-        assert_src_parses_to_expected(src, vec!["x"]);
+        assert_src_parses_to_expected(src, vec![]);
+    }
+
+    #[test]
+    fn unreachable_after_raise() {
+        let src = "
+a = 1
+raise ImportError('Unsupported platform')
+b = 2
+";
+        assert_src_parses_to_expected(src, vec!["a"]);
+    }
+
+    #[test]
+    fn unreachable_after_exit() {
+        let src = "
+import sys
+sys.exit(1)
+b = 2
+";
+        assert_src_parses_to_expected(src, vec!["sys"]);
+    }
+
+    #[test]
+    fn unreachable_after_raise_in_every_branch() {
+        let src = "
+if foo:
+    raise ValueError
+else:
+    raise TypeError
+b = 2
+";
+        assert_src_parses_to_expected(src, vec![]);
+    }
+
+    #[test]
+    fn reachable_after_raise_in_one_branch() {
+        let src = "
+if foo:
+    raise ValueError
+    a = 1
+b = 2
+";
+        assert_src_parses_to_expected(src, vec!["b"]);
+    }
+
+    #[test]
+    fn reachable_after_caught_raise() {
+        let src = "
+try:
+    raise ValueError
+    a = 1
+except ValueError:
+    b = 2
+c = 3
+";
+        assert_src_parses_to_expected(src, vec!["b", "c"]);
     }
 }
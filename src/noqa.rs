@@ -0,0 +1,221 @@
+use std::collections::BTreeSet;
+
+use crate::comments::comments;
+use crate::diagnostic::line_number;
+use crate::diagnostic::Diagnostic;
+use crate::rules::Rule;
+
+const NOQA_COMMENT: &str = "# allways: noqa";
+
+/// A `# allways: noqa ALW003` comment, suppressing diagnostics on its line.
+struct Suppression {
+    line: usize,
+    /// Suppressed rules, every rule when there are no codes at all.
+    rules: Vec<Rule>,
+    /// Codes of no rule, which suppress nothing.
+    unknown: Vec<String>,
+    used: BTreeSet<Rule>,
+}
+
+impl Suppression {
+    fn suppresses(&self, rule: Rule) -> bool {
+        (self.rules.is_empty() && self.unknown.is_empty()) || self.rules.contains(&rule)
+    }
+}
+
+/// Drop the diagnostics suppressed by a `# allways: noqa` comment on their line.
+///
+/// Comments that don't suppress anything are reported as `Rule::UnusedNoqa` when `enabled`.
+pub fn suppress(
+    src: &str,
+    diagnostics: Vec<Diagnostic>,
+    enabled: impl Fn(Rule) -> bool,
+) -> Vec<Diagnostic> {
+    let mut suppressions = suppressions(src);
+    let mut kept = Vec::new();
+    for diagnostic in diagnostics {
+        let suppression = diagnostic.rule.and_then(|rule| {
+            suppressions
+                .iter_mut()
+                .find(|suppression| suppression.line == diagnostic.line)
+                .filter(|suppression| suppression.suppresses(rule))
+                .map(|suppression| suppression.used.insert(rule))
+        });
+        if suppression.is_none() {
+            kept.push(diagnostic);
+        }
+    }
+    if enabled(Rule::UnusedNoqa) {
+        kept.extend(suppressions.iter().filter_map(unused_diagnostic));
+        kept.sort_by_key(|diagnostic| diagnostic.line);
+    }
+    kept
+}
+
+/// Whether a `# allways: noqa` comment on `line` suppresses `rule`, e.g. to leave out its fix.
+pub(crate) fn is_suppressed(src: &str, line: usize, rule: Rule) -> bool {
+    suppressions(src)
+        .iter()
+        .any(|suppression| suppression.line == line && suppression.suppresses(rule))
+}
+
+/// The `# allways: noqa` comments of `src`, those in strings left out.
+///
+/// The codes end at the first word that doesn't look like one, e.g. an explanation following
+/// them.
+fn suppressions(src: &str) -> Vec<Suppression> {
+    comments(src)
+        .into_iter()
+        .filter_map(|comment| {
+            let (_, codes) = src[comment.clone()].split_once(NOQA_COMMENT)?;
+            let mut rules = Vec::new();
+            let mut unknown = Vec::new();
+            for code in codes
+                .trim_start_matches(':')
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|code| !code.is_empty())
+                .take_while(|code| is_code(code))
+            {
+                match code.parse() {
+                    Ok(rule) => rules.push(rule),
+                    Err(_) => unknown.push(code.to_string()),
+                }
+            }
+            Some(Suppression {
+                line: line_number(src, comment.start),
+                rules,
+                unknown,
+                used: BTreeSet::new(),
+            })
+        })
+        .collect()
+}
+
+/// Whether `word` looks like a rule code, letters followed by digits, e.g. `ALW003` or `E501`.
+fn is_code(word: &str) -> bool {
+    let digits = word.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    digits.len() < word.len() && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+fn unused_diagnostic(suppression: &Suppression) -> Option<Diagnostic> {
+    let message = if suppression.rules.is_empty() && suppression.unknown.is_empty() {
+        if !suppression.used.is_empty() {
+            return None;
+        }
+        format!("`{NOQA_COMMENT}` comment doesn't suppress anything")
+    } else {
+        let unused = suppression
+            .rules
+            .iter()
+            .filter(|rule| !suppression.used.contains(rule))
+            .map(|rule| rule.code())
+            .chain(suppression.unknown.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        if unused.is_empty() {
+            return None;
+        }
+        format!(
+            "`{NOQA_COMMENT}` comment doesn't suppress {}",
+            unused.join(", ")
+        )
+    };
+    Some(Diagnostic {
+        line: suppression.line,
+        message,
+        rule: Some(Rule::UnusedNoqa),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(line: usize, rule: Rule) -> Diagnostic {
+        Diagnostic {
+            line,
+            message: String::new(),
+            rule: Some(rule),
+        }
+    }
+
+    #[test]
+    fn suppressed_by_code() {
+        let src = "
+__all__ = ['b', 'a']  # allways: noqa ALW004
+";
+        let diagnostics = vec![lint(2, Rule::UnknownName), lint(2, Rule::UnsortedAll)];
+        assert_eq!(
+            suppress(src, diagnostics, |_| true),
+            vec![lint(2, Rule::UnknownName)]
+        );
+    }
+
+    #[test]
+    fn bare_suppresses_all() {
+        let src = "
+__all__ = ['b', 'a']  # allways: noqa
+";
+        let diagnostics = vec![lint(2, Rule::UnknownName), lint(2, Rule::UnsortedAll)];
+        assert!(suppress(src, diagnostics, |_| true).is_empty());
+    }
+
+    #[test]
+    fn unused_suppressions() {
+        let src = "
+import os  # allways: noqa
+__all__ = ['os']  # allways: noqa: ALW003, ALW004
+";
+        let diagnostics = vec![lint(3, Rule::UnsortedAll)];
+        let suppressed = suppress(src, diagnostics, |_| true);
+        assert_eq!(
+            suppressed
+                .iter()
+                .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, "`# allways: noqa` comment doesn't suppress anything"),
+                (3, "`# allways: noqa` comment doesn't suppress ALW003"),
+            ]
+        );
+        assert!(suppress(src, Vec::new(), |rule| rule != Rule::UnusedNoqa).is_empty());
+    }
+
+    #[test]
+    fn comments_only() {
+        let src = "
+__all__ = ['b', 'a', '# allways: noqa']
+";
+        let diagnostics = vec![lint(2, Rule::UnsortedAll)];
+        assert_eq!(suppress(src, diagnostics.clone(), |_| true), diagnostics);
+        assert!(!is_suppressed(src, 2, Rule::UnsortedAll));
+    }
+
+    #[test]
+    fn unknown_codes() {
+        let src = "
+__all__ = ['b', 'a']  # allways: noqa FOO123, ALW004 sorted by hand
+__all__ = ['b', 'a']  # allways: noqa FOO123
+";
+        let diagnostics = vec![
+            lint(2, Rule::UnknownName),
+            lint(2, Rule::UnsortedAll),
+            lint(3, Rule::UnsortedAll),
+        ];
+        let kept = suppress(src, diagnostics, |_| true);
+        assert_eq!(
+            kept.iter()
+                .map(|diagnostic| (diagnostic.line, diagnostic.rule))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, Some(Rule::UnknownName)),
+                (2, Some(Rule::UnusedNoqa)),
+                (3, Some(Rule::UnsortedAll)),
+                (3, Some(Rule::UnusedNoqa)),
+            ]
+        );
+        assert_eq!(
+            kept[1].message,
+            "`# allways: noqa` comment doesn't suppress FOO123"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::add_all::do_it_allways;
+use crate::add_all::get_file_state;
+use crate::add_all::FileState;
+use crate::add_all::Outcome;
+use crate::check::check;
+use crate::diagnostic::Diagnostic;
+use crate::options::Options;
+
+/// Id of the cell holding the allways block, for notebook formats requiring cell ids.
+const CELL_ID: &str = "allways";
+
+/// Whether `path` is a Jupyter notebook.
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "ipynb")
+}
+
+/// The code cells of a notebook, concatenated into a single python module.
+struct Notebook {
+    json: Value,
+    python: String,
+    cells: Vec<CodeCell>,
+}
+
+struct CodeCell {
+    /// Index of the cell among all cells of the notebook.
+    index: usize,
+    /// 1-based line of `python` the cell starts at.
+    line: usize,
+    source: String,
+}
+
+impl Notebook {
+    fn parse(src: &str) -> Result<Self> {
+        let json = serde_json::from_str::<Value>(src)?;
+        let cells = json
+            .get("cells")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("Notebook has no cells"))?;
+        let mut python = String::new();
+        let mut code_cells = Vec::new();
+        for (index, cell) in cells.iter().enumerate() {
+            if cell.get("cell_type").and_then(Value::as_str) != Some("code") {
+                continue;
+            }
+            let source = cell_source(cell);
+            let line = python.lines().count() + 1;
+            python.push_str(&mask_magics(&source));
+            if !python.is_empty() && !python.ends_with('\n') {
+                python.push('\n');
+            }
+            code_cells.push(CodeCell {
+                index,
+                line,
+                source,
+            });
+        }
+        Ok(Self {
+            json,
+            python,
+            cells: code_cells,
+        })
+    }
+
+    /// `diagnostic` of the concatenated module, pointing at a line of the cell it is in.
+    fn locate(&self, mut diagnostic: Diagnostic) -> Diagnostic {
+        if let Some(cell) = self
+            .cells
+            .iter()
+            .rev()
+            .find(|cell| cell.line <= diagnostic.line)
+        {
+            diagnostic.line = diagnostic.line - cell.line + 1;
+            diagnostic.message = format!("cell {}: {}", cell.index + 1, diagnostic.message);
+        }
+        diagnostic
+    }
+
+    /// The notebook with the allways block of its cell replaced by `block`, or a new cell
+    /// holding it appended. `None` when the block is up to date.
+    fn with_block(mut self, block: &str) -> Result<Option<String>> {
+        let existing = self
+            .cells
+            .iter()
+            .find_map(|cell| match get_file_state(&cell.source) {
+                FileState::YesAll(start, end) => Some((cell, start, end)),
+                FileState::NoAll => None,
+            });
+        let (index, source) = match existing {
+            Some((cell, start, end)) => {
+                let mut source = cell.source.clone();
+                // Jupyter drops the newline ending the last line of a cell
+                let block = if end == source.len() && !source.ends_with('\n') {
+                    block.trim_end_matches('\n')
+                } else {
+                    block
+                };
+                source.replace_range(start..end, block);
+                if source == cell.source {
+                    return Ok(None);
+                }
+                (Some(cell.index), source)
+            }
+            None => (None, block.trim_end_matches('\n').to_string()),
+        };
+        let with_ids = self.json.get("nbformat_minor").and_then(Value::as_u64) >= Some(5);
+        let cells = self
+            .json
+            .get_mut("cells")
+            .and_then(Value::as_array_mut)
+            .ok_or_else(|| anyhow!("Notebook has no cells"))?;
+        let source = source_lines(&source);
+        match index {
+            Some(index) => cells[index]["source"] = source,
+            None => {
+                let mut cell = serde_json::json!({
+                    "cell_type": "code",
+                    "execution_count": null,
+                    "metadata": {},
+                    "outputs": [],
+                    "source": source,
+                });
+                if with_ids {
+                    cell["id"] = Value::from(CELL_ID);
+                }
+                cells.push(cell);
+            }
+        }
+        self.serialize().map(Some)
+    }
+
+    /// The notebook formatted the way Jupyter writes it: one space indents, sorted keys and a
+    /// final newline.
+    fn serialize(&self) -> Result<String> {
+        let mut json = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+        self.json.serialize(&mut serializer)?;
+        json.push(b'\n');
+        Ok(String::from_utf8(json)?)
+    }
+}
+
+/// The code cells of the notebook `src` concatenated into a python module.
+pub(crate) fn notebook_python(src: &str) -> Result<String> {
+    Ok(Notebook::parse(src)?.python)
+}
+
+/// Compute the updated notebook `src`, exporting the public names of its code cells from the
+/// cell holding the allways block, which is appended when missing.
+pub fn do_it_allways_notebook(
+    src: &str,
+    path: Option<&Path>,
+    options: &Options,
+) -> Result<Outcome> {
+    let notebook = Notebook::parse(src)?;
+    let outcome = do_it_allways(&notebook.python, path, options)?;
+    let diagnostics = outcome
+        .diagnostics
+        .into_iter()
+        .map(|diagnostic| notebook.locate(diagnostic))
+        .collect();
+    let block = outcome
+        .src
+        .and_then(|python| match get_file_state(&python) {
+            FileState::YesAll(start, end) => Some(python[start..end].to_string()),
+            FileState::NoAll => None,
+        });
+    let src = match block {
+        Some(block) => Some(
+            notebook
+                .with_block(&block)?
+                .unwrap_or_else(|| src.to_string()),
+        ),
+        None => None,
+    };
+    Ok(Outcome { src, diagnostics })
+}
+
+/// Lint the code cells of the notebook `src` as a single module.
+pub fn check_notebook(
+    src: &str,
+    path: Option<&Path>,
+    options: &Options,
+) -> Result<Vec<Diagnostic>> {
+    let notebook = Notebook::parse(src)?;
+    Ok(check(&notebook.python, path, options)?
+        .into_iter()
+        .map(|diagnostic| notebook.locate(diagnostic))
+        .collect())
+}
+
+/// The source of a cell, stored either as a string or a list of lines.
+fn cell_source(cell: &Value) -> String {
+    match cell.get("source") {
+        Some(Value::String(source)) => source.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn source_lines(source: &str) -> Value {
+    Value::from(source.split_inclusive('\n').collect::<Vec<_>>())
+}
+
+/// IPython magics (`%time`, `!pip install ...`) and cells of cell magics (`%%bash`) aren't
+/// python, they are commented out keeping the line numbers.
+fn mask_magics(source: &str) -> String {
+    let cell_magic = source.trim_start().starts_with("%%");
+    source
+        .split_inclusive('\n')
+        .map(|line| {
+            let trimmed = line.trim_start();
+            if cell_magic || trimmed.starts_with('%') || trimmed.starts_with('!') {
+                format!("# {trimmed}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notebook(cells: &[(&str, &str)]) -> String {
+        let cells = cells
+            .iter()
+            .map(|(cell_type, source)| {
+                serde_json::json!({
+                    "cell_type": cell_type,
+                    "metadata": {},
+                    "source": source_lines(source),
+                })
+            })
+            .collect::<Vec<_>>();
+        let notebook = Notebook {
+            json: serde_json::json!({
+                "cells": cells,
+                "metadata": {},
+                "nbformat": 4,
+                "nbformat_minor": 4,
+            }),
+            python: String::new(),
+            cells: Vec::new(),
+        };
+        notebook.serialize().unwrap()
+    }
+
+    fn sources(src: &str) -> Vec<String> {
+        let json = serde_json::from_str::<Value>(src).unwrap();
+        json["cells"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(cell_source)
+            .collect()
+    }
+
+    #[test]
+    fn appends_allways_cell() {
+        let src = notebook(&[
+            ("markdown", "# Title\n"),
+            ("code", "%matplotlib inline\nimport os\n"),
+            ("code", "def foo():\n    ...\n"),
+        ]);
+        let outcome = do_it_allways_notebook(&src, None, &Options::default()).unwrap();
+        assert_eq!(
+            sources(&outcome.src.unwrap()),
+            vec![
+                "# Title\n",
+                "%matplotlib inline\nimport os\n",
+                "def foo():\n    ...\n",
+                "# allways: start\n__all__ = [\n    \"foo\",\n    \"os\",\n]\n# allways: end",
+            ]
+        );
+    }
+
+    #[test]
+    fn updates_allways_cell() {
+        let src = notebook(&[
+            ("code", "def foo():\n    ...\n"),
+            ("code", "# allways: start\n__all__ = []\n# allways: end"),
+            ("code", "bar = 1\n"),
+        ]);
+        let outcome = do_it_allways_notebook(&src, None, &Options::default()).unwrap();
+        assert_eq!(
+            sources(&outcome.src.unwrap())[1],
+            "# allways: start\n__all__ = [\n    \"bar\",\n    \"foo\",\n]\n# allways: end"
+        );
+
+        let fixed = do_it_allways_notebook(&src, None, &Options::default())
+            .unwrap()
+            .src
+            .unwrap();
+        let outcome = do_it_allways_notebook(&fixed, None, &Options::default()).unwrap();
+        assert_eq!(outcome.src.unwrap(), fixed);
+    }
+
+    #[test]
+    fn diagnostics_point_at_cells() {
+        let src = notebook(&[
+            ("code", "import os\n"),
+            ("code", "x = 1\n__all__ = ['x', 'y']\n"),
+        ]);
+        let diagnostics = check_notebook(&src, None, &Options::default()).unwrap();
+        assert!(diagnostics.iter().any(|diagnostic| diagnostic.line == 2
+            && diagnostic.message == "cell 2: `y` is listed in `__all__` but never defined"));
+    }
+}
@@ -0,0 +1,778 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::editorconfig;
+use crate::filter::NameFilter;
+use crate::glob;
+use crate::name_parser::Kind;
+use crate::rules::RuleSettings;
+
+/// Knobs controlling which names end up in the generated `__all__`.
+///
+/// Built with [`Options::builder`] outside of this crate, new options may be added at any time.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Options {
+    /// Export names bound by PEP 695 `type X = ...` statements, and by `X: TypeAlias = ...`
+    /// when `target_version` is before 3.12.
+    pub type_aliases: bool,
+    /// Skip top-level statements that fail to parse instead of failing the whole file.
+    pub lenient: bool,
+    /// Export names only bound inside `if TYPE_CHECKING:` blocks.
+    pub type_checking: bool,
+    /// Minimum supported python version, used to skip branches guarded by `sys.version_info`.
+    /// Syntax it doesn't accept is an error, or a skipped statement when `lenient`.
+    pub target_version: Option<PythonVersion>,
+    /// Extend `__all__` with the `__all__` of star-imported modules that can't be found on disk.
+    pub star_import_fallback: bool,
+    /// Export names functions declare `global` and then bind.
+    pub global_declarations: bool,
+    /// Warn about public names only bound on some paths through an `if` or `try` statement.
+    pub warn_conditional: bool,
+    /// Decorators marking functions and classes for export, e.g. `public` for `@public`.
+    pub export_decorators: Vec<String>,
+    /// Export only functions and classes marked with one of `export_decorators`.
+    pub decorated_only: bool,
+    /// Lint rules reported by `check`.
+    pub rules: RuleSettings,
+    /// Create a missing `__init__.py` for PEP 420 namespace packages instead of skipping them.
+    pub create_init: bool,
+    /// End files with a newline, even when they didn't before.
+    pub final_newline: bool,
+    /// Blank lines before a new allways block, by default as many as the file separates
+    /// statements with.
+    pub blank_lines: Option<usize>,
+    /// Indentation of the names in the allways block.
+    pub indent: String,
+    /// Take the indentation and final newline of files from the `.editorconfig` files that
+    /// apply to them.
+    pub editorconfig: bool,
+    /// How the `__all__` assignment of the block is laid out.
+    pub block_style: BlockStyle,
+    /// The longest line `BlockStyle::Black` puts `__all__` on.
+    pub line_length: usize,
+    /// Where a new allways block is inserted.
+    pub placement: Placement,
+    /// Sibling module of package `__init__.py` files, e.g. `_allways_exports`, that `__all__`
+    /// is generated into and imported from, keeping the `__init__.py` allways block short.
+    pub exports_module: Option<ModuleName>,
+    /// Export names only bound when a test on the platform holds, e.g.
+    /// `if sys.platform == "win32":`, in matching `if` statements of the allways block.
+    pub platform_conditional: bool,
+    /// Embed a hash of the allways block in it, `check` reports blocks edited since.
+    pub block_hash: bool,
+    /// The layout of the allways block, by default its start marker, header, `__all__` and
+    /// end marker.
+    pub block_template: Option<BlockTemplate>,
+    /// Comment lines starting the allways block, after its start marker, e.g. `This list is
+    /// generated by allways; edit source definitions instead.`, `# ` being added to lines
+    /// without it.
+    pub block_header: Option<BlockHeader>,
+    /// The names each named section, e.g. `# allways: start(api)`, exports by section name.
+    /// Sections missing here export every name no other section does.
+    pub sections: BTreeMap<String, Section>,
+    /// Sort the names of the allways block, otherwise an existing block keeps the order of the
+    /// names it lists and new names are appended to them.
+    pub sort: bool,
+    /// Glob patterns of files and directories skipped while looking for packages, e.g. `build`
+    /// or `tests/*.py`, matched against the end of their path.
+    pub exclude: Vec<String>,
+    /// Apply `exclude` to files passed explicitly too, not only to those found in directories.
+    pub force_exclude: bool,
+    /// Skip hidden directories, virtual environments, caches and build output while looking for
+    /// packages, see [`DEFAULT_EXCLUDE`](crate::discover::DEFAULT_EXCLUDE). `exclude` extends
+    /// them.
+    pub default_exclude: bool,
+    /// Leave files without allways markers, or named sections, untouched instead of adding a block.
+    pub require_markers: bool,
+    /// Walk and modify virtual environments, directories holding a `pyvenv.cfg`, and `site-packages`
+    /// directories too, which hold installed packages.
+    pub include_site_packages: bool,
+    /// Replace a list or tuple assigned to `__all__` by hand, in a module without allways markers,
+    /// by an allways block, keeping the entries that aren't string literals.
+    pub adopt: bool,
+    /// Fix the problems of the lint rules with an automatic fix, e.g. private names listed in
+    /// `__all__`, in the files allways updates.
+    pub fix_lints: bool,
+    /// Empty the allways block of a module without public names, keeping its markers, rather than
+    /// removing it.
+    pub keep_empty_block: bool,
+    /// Last say on which public names are exported, only available to library users.
+    pub name_filter: Option<Arc<dyn NameFilter>>,
+    /// The directory of the project, that of the root config, above which imports aren't
+    /// resolved. The current directory when `None`.
+    pub root: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            type_aliases: true,
+            lenient: false,
+            type_checking: false,
+            target_version: None,
+            star_import_fallback: false,
+            global_declarations: false,
+            warn_conditional: false,
+            export_decorators: Vec::new(),
+            decorated_only: false,
+            rules: RuleSettings::default(),
+            create_init: false,
+            final_newline: true,
+            blank_lines: None,
+            indent: String::from("    "),
+            editorconfig: true,
+            block_style: BlockStyle::default(),
+            line_length: 88,
+            placement: Placement::default(),
+            exports_module: None,
+            platform_conditional: false,
+            block_hash: false,
+            block_template: None,
+            block_header: None,
+            sections: BTreeMap::new(),
+            sort: true,
+            exclude: Vec::new(),
+            force_exclude: false,
+            default_exclude: true,
+            require_markers: false,
+            include_site_packages: false,
+            adopt: false,
+            fix_lints: false,
+            keep_empty_block: false,
+            name_filter: None,
+            root: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    /// These options as they apply to the python module at `path`, its `.editorconfig`
+    /// settings included.
+    pub fn for_path(&self, path: &Path) -> anyhow::Result<Options> {
+        Ok(editorconfig::options_for(Some(path), self)?.into_owned())
+    }
+
+    /// `root`, or else the current directory.
+    pub fn project_root(&self) -> PathBuf {
+        match &self.root {
+            Some(root) => root.clone(),
+            None => std::env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    /// Whether `path`, or a directory it is in, matches one of the `exclude` patterns.
+    ///
+    /// Only the part of `path` below the project root is matched, the directories the project is
+    /// in never exclude it.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        if self.exclude.is_empty() {
+            return false;
+        }
+        let absolute = match std::env::current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => path.to_path_buf(),
+        };
+        let relative = absolute.strip_prefix(self.project_root()).unwrap_or(path);
+        relative.ancestors().any(|path| {
+            self.exclude
+                .iter()
+                .any(|pattern| glob::matches_path(pattern, path))
+        })
+    }
+}
+
+/// Builds [`Options`], starting from the defaults.
+#[derive(Clone, Debug, Default)]
+pub struct OptionsBuilder {
+    options: Options,
+}
+
+impl OptionsBuilder {
+    pub fn type_aliases(mut self, type_aliases: bool) -> Self {
+        self.options.type_aliases = type_aliases;
+        self
+    }
+
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.options.lenient = lenient;
+        self
+    }
+
+    pub fn type_checking(mut self, type_checking: bool) -> Self {
+        self.options.type_checking = type_checking;
+        self
+    }
+
+    pub fn target_version(mut self, target_version: PythonVersion) -> Self {
+        self.options.target_version = Some(target_version);
+        self
+    }
+
+    pub fn star_import_fallback(mut self, star_import_fallback: bool) -> Self {
+        self.options.star_import_fallback = star_import_fallback;
+        self
+    }
+
+    pub fn global_declarations(mut self, global_declarations: bool) -> Self {
+        self.options.global_declarations = global_declarations;
+        self
+    }
+
+    pub fn warn_conditional(mut self, warn_conditional: bool) -> Self {
+        self.options.warn_conditional = warn_conditional;
+        self
+    }
+
+    pub fn export_decorators<I, S>(mut self, export_decorators: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.export_decorators = export_decorators.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn decorated_only(mut self, decorated_only: bool) -> Self {
+        self.options.decorated_only = decorated_only;
+        self
+    }
+
+    pub fn rules(mut self, rules: RuleSettings) -> Self {
+        self.options.rules = rules;
+        self
+    }
+
+    pub fn create_init(mut self, create_init: bool) -> Self {
+        self.options.create_init = create_init;
+        self
+    }
+
+    pub fn final_newline(mut self, final_newline: bool) -> Self {
+        self.options.final_newline = final_newline;
+        self
+    }
+
+    pub fn blank_lines(mut self, blank_lines: usize) -> Self {
+        self.options.blank_lines = Some(blank_lines);
+        self
+    }
+
+    pub fn indent(mut self, indent: impl Into<String>) -> Self {
+        self.options.indent = indent.into();
+        self
+    }
+
+    pub fn editorconfig(mut self, editorconfig: bool) -> Self {
+        self.options.editorconfig = editorconfig;
+        self
+    }
+
+    pub fn block_style(mut self, block_style: BlockStyle) -> Self {
+        self.options.block_style = block_style;
+        self
+    }
+
+    pub fn block_template(mut self, block_template: BlockTemplate) -> Self {
+        self.options.block_template = Some(block_template);
+        self
+    }
+
+    pub fn block_header(mut self, block_header: BlockHeader) -> Self {
+        self.options.block_header = Some(block_header);
+        self
+    }
+
+    pub fn line_length(mut self, line_length: usize) -> Self {
+        self.options.line_length = line_length;
+        self
+    }
+
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.options.placement = placement;
+        self
+    }
+
+    pub fn exports_module(mut self, exports_module: ModuleName) -> Self {
+        self.options.exports_module = Some(exports_module);
+        self
+    }
+
+    pub fn platform_conditional(mut self, platform_conditional: bool) -> Self {
+        self.options.platform_conditional = platform_conditional;
+        self
+    }
+
+    pub fn block_hash(mut self, block_hash: bool) -> Self {
+        self.options.block_hash = block_hash;
+        self
+    }
+
+    pub fn section(mut self, name: impl Into<String>, section: Section) -> Self {
+        self.options.sections.insert(name.into(), section);
+        self
+    }
+
+    pub fn sort(mut self, sort: bool) -> Self {
+        self.options.sort = sort;
+        self
+    }
+
+    pub fn exclude<I, S>(mut self, exclude: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.exclude = exclude.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn force_exclude(mut self, force_exclude: bool) -> Self {
+        self.options.force_exclude = force_exclude;
+        self
+    }
+
+    pub fn default_exclude(mut self, default_exclude: bool) -> Self {
+        self.options.default_exclude = default_exclude;
+        self
+    }
+
+    pub fn require_markers(mut self, require_markers: bool) -> Self {
+        self.options.require_markers = require_markers;
+        self
+    }
+
+    pub fn include_site_packages(mut self, include_site_packages: bool) -> Self {
+        self.options.include_site_packages = include_site_packages;
+        self
+    }
+
+    pub fn adopt(mut self, adopt: bool) -> Self {
+        self.options.adopt = adopt;
+        self
+    }
+
+    pub fn fix_lints(mut self, fix_lints: bool) -> Self {
+        self.options.fix_lints = fix_lints;
+        self
+    }
+
+    pub fn keep_empty_block(mut self, keep_empty_block: bool) -> Self {
+        self.options.keep_empty_block = keep_empty_block;
+        self
+    }
+
+    pub fn name_filter(mut self, name_filter: impl NameFilter + 'static) -> Self {
+        self.options.name_filter = Some(Arc::new(name_filter));
+        self
+    }
+
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.options.root = Some(root.into());
+        self
+    }
+
+    pub fn build(self) -> Options {
+        self.options
+    }
+}
+
+/// The names a named section of allways blocks exports, see `Options::sections`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Section {
+    /// Patterns the names match, e.g. `*_VERSION`, any name when empty.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Kinds of statement binding the names, star-imported names being imports, any kind
+    /// when empty.
+    #[serde(default)]
+    pub kinds: Vec<Kind>,
+}
+
+impl Section {
+    /// Whether the section exports every name no other section does.
+    pub(crate) fn is_catch_all(&self) -> bool {
+        self.include.is_empty() && self.kinds.is_empty()
+    }
+
+    pub(crate) fn matches(&self, name: &str, kind: Kind) -> bool {
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| glob::matches(pattern, name));
+        included && (self.kinds.is_empty() || self.kinds.contains(&kind))
+    }
+}
+
+/// How the `__all__` assignment of the allways block is laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlockStyle {
+    /// One name per line, whatever their number.
+    #[default]
+    Allways,
+    /// What black formats the assignment to: on one line when it fits in the line length,
+    /// otherwise one name per line, indented with 4 spaces.
+    Black,
+}
+
+impl FromStr for BlockStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allways" => Ok(Self::Allways),
+            "black" => Ok(Self::Black),
+            _ => Err(format!(
+                "Invalid block style {s:?}, expected \"allways\" or \"black\""
+            )),
+        }
+    }
+}
+
+/// Where a new allways block is inserted, existing ones are updated where they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// At the end of the module.
+    #[default]
+    End,
+    /// After the import section, as isort delimits it.
+    AfterImports,
+}
+
+impl FromStr for Placement {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "end" => Ok(Self::End),
+            "after-imports" => Ok(Self::AfterImports),
+            _ => Err(format!(
+                "Invalid placement {s:?}, expected \"end\" or \"after-imports\""
+            )),
+        }
+    }
+}
+
+/// The layout of the allways block, e.g. to add the comments a codegen policy requires.
+///
+/// Lines holding only `{start_marker}`, `{header}`, `{names}` or `{end_marker}` stand for the
+/// markers, the header, made of `Options::block_header` and the hash line of
+/// `Options::block_hash`, and `__all__`. They are dropped when what
+/// they stand for is empty, the other lines are copied as they are. The template starts with
+/// the start marker and ends with the end marker, so that the block can be found again.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct BlockTemplate(String);
+
+/// A line of a [`BlockTemplate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TemplateLine<'a> {
+    StartMarker,
+    Header,
+    Names,
+    EndMarker,
+    Text(&'a str),
+}
+
+impl BlockTemplate {
+    const PLACEHOLDERS: [(&'static str, TemplateLine<'static>); 4] = [
+        ("{start_marker}", TemplateLine::StartMarker),
+        ("{header}", TemplateLine::Header),
+        ("{names}", TemplateLine::Names),
+        ("{end_marker}", TemplateLine::EndMarker),
+    ];
+
+    /// The lines of the template, without the line ending of the last one.
+    pub(crate) fn lines(&self) -> impl Iterator<Item = TemplateLine<'_>> {
+        self.0.lines().map(|line| {
+            Self::PLACEHOLDERS
+                .into_iter()
+                .find(|(placeholder, _)| line.trim() == *placeholder)
+                .map_or(TemplateLine::Text(line), |(_, kind)| kind)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for BlockTemplate {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let template = Self(s.to_string());
+        let invalid = |problem: &str| format!("Invalid block template {s:?}, {problem}");
+        let lines = template.lines().collect::<Vec<_>>();
+        for (placeholder, kind) in Self::PLACEHOLDERS {
+            match lines.iter().filter(|line| **line == kind).count() {
+                // without it the header goes after the start marker
+                0 if kind == TemplateLine::Header => {}
+                1 => {}
+                0 => return Err(invalid(&format!("{placeholder} is missing"))),
+                _ => return Err(invalid(&format!("{placeholder} is repeated"))),
+            }
+        }
+        for line in &lines {
+            let TemplateLine::Text(text) = line else {
+                continue;
+            };
+            if let Some((placeholder, _)) = Self::PLACEHOLDERS
+                .into_iter()
+                .find(|(placeholder, _)| text.contains(placeholder))
+            {
+                return Err(invalid(&format!(
+                    "{placeholder} must be on a line of its own"
+                )));
+            }
+            if text.trim_start().starts_with("# allways:") {
+                return Err(invalid("its lines can't be allways comments"));
+            }
+        }
+        if lines.first() != Some(&TemplateLine::StartMarker)
+            || lines.last() != Some(&TemplateLine::EndMarker)
+        {
+            return Err(invalid(
+                "it must start with {start_marker} and end with {end_marker}",
+            ));
+        }
+        Ok(template)
+    }
+}
+
+impl TryFrom<String> for BlockTemplate {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The comment lines of `Options::block_header`, none of which is an allways comment that would
+/// be taken for a marker.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct BlockHeader(String);
+
+impl BlockHeader {
+    pub(crate) fn lines(&self) -> impl Iterator<Item = &str> {
+        self.0.lines()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for BlockHeader {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let marker = s
+            .lines()
+            .map(|line| line.trim_start_matches('#').trim_start())
+            .any(|line| line.starts_with("allways:"));
+        if marker {
+            return Err(format!(
+                "Invalid block header {s:?}, its lines can't be allways comments"
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for BlockHeader {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The name of a python module of `Options::exports_module`, an identifier without `.py`, which
+/// is written next to `__init__.py`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ModuleName(String);
+
+impl ModuleName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ModuleName {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let identifier = chars
+            .next()
+            .is_some_and(|first| first == '_' || first.is_alphabetic())
+            && chars.all(|char| char == '_' || char.is_alphanumeric());
+        if !identifier {
+            return Err(format!(
+                "Invalid module name {s:?}, expected the name of a python module without `.py`"
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for ModuleName {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A `major.minor` python version, e.g. `3.11`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PythonVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl PythonVersion {
+    pub fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl FromStr for PythonVersion {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid python version {s:?}, expected e.g. \"3.11\"");
+        let (major, minor) = match s.strip_prefix("py") {
+            Some(digits) if digits.len() > 1 => digits.split_at(1),
+            Some(_) => return Err(invalid()),
+            None => s.split_once('.').ok_or_else(invalid)?,
+        };
+        Ok(Self {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for PythonVersion {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for PythonVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_python_version() {
+        assert_eq!("3.11".parse(), Ok(PythonVersion::new(3, 11)));
+        assert_eq!("py38".parse(), Ok(PythonVersion::new(3, 8)));
+        assert_eq!("py312".parse(), Ok(PythonVersion::new(3, 12)));
+        assert!("3".parse::<PythonVersion>().is_err());
+        assert!("three.eleven".parse::<PythonVersion>().is_err());
+    }
+
+    #[test]
+    fn parse_block_template() {
+        let template = "{start_marker}\n# generated, don't edit\n{header}\n{names}\n{end_marker}\n";
+        let lines = template.parse::<BlockTemplate>().unwrap();
+        assert_eq!(
+            lines.lines().collect::<Vec<_>>(),
+            [
+                TemplateLine::StartMarker,
+                TemplateLine::Text("# generated, don't edit"),
+                TemplateLine::Header,
+                TemplateLine::Names,
+                TemplateLine::EndMarker,
+            ]
+        );
+        for invalid in [
+            "{start_marker}\n{end_marker}",
+            "{start_marker}\n{names}\n{names}\n{end_marker}",
+            "# header\n{start_marker}\n{names}\n{end_marker}",
+            "{start_marker}\n__all__ = {names}\n{end_marker}",
+            "{start_marker}\n# allways: start\n{names}\n{end_marker}",
+        ] {
+            assert!(invalid.parse::<BlockTemplate>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_block_header() {
+        let header = "Generated\n# edit the definitions instead".parse::<BlockHeader>();
+        assert_eq!(
+            header.unwrap().lines().collect::<Vec<_>>(),
+            ["Generated", "# edit the definitions instead"]
+        );
+        for invalid in ["allways: end", "Generated\n# allways: start"] {
+            assert!(invalid.parse::<BlockHeader>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_module_name() {
+        assert_eq!(
+            "_exports".parse::<ModuleName>().unwrap().as_str(),
+            "_exports"
+        );
+        for invalid in ["_exports.py", "../x", "", "1st", "a-b"] {
+            assert!(invalid.parse::<ModuleName>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let options = Options::builder()
+            .type_aliases(false)
+            .target_version(PythonVersion::new(3, 9))
+            .export_decorators(["public"])
+            .blank_lines(1)
+            .build();
+        assert!(!options.type_aliases);
+        assert_eq!(options.target_version, Some(PythonVersion::new(3, 9)));
+        assert_eq!(options.export_decorators, vec![String::from("public")]);
+        assert_eq!(options.blank_lines, Some(1));
+        assert!(options.final_newline);
+    }
+
+    #[test]
+    fn python_version_order() {
+        assert!(PythonVersion::new(3, 8) < PythonVersion::new(3, 11));
+        assert!(PythonVersion::new(2, 7) < PythonVersion::new(3, 0));
+    }
+
+    #[test]
+    fn excluded_paths() {
+        let options = Options::builder().exclude(["build", "tests/*.py"]).build();
+        assert!(options.is_excluded(Path::new("build/lib/pkg/__init__.py")));
+        assert!(options.is_excluded(Path::new("proj/tests/test_foo.py")));
+        assert!(!options.is_excluded(Path::new("proj/tests/data/__init__.py")));
+        assert!(!options.is_excluded(Path::new("src/builder/__init__.py")));
+
+        // a project inside an excluded directory isn't excluded
+        let root = std::env::current_dir()
+            .unwrap()
+            .join("build")
+            .join("project");
+        let options = Options::builder().exclude(["build"]).root(&root).build();
+        assert!(!options.is_excluded(&root.join("pkg").join("__init__.py")));
+        assert!(options.is_excluded(&root.join("build").join("lib.py")));
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use rustpython_parser::ast;
+use rustpython_parser::ast::Ranged;
+use rustpython_parser::ast::Stmt;
+use rustpython_parser::ast::Suite;
+use rustpython_parser::Parse;
+
+use crate::add_all::case_insensitive_cmp;
+use crate::add_all::find_block;
+use crate::add_all::get_public_names;
+use crate::add_all::line_ending;
+use crate::add_all::with_allways_block;
+use crate::add_all::Outcome;
+use crate::add_all::PublicNames;
+use crate::editorconfig;
+use crate::encoding::read_source;
+use crate::name_parser::timed_parse;
+use crate::name_parser::NameParser;
+use crate::options::Options;
+use crate::resolve::module_names;
+
+const INIT: &str = "__init__.py";
+const IMPORTS_START_COMMENT: &str = "# allways: imports start";
+const IMPORTS_END_COMMENT: &str = "# allways: imports end";
+
+/// Knobs of `allways package`.
+#[derive(Clone, Debug, Default)]
+pub struct PackageOptions {
+    /// Export only the names of modules that `__init__.py` actually imports.
+    pub imported_only: bool,
+    /// Import every exported name into `__init__.py` as `from .module import name as name`,
+    /// inside a managed region of its own.
+    pub reexports: bool,
+}
+
+/// Compute the updated `__init__.py` of the package `dir`, exporting the public names of
+/// every module in it next to the names `__init__.py` exports itself.
+///
+/// Sub-packages have their own `__init__.py` and are not scanned.
+pub fn do_it_allways_package(
+    dir: &Path,
+    options: &Options,
+    package_options: &PackageOptions,
+) -> Result<Outcome> {
+    let init = dir.join(INIT);
+    let mut src = if init.is_file() {
+        read_source(&init)?.0
+    } else if options.create_init {
+        String::new()
+    } else {
+        return Err(anyhow!(
+            "{} is a namespace package without `{INIT}`, pass --create-init to create one",
+            dir.display()
+        ));
+    };
+    // names imported by the managed region are regenerated from scratch
+    let imports_region = find_block(&src, IMPORTS_START_COMMENT, IMPORTS_END_COMMENT)
+        .filter(|_| package_options.reexports);
+    if let Some((start, end)) = imports_region {
+        src.replace_range(start..end, "");
+    }
+    let PublicNames {
+        names: mut init_names,
+        diagnostics,
+        conflict,
+        conditional,
+        ..
+    } = get_public_names(&src, Some(&init), options)?;
+    // the package exports names of every platform
+    init_names.extend(
+        conditional
+            .into_iter()
+            .flat_map(|conditional| conditional.names),
+    );
+    if conflict {
+        return Ok(Outcome {
+            src: None,
+            diagnostics,
+        });
+    }
+
+    let bound = NameParser::parse(&src, options)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let mut names = HashSet::new();
+    let mut reexports = String::new();
+    for module in modules(dir)? {
+        let mut module_names = module_names(&module, options)
+            .with_context(|| format!("Failed to read {}", module.display()))?;
+        module_names.sort_by(case_insensitive_cmp);
+        let stem = module.file_stem().unwrap_or_default().to_string_lossy();
+        for name in &module_names {
+            if !bound.contains(name) && !names.contains(name) {
+                reexports.push_str(&format!("from .{stem} import {name} as {name}\n"));
+            }
+        }
+        names.extend(module_names);
+    }
+    if package_options.imported_only {
+        names.retain(|name| bound.contains(name));
+    } else {
+        names.extend(init_names);
+    }
+    if package_options.reexports && (imports_region.is_some() || !reexports.is_empty()) {
+        let offset = match imports_region {
+            Some((start, _)) => start,
+            None => imports_offset(&src)?,
+        };
+        let region = format!("{IMPORTS_START_COMMENT}\n{reexports}{IMPORTS_END_COMMENT}\n");
+        src.insert_str(offset, &region.replace('\n', line_ending(&src)));
+    }
+    if names.is_empty() {
+        return Ok(Outcome {
+            src: None,
+            diagnostics,
+        });
+    }
+    let mut names = names.into_iter().collect::<Vec<_>>();
+    names.sort_by(case_insensitive_cmp);
+    Ok(Outcome {
+        src: Some(with_allways_block(
+            &src,
+            &names,
+            &[],
+            &editorconfig::options_for(Some(&init), options)?,
+        )),
+        diagnostics,
+    })
+}
+
+/// Where new imports go: after the module docstring and `from __future__` imports.
+fn imports_offset(src: &str) -> Result<usize> {
+    let mut offset = 0;
+    let statements = timed_parse(|| Suite::parse(src, "<embedded>"))?;
+    for (index, statement) in statements.iter().enumerate() {
+        let skipped = match statement {
+            Stmt::Expr(ast::StmtExpr { value, .. }) => index == 0 && value.is_constant_expr(),
+            Stmt::ImportFrom(ast::StmtImportFrom { module, .. }) => module
+                .as_ref()
+                .is_some_and(|module| module.as_str() == "__future__"),
+            _ => false,
+        };
+        if !skipped {
+            break;
+        }
+        let end = usize::from(statement.end());
+        offset = src[end..]
+            .find('\n')
+            .map_or(src.len(), |newline| end + newline + 1);
+    }
+    Ok(offset)
+}
+
+/// The python modules directly inside `dir`, other than `__init__.py`.
+fn modules(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut modules = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_module = path.extension().is_some_and(|extension| extension == "py")
+            && path.file_name().is_some_and(|name| name != INIT);
+        if is_module && path.is_file() {
+            modules.push(path);
+        }
+    }
+    modules.sort();
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    fn write_package(files: &[(&str, &str)]) -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (name, src) in files {
+            std::fs::write(root.path().join(name), src).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn exports_module_names() {
+        let root = write_package(&[
+            ("__init__.py", "from ._foo import foo\nVERSION = '1.0'\n"),
+            ("_foo.py", "def foo():\n    ...\ndef _helper():\n    ...\n"),
+            (
+                "_bar.py",
+                "__all__ = ['Bar']\nclass Bar:\n    ...\nBaz = 1\n",
+            ),
+        ]);
+        let outcome =
+            do_it_allways_package(root.path(), &Options::default(), &PackageOptions::default())
+                .unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "\
+from ._foo import foo
+VERSION = '1.0'
+
+
+# allways: start
+__all__ = [
+    \"Bar\",
+    \"foo\",
+    \"VERSION\",
+]
+# allways: end
+"
+        );
+    }
+
+    #[test]
+    fn imported_only() {
+        let root = write_package(&[
+            ("__init__.py", "from ._foo import foo\nVERSION = '1.0'\n"),
+            ("_foo.py", "def foo():\n    ...\ndef bar():\n    ...\n"),
+        ]);
+        let package_options = PackageOptions {
+            imported_only: true,
+            ..Default::default()
+        };
+        let outcome =
+            do_it_allways_package(root.path(), &Options::default(), &package_options).unwrap();
+        assert!(outcome
+            .src
+            .unwrap()
+            .contains("__all__ = [\n    \"foo\",\n]\n"));
+    }
+
+    #[test]
+    fn reexports() {
+        let root = write_package(&[
+            (
+                "__init__.py",
+                "\"\"\"Docs.\"\"\"\nfrom __future__ import annotations\nfrom ._foo import foo\n",
+            ),
+            ("_foo.py", "def foo():\n    ...\ndef bar():\n    ...\n"),
+            ("_baz.py", "class Baz:\n    ...\n"),
+        ]);
+        let package_options = PackageOptions {
+            reexports: true,
+            ..Default::default()
+        };
+        let outcome =
+            do_it_allways_package(root.path(), &Options::default(), &package_options).unwrap();
+        let expected = "\
+\"\"\"Docs.\"\"\"
+from __future__ import annotations
+# allways: imports start
+from ._baz import Baz as Baz
+from ._foo import bar as bar
+# allways: imports end
+from ._foo import foo
+
+
+# allways: start
+__all__ = [
+    \"bar\",
+    \"Baz\",
+    \"foo\",
+]
+# allways: end
+";
+        let new_src = outcome.src.unwrap();
+        assert_eq!(new_src, expected);
+
+        std::fs::write(root.path().join(INIT), &new_src).unwrap();
+        let outcome =
+            do_it_allways_package(root.path(), &Options::default(), &package_options).unwrap();
+        assert_eq!(outcome.src.unwrap(), expected);
+    }
+
+    #[test]
+    fn namespace_package() {
+        let root = write_package(&[("foo.py", "A = 1\n")]);
+        let err =
+            do_it_allways_package(root.path(), &Options::default(), &PackageOptions::default())
+                .unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("pass --create-init to create one"));
+
+        let options = Options {
+            create_init: true,
+            ..Default::default()
+        };
+        let outcome =
+            do_it_allways_package(root.path(), &options, &PackageOptions::default()).unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "\
+# allways: start
+__all__ = [
+    \"A\",
+]
+# allways: end
+"
+        );
+    }
+}
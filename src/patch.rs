@@ -0,0 +1,100 @@
+/// Lines of context around the changed lines.
+const CONTEXT: usize = 3;
+
+/// A unified diff turning `old`, named `old_name` in its header, into `new`, `None` when they
+/// are the same.
+///
+/// The diff is a single hunk spanning the lines between the leading and trailing lines `old`
+/// and `new` share, allways only ever changes one region of a file.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(left, right)| left == right)
+        .count();
+    let max_suffix = old_lines.len().min(new_lines.len()) - prefix;
+    let suffix = old_lines
+        .iter()
+        .rev()
+        .zip(new_lines.iter().rev())
+        .take(max_suffix)
+        .take_while(|(left, right)| left == right)
+        .count();
+
+    let start = prefix.saturating_sub(CONTEXT);
+    let after = suffix.min(CONTEXT);
+    let old_end = old_lines.len() - suffix + after;
+    let new_end = new_lines.len() - suffix + after;
+    // an empty range starts at the line before it
+    let range = |end: usize| {
+        let count = end - start;
+        let first = if count == 0 { start } else { start + 1 };
+        format!("{first},{count}")
+    };
+
+    let mut diff = format!("--- {old_name}\n+++ {new_name}\n");
+    diff.push_str(&format!("@@ -{} +{} @@\n", range(old_end), range(new_end)));
+    let mut push = |sign: char, line: &str| {
+        diff.push(sign);
+        diff.push_str(line);
+        if !line.ends_with('\n') {
+            diff.push_str("\n\\ No newline at end of file\n");
+        }
+    };
+    for line in &old_lines[start..prefix] {
+        push(' ', line);
+    }
+    for line in &old_lines[prefix..old_lines.len() - suffix] {
+        push('-', line);
+    }
+    for line in &new_lines[prefix..new_lines.len() - suffix] {
+        push('+', line);
+    }
+    for line in &old_lines[old_lines.len() - suffix..old_end] {
+        push(' ', line);
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunk() {
+        let old = "a\nb\nc\nd\ne\nf\n";
+        let new = "a\nb\nc\nd\nX\ne\nf\n";
+        assert_eq!(
+            unified_diff(old, new, "a/mod.py", "b/mod.py").unwrap(),
+            "\
+--- a/mod.py
++++ b/mod.py
+@@ -2,5 +2,6 @@
+ b
+ c
+ d
++X
+ e
+ f
+"
+        );
+        assert_eq!(unified_diff(old, old, "a", "b"), None);
+    }
+
+    #[test]
+    fn edges() {
+        assert_eq!(
+            unified_diff("", "x = 1\n", "a", "b").unwrap(),
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+x = 1\n"
+        );
+        assert_eq!(
+            unified_diff("x = 1", "x = 1\n", "a", "b").unwrap(),
+            "--- a\n+++ b\n@@ -1,1 +1,1 @@\n-x = 1\n\\ No newline at end of file\n+x = 1\n"
+        );
+    }
+}
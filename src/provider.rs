@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use rustpython_parser::ast::Ranged;
+use rustpython_parser::ast::Stmt;
+
+use crate::add_all::case_insensitive_cmp;
+use crate::add_all::get_public_names;
+use crate::add_all::PublicNames;
+use crate::manifest::Manifest;
+use crate::name_parser::parse_module;
+use crate::name_parser::NameParser;
+use crate::options::Options;
+
+/// Finds the names the allways block of a module exports, see [`do_it_allways_with`].
+///
+/// [`do_it_allways_with`]: crate::do_it_allways_with
+pub trait NameProvider {
+    /// The names exported by the module `src`, read from `path` when known.
+    fn public_names(
+        &self,
+        src: &str,
+        path: Option<&Path>,
+        options: &Options,
+    ) -> Result<PublicNames>;
+}
+
+/// The public names a python module binds, used unless told otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceNames;
+
+impl NameProvider for SourceNames {
+    fn public_names(
+        &self,
+        src: &str,
+        path: Option<&Path>,
+        options: &Options,
+    ) -> Result<PublicNames> {
+        get_public_names(src, path, options)
+    }
+}
+
+/// The public names of a `.pyi` stub, which per PEP 484 only re-exports the names it imports
+/// with a redundant alias, e.g. `from foo import bar as bar`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StubNames;
+
+impl NameProvider for StubNames {
+    fn public_names(
+        &self,
+        src: &str,
+        path: Option<&Path>,
+        options: &Options,
+    ) -> Result<PublicNames> {
+        let mut public_names = get_public_names(src, path, options)?;
+        let private = private_imports(src, options)?;
+        public_names.names.retain(|name| !private.contains(name));
+        Ok(public_names)
+    }
+}
+
+/// Names last bound by a module level import without a redundant alias.
+fn private_imports(src: &str, options: &Options) -> Result<HashSet<String>> {
+    let definitions = NameParser::parse(src, options)?.take_definitions();
+    let (statements, _) = parse_module(src, options)?;
+    let mut private = HashSet::new();
+    for statement in statements {
+        let offset = usize::from(statement.start());
+        let symbols = match statement {
+            Stmt::Import(import) => import.names,
+            Stmt::ImportFrom(import) => import.names,
+            _ => continue,
+        };
+        for symbol in symbols {
+            let redundant = symbol.asname.as_ref() == Some(&symbol.name);
+            let name = symbol.asname.unwrap_or(symbol.name);
+            let definition = definitions.get(name.as_str());
+            if !redundant && definition.is_some_and(|definition| definition.offset == offset) {
+                private.insert(name.to_string());
+            }
+        }
+    }
+    Ok(private)
+}
+
+/// The names a sidecar manifest, as written by `allways manifest`, lists for the module,
+/// whatever its source binds.
+#[derive(Clone, Debug)]
+pub struct ManifestNames {
+    manifest: Manifest,
+}
+
+impl ManifestNames {
+    pub fn new(manifest: Manifest) -> Self {
+        Self { manifest }
+    }
+
+    /// Read the JSON manifest at `path`.
+    #[cfg(feature = "serde")]
+    pub fn read(path: &Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let json = std::fs::read_to_string(path)?;
+        let manifest = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Self::new(manifest))
+    }
+}
+
+impl NameProvider for ManifestNames {
+    fn public_names(
+        &self,
+        _src: &str,
+        path: Option<&Path>,
+        _options: &Options,
+    ) -> Result<PublicNames> {
+        let path = path.ok_or_else(|| anyhow!("A module is found in the manifest by its path"))?;
+        let module = self
+            .manifest
+            .modules
+            .iter()
+            .find(|module| path.ends_with(&module.path))
+            .ok_or_else(|| anyhow!("{} isn't listed in the manifest", path.display()))?;
+        let mut names = module
+            .names
+            .iter()
+            .map(|name| name.name.clone())
+            .collect::<Vec<_>>();
+        names.sort_by(case_insensitive_cmp);
+        Ok(PublicNames {
+            names,
+            ..PublicNames::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::add_all::do_it_allways_with;
+    use crate::manifest::ExportedName;
+    use crate::manifest::ModuleManifest;
+
+    #[test]
+    fn stub_names() {
+        let src = "\
+import os
+import sys as sys
+from typing import Any, List as List
+from ._impl import helper
+from ._impl import helper as helper2
+
+def foo() -> Any: ...
+";
+        let names = StubNames
+            .public_names(src, None, &Options::default())
+            .unwrap()
+            .names;
+        assert_eq!(names, vec!["foo", "List", "sys"]);
+    }
+
+    #[test]
+    fn manifest_names() {
+        let provider = ManifestNames::new(Manifest {
+            modules: vec![ModuleManifest {
+                module: String::from("pkg"),
+                path: String::from("pkg/__init__.py"),
+                names: vec![ExportedName {
+                    name: String::from("foo"),
+                    kind: None,
+                    line: None,
+                }],
+            }],
+        });
+        let path = Path::new("src/pkg/__init__.py");
+        let outcome = do_it_allways_with(
+            &provider,
+            "foo = bar = 1\n",
+            Some(path),
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            outcome.src.unwrap(),
+            "foo = bar = 1\n\n\n# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n"
+        );
+        let other = Path::new("src/other/__init__.py");
+        assert!(provider
+            .public_names("", Some(other), &Options::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBool;
+use pyo3::types::PyDict;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::options::Options;
+
+/// The python module `src` with its allways block inserted or updated.
+///
+/// Options are the settings of the `[tool.allways]` table spelled with underscores, e.g.
+/// `fix_source(src, type_aliases=False)`.
+#[pyfunction]
+#[pyo3(signature = (src, **options))]
+fn fix_source(src: &str, options: Option<&PyDict>) -> PyResult<String> {
+    let mut config = serde_json::Map::new();
+    for (key, value) in options.into_iter().flatten() {
+        let key = key.extract::<&str>()?.replace('_', "-");
+        config.insert(key, to_json(value)?);
+    }
+    let config = serde_json::from_value::<Config>(Value::Object(config))
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+    let mut fix_options = Options::default();
+    config.apply(&mut fix_options);
+    let outcome = crate::fix::fix_source(src, &fix_options)
+        .map_err(|err| PyValueError::new_err(format!("{err:#}")))?;
+    Ok(outcome.src)
+}
+
+/// A keyword argument as the JSON value the config is deserialized from.
+fn to_json(value: &PyAny) -> PyResult<Value> {
+    if let Ok(value) = value.downcast::<PyBool>() {
+        Ok(Value::from(value.is_true()))
+    } else if let Ok(value) = value.extract::<u64>() {
+        Ok(Value::from(value))
+    } else if let Ok(value) = value.extract::<String>() {
+        Ok(Value::from(value))
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        dict.iter()
+            .map(|(key, value)| Ok((key.extract::<String>()?, to_json(value)?)))
+            .collect()
+    } else if let Ok(items) = value.extract::<Vec<&PyAny>>() {
+        items.into_iter().map(to_json).collect()
+    } else {
+        Err(PyValueError::new_err(format!(
+            "Unsupported option value {value}"
+        )))
+    }
+}
+
+#[pymodule]
+fn allways(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(fix_source, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use rustpython_parser::ast::Suite;
+use rustpython_parser::Parse;
+
+use crate::diagnostic::Diagnostic;
+
+/// Keywords that continue a compound statement started on an earlier line.
+const CONTINUATIONS: [&str; 4] = ["elif", "else", "except", "finally"];
+
+/// Parse `src`, blanking out top-level statements that fail to parse until the rest does.
+///
+/// Every skipped statement is reported as a diagnostic. Blanked statements are replaced by as
+/// many bytes of whitespace, keeping their line endings, so that offsets and line numbers of
+/// the statements parsed still point into the original file.
+pub fn parse_recovering(src: &str) -> (Suite, Vec<Diagnostic>) {
+    let mut src = src.to_string();
+    let mut diagnostics = Vec::new();
+    loop {
+        let err = match Suite::parse(&src, "<embedded>") {
+            Ok(statements) => return (statements, diagnostics),
+            Err(err) => err,
+        };
+        let offset = usize::from(err.offset).min(src.len());
+        let (start, end) = enclosing_statement(&src, offset);
+        diagnostics.push(Diagnostic::at_offset(
+            &src,
+            start,
+            format!("Ignoring statement that failed to parse: {}", err.error),
+        ));
+        if src[start..end].trim().is_empty() {
+            // nothing left to blank out, give up on the whole file
+            return (Vec::new(), diagnostics);
+        }
+        let blank = blank_out(&src[start..end]);
+        src.replace_range(start..end, &blank);
+    }
+}
+
+/// `text` with every character but line endings replaced by spaces of the same length in bytes.
+fn blank_out(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| {
+            let (c, len) = match c {
+                '\n' | '\r' => (c, 1),
+                _ => (' ', c.len_utf8()),
+            };
+            std::iter::repeat(c).take(len)
+        })
+        .collect()
+}
+
+/// Byte range of the top-level statement containing `offset`.
+fn enclosing_statement(src: &str, offset: usize) -> (usize, usize) {
+    let mut start = 0;
+    let mut line_start = 0;
+    for line in src.split_inclusive('\n') {
+        if line_start > offset && starts_statement(line) {
+            return (start, line_start);
+        }
+        if line_start <= offset && starts_statement(line) {
+            start = line_start;
+        }
+        line_start += line.len();
+    }
+    (start, src.len())
+}
+
+fn starts_statement(line: &str) -> bool {
+    let Some(first) = line.chars().next() else {
+        return false;
+    };
+    if first.is_whitespace() || matches!(first, '#' | ')' | ']' | '}') {
+        return false;
+    }
+    let word = line
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default();
+    !CONTINUATIONS.contains(&word)
+}
+
+#[cfg(test)]
+mod tests {
+    use rustpython_parser::ast::Ranged;
+
+    use super::*;
+
+    #[test]
+    fn valid_source_is_untouched() {
+        let (statements, diagnostics) = parse_recovering("a = 1\nb = 2\n");
+        assert_eq!(statements.len(), 2);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn skips_bad_statement() {
+        let src = "
+a = 1
+b = = 2
+def foo():
+    ...
+";
+        let (statements, diagnostics) = parse_recovering(src);
+        assert_eq!(statements.len(), 2);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+    }
+
+    #[test]
+    fn skips_bad_compound_statement() {
+        let src = "
+if x:
+    a = = 1
+else:
+    b = 2
+c = 3
+";
+        let (statements, diagnostics) = parse_recovering(src);
+        assert_eq!(statements.len(), 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn offsets_match_the_original() {
+        let src = "é = = 1\nnaïve = 2\nc = = 3\n__all__ = ['naïve']\n";
+        let (statements, diagnostics) = parse_recovering(src);
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [1, 3]);
+        let starts = statements
+            .iter()
+            .map(|statement| &src[usize::from(statement.start())..usize::from(statement.end())])
+            .collect::<Vec<_>>();
+        assert_eq!(starts, ["naïve = 2", "__all__ = ['naïve']"]);
+    }
+
+    #[test]
+    fn statement_ranges() {
+        let src = "a = (\n    1\n)\nb = 2\n";
+        assert_eq!(enclosing_statement(src, 8), (0, 14));
+        assert_eq!(enclosing_statement(src, 15), (14, src.len()));
+    }
+}
@@ -0,0 +1,116 @@
+use std::ops::Range;
+
+/// Comments opening and closing regions other tools manage or keep their hands off, which the
+/// allways block must not be inserted into.
+const REGIONS: [(&str, &str); 5] = [
+    ("# fmt: off", "# fmt: on"),
+    ("# yapf: disable", "# yapf: enable"),
+    ("# isort: off", "# isort: on"),
+    // mkinit
+    ("# <AUTOGEN_INIT>", "# </AUTOGEN_INIT>"),
+    // `allways package --reexports`
+    ("# allways: imports start", "# allways: imports end"),
+];
+
+/// A region of a module managed by another tool.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Region {
+    /// From the start of the opening comment line to the end of the closing one, or of the
+    /// module when the region isn't closed.
+    pub range: Range<usize>,
+    pub closed: bool,
+}
+
+/// The regions of `src` managed by other tools.
+pub(crate) fn foreign_regions(src: &str) -> Vec<Region> {
+    let mut regions = Vec::new();
+    let mut open: Option<(usize, &str)> = None;
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let comment = line.trim();
+        match open {
+            Some((start, end_comment)) if comment == end_comment => {
+                regions.push(Region {
+                    range: start..offset + line.len(),
+                    closed: true,
+                });
+                open = None;
+            }
+            Some(_) => {}
+            None => {
+                open = REGIONS
+                    .iter()
+                    .find(|(start_comment, _)| comment == *start_comment)
+                    .map(|&(_, end_comment)| (offset, end_comment));
+            }
+        }
+        offset += line.len();
+    }
+    if let Some((start, _)) = open {
+        regions.push(Region {
+            range: start..src.len(),
+            closed: false,
+        });
+    }
+    regions
+}
+
+/// The insertion point nearest to `offset` outside of the regions of other tools: `offset`
+/// itself, or the start or end of the region it falls in.
+///
+/// A region that isn't closed can only be inserted before, the end of `src` is inside it.
+pub(crate) fn safe_offset(src: &str, offset: usize) -> usize {
+    let Some(Region { range, closed }) = foreign_regions(src).into_iter().find(|region| {
+        region.range.start < offset && (offset < region.range.end || !region.closed)
+    }) else {
+        return offset;
+    };
+    if !closed || offset - range.start < range.end - offset {
+        range.start
+    } else {
+        range.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions() {
+        let src = "\
+import os
+# fmt: off
+X = [1,
+  2]
+# fmt: on
+# <AUTOGEN_INIT>
+from .foo import bar
+";
+        let fmt = src.find("# fmt: off").unwrap();
+        let mkinit = src.find("# <AUTOGEN_INIT>").unwrap();
+        assert_eq!(
+            foreign_regions(src),
+            vec![
+                Region {
+                    range: fmt..mkinit,
+                    closed: true,
+                },
+                Region {
+                    range: mkinit..src.len(),
+                    closed: false,
+                },
+            ]
+        );
+
+        // inside, nearest the end
+        assert_eq!(safe_offset(src, mkinit - 2), mkinit);
+        // inside, nearest the start
+        assert_eq!(safe_offset(src, fmt + 2), fmt);
+        // the end of the file is inside the unclosed region
+        assert_eq!(safe_offset(src, src.len()), mkinit);
+        assert_eq!(safe_offset(src, fmt), fmt);
+        assert_eq!(safe_offset("x = 1\n", 6), 6);
+        assert_eq!(safe_offset("# fmt: off\n# fmt: on\n", 21), 21);
+    }
+}
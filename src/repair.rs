@@ -0,0 +1,271 @@
+use std::ops::Range;
+
+use rustpython_parser::ast;
+use rustpython_parser::ast::Stmt;
+use rustpython_parser::ast::Suite;
+use rustpython_parser::Parse;
+
+use crate::add_all::line_ending;
+use crate::add_all::ALLWAYS_END_COMMENT;
+use crate::add_all::ALLWAYS_START_COMMENT;
+use crate::comments::comments as find_comments;
+use crate::comments::starts_comment;
+use crate::name_parser::dunder_all_value;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Marker {
+    Start,
+    End,
+}
+
+/// The allways markers of `src`, with the byte range of their line, line ending included.
+///
+/// Marker lines in strings, e.g. a docstring showing an allways block, aren't markers.
+fn markers(src: &str) -> Vec<(Marker, Range<usize>)> {
+    let mut markers = Vec::new();
+    // only looked for once a marker line is found
+    let mut comments: Option<Vec<Range<usize>>> = None;
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let marker = match line.trim_end() {
+            ALLWAYS_START_COMMENT => Some(Marker::Start),
+            ALLWAYS_END_COMMENT => Some(Marker::End),
+            _ => None,
+        };
+        let marker = marker.filter(|_| {
+            let comments = comments.get_or_insert_with(|| find_comments(src));
+            starts_comment(comments, offset)
+        });
+        if let Some(marker) = marker {
+            markers.push((marker, offset..offset + line.len()));
+        }
+        offset += line.len();
+    }
+    markers
+}
+
+/// The offset of the first marker of `src` that doesn't belong to a single, well-formed allways
+/// block, and what is wrong with it.
+pub(crate) fn malformed_block(src: &str) -> Option<(usize, &'static str)> {
+    let mut start = None;
+    let mut closed = false;
+    for (marker, line) in markers(src) {
+        let problem = match (marker, start, closed) {
+            (Marker::Start, None, _) => {
+                start = Some(line.start);
+                continue;
+            }
+            (Marker::End, Some(_), false) => {
+                closed = true;
+                continue;
+            }
+            (Marker::Start, _, true) => "A second allways block starts here",
+            (Marker::Start, ..) => "The allways start marker is repeated before the end marker",
+            (Marker::End, None, _) => "The allways end marker comes before the start marker",
+            (Marker::End, ..) => "The allways end marker is repeated",
+        };
+        return Some((line.start, problem));
+    }
+    match (start, closed) {
+        (Some(start), false) => Some((start, "The allways block has no end marker")),
+        _ => None,
+    }
+}
+
+/// `src` with the markers of its allways block normalized, `None` when they are well-formed.
+///
+/// The block kept is the one between the first end marker and the last start marker before
+/// it, every other marker is removed, along with the `__all__` statements directly following
+/// a removed start marker, which were generated for a block that is no more. Without such an
+/// end marker, one is added after the lines directly following the last start marker, up to
+/// the first blank line. The names of the block are left as they are.
+pub fn repair_block(src: &str) -> Option<String> {
+    malformed_block(src)?;
+    let markers = markers(src);
+    let is = |index: usize, kind: Marker| markers[index].0 == kind;
+    let pair = (0..markers.len())
+        .filter(|&end| is(end, Marker::End))
+        .find_map(|end| {
+            let start = (0..end).rev().find(|&start| is(start, Marker::Start))?;
+            Some((start, end))
+        });
+    let newline = line_ending(src);
+    // byte ranges of `src` replaced by the strings
+    let mut edits = Vec::new();
+    let kept = match pair {
+        Some((start, end)) => vec![start, end],
+        None => match (0..markers.len())
+            .rev()
+            .find(|&start| is(start, Marker::Start))
+        {
+            Some(start) => {
+                let end = run_end(src, markers[start].1.end);
+                let separator = if src[..end].ends_with('\n') {
+                    ""
+                } else {
+                    newline
+                };
+                let marker = format!("{separator}{ALLWAYS_END_COMMENT}{newline}");
+                edits.push((end..end, marker));
+                vec![start]
+            }
+            None => Vec::new(),
+        },
+    };
+    for (index, (marker, line)) in markers.into_iter().enumerate() {
+        if kept.contains(&index) {
+            continue;
+        }
+        // left behind, the names would be a hand-written `__all__` keeping the file unrepaired
+        let body = line.end..run_end(src, line.end);
+        if marker == Marker::Start && !body.is_empty() && generated(&src[body.clone()]) {
+            edits.push((body, String::new()));
+        }
+        edits.push((line, String::new()));
+    }
+    // an insertion goes before a removal at the same offset
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+
+    let mut repaired = String::with_capacity(src.len() + ALLWAYS_END_COMMENT.len() + 2);
+    let mut copied = 0;
+    for (range, replacement) in edits {
+        repaired.push_str(&src[copied..range.start]);
+        repaired.push_str(&replacement);
+        copied = range.end;
+    }
+    repaired.push_str(&src[copied..]);
+    Some(repaired)
+}
+
+/// Whether the statements of `body` only build `__all__` like those of an allways block do.
+fn generated(body: &str) -> bool {
+    let Ok(statements) = Suite::parse(body, "<block>") else {
+        return false;
+    };
+    statements.iter().all(builds_dunder_all)
+}
+
+/// Whether `statement` assigns or extends `__all__`, imports the `__all__` of another module,
+/// or does so under a condition, like the statements of an allways block.
+fn builds_dunder_all(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::ImportFrom(ast::StmtImportFrom { names, .. }) => {
+            names.iter().all(|alias| alias.name.as_str() == "__all__")
+        }
+        Stmt::If(ast::StmtIf { body, orelse, .. }) => {
+            body.iter().chain(orelse).all(builds_dunder_all)
+        }
+        _ => dunder_all_value(statement).is_some(),
+    }
+}
+
+/// The end of the lines following `offset` up to the first blank line or marker.
+fn run_end(src: &str, offset: usize) -> usize {
+    let mut end = offset;
+    for line in src[offset..].split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed == ALLWAYS_START_COMMENT || trimmed == ALLWAYS_END_COMMENT
+        {
+            break;
+        }
+        end += line.len();
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: &str = "# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n";
+
+    #[test]
+    fn well_formed() {
+        assert_eq!(malformed_block("foo = 1\n"), None);
+        assert_eq!(malformed_block(&format!("foo = 1\n{BLOCK}")), None);
+        assert_eq!(repair_block(&format!("foo = 1\n{BLOCK}")), None);
+    }
+
+    #[test]
+    fn problems() {
+        let cases = [
+            (
+                "# allways: end\nfoo = 1\n",
+                (0, "The allways end marker comes before the start marker"),
+            ),
+            (
+                "# allways: start\nfoo = 1\n",
+                (0, "The allways block has no end marker"),
+            ),
+            (
+                "# allways: start\n# allways: start\n# allways: end\n",
+                (
+                    17,
+                    "The allways start marker is repeated before the end marker",
+                ),
+            ),
+            (
+                "# allways: start\n# allways: end\n# allways: end\n",
+                (32, "The allways end marker is repeated"),
+            ),
+            (
+                "# allways: start\n# allways: end\n# allways: start\n# allways: end\n",
+                (32, "A second allways block starts here"),
+            ),
+        ];
+        for (src, problem) in cases {
+            assert_eq!(malformed_block(src), Some(problem), "{src}");
+        }
+        // a docstring showing the markers has none
+        let src = "\"\"\"Generated by\n# allways: start\n\"\"\"\nfoo = 1\n";
+        assert_eq!(malformed_block(src), None);
+    }
+
+    #[test]
+    fn repairs() {
+        // a second block was appended after the end marker of the first one was lost, whose
+        // names are dropped with its start marker
+        let src = "# allways: start\n__all__ = [\"foo\"]\n\nfoo = 1\n\n\n# allways: start\n__all__ = [\"foo\"]\n# allways: end\n";
+        assert_eq!(
+            repair_block(src).unwrap(),
+            "\nfoo = 1\n\n\n# allways: start\n__all__ = [\"foo\"]\n# allways: end\n"
+        );
+        // missing end marker
+        let src = "foo = 1\n\n# allways: start\n__all__ = [\n    \"foo\",\n]\n\nbar = 1";
+        assert_eq!(
+            repair_block(src).unwrap(),
+            "foo = 1\n\n# allways: start\n__all__ = [\n    \"foo\",\n]\n# allways: end\n\nbar = 1"
+        );
+        let src = "foo = 1\n# allways: start\n__all__ = [\"foo\"]";
+        assert_eq!(
+            repair_block(src).unwrap(),
+            "foo = 1\n# allways: start\n__all__ = [\"foo\"]\n# allways: end\n"
+        );
+        // stray end marker
+        let src = "# allways: end\nfoo = 1\n";
+        assert_eq!(repair_block(src).unwrap(), "foo = 1\n");
+        // the names of a second block go with its markers
+        let src = "# allways: start\n__all__ = [\"a\"]\n# allways: end\nfoo = 1\n# allways: start\n__all__ = [\"b\"]\n# allways: end\n";
+        assert_eq!(
+            repair_block(src).unwrap(),
+            "# allways: start\n__all__ = [\"a\"]\n# allways: end\nfoo = 1\n"
+        );
+        // code that isn't generated stays
+        let src = "# allways: start\nbar = 1\n\n# allways: start\n__all__ = []\n# allways: end\n";
+        assert_eq!(
+            repair_block(src).unwrap(),
+            "bar = 1\n\n# allways: start\n__all__ = []\n# allways: end\n"
+        );
+        for src in [
+            "# allways: start\n# allways: start\n__all__ = []\n# allways: end\n# allways: end\n",
+            "# allways: end\n# allways: start\n__all__ = []\n",
+        ] {
+            let repaired = repair_block(src).unwrap();
+            assert_eq!(
+                repaired, "# allways: start\n__all__ = []\n# allways: end\n",
+                "{src}"
+            );
+            assert_eq!(malformed_block(&repaired), None);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::encoding::read_source;
+use crate::name_parser::NameParser;
+use crate::name_parser::StarImport;
+use crate::options::Options;
+
+const INIT: &str = "__init__.py";
+
+/// Locate the file a `from <module> import ...` statement in `importer` refers to.
+///
+/// Relative imports are resolved against the importer's package, absolute imports against
+/// the directory containing the importer's top-level package, or any directory above it up to
+/// the project `root`, since namespace packages (PEP 420) have no `__init__.py` to tell where
+/// they start.
+pub fn resolve_import(
+    importer: &Path,
+    module: Option<&str>,
+    level: u32,
+    root: &Path,
+) -> Option<PathBuf> {
+    let mut base = importer.parent()?.to_path_buf();
+    if level == 0 {
+        while base.join(INIT).is_file() {
+            base = base.parent()?.to_path_buf();
+        }
+        let module = module?;
+        let current_dir = std::env::current_dir().ok()?;
+        let above = base
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| current_dir.join(dir).starts_with(root));
+        return std::iter::once(base.as_path())
+            .chain(above)
+            .find_map(|dir| resolve_module(dir, Some(module)));
+    }
+    for _ in 1..level {
+        base = base.parent()?.to_path_buf();
+    }
+    resolve_module(&base, module)
+}
+
+/// The file of `module` relative to `base`, the package `base` itself when `None`.
+fn resolve_module(base: &Path, module: Option<&str>) -> Option<PathBuf> {
+    let Some(module) = module else {
+        return Some(base.join(INIT)).filter(|path| path.is_file());
+    };
+    let mut path = base.to_path_buf();
+    path.extend(module.split('.'));
+    [path.with_extension("py"), path.join(INIT)]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+/// Whether `name` is a module or package inside the package of `init`.
+pub fn is_submodule(init: &Path, name: &str) -> bool {
+    let Some(dir) = init.parent().filter(|_| init.ends_with(INIT)) else {
+        return false;
+    };
+    dir.join(format!("{name}.py")).is_file() || dir.join(name).is_dir()
+}
+
+/// Every name the module at `path` binds, private ones included, or `None` if that can't be
+/// known because of a star import of a module that isn't on disk or a module level
+/// `__getattr__`.
+pub fn defined_names(path: &Path, options: &Options) -> Result<Option<HashSet<String>>> {
+    let (src, _) = read_source(path)?;
+    let mut parser = NameParser::parse(&src, options)?;
+    let star_imports = parser.take_star_imports();
+    let mut names = parser.into_iter().collect::<HashSet<_>>();
+    if names.contains("__getattr__") {
+        return Ok(None);
+    }
+    for star_import in &star_imports {
+        match expand_star_import(path, star_import, options)? {
+            Some(expanded) => names.extend(expanded),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(names))
+}
+
+/// Names bound by `star_import` in `importer`, or `None` if its module isn't on disk.
+pub fn expand_star_import(
+    importer: &Path,
+    star_import: &StarImport,
+    options: &Options,
+) -> Result<Option<Vec<String>>> {
+    let module = star_import.module.as_deref();
+    let root = options.project_root();
+    let Some(path) = resolve_import(importer, module, star_import.level, &root) else {
+        return Ok(None);
+    };
+    let mut visited = HashSet::from([importer.to_path_buf()]);
+    Ok(Some(star_import_names(&path, options, &mut visited)?))
+}
+
+/// Names `from <module> import *` binds for the module at `path`: its `__all__` if it
+/// statically defines one, otherwise its public names.
+pub fn module_names(path: &Path, options: &Options) -> Result<Vec<String>> {
+    star_import_names(path, options, &mut HashSet::new())
+}
+
+/// The module's `__all__` if it statically defines one, otherwise its public names.
+fn star_import_names(
+    path: &Path,
+    options: &Options,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<Vec<String>> {
+    if !visited.insert(path.to_path_buf()) {
+        return Ok(Vec::new());
+    }
+    let (src, _) = read_source(path)?;
+    let mut parser = NameParser::parse(&src, options)?;
+    if let Some(names) = parser.dunder_all() {
+        return Ok(names.to_vec());
+    }
+    let star_imports = parser.take_star_imports();
+    let mut names = parser
+        .into_iter()
+        .filter(|name| !name.starts_with('_'))
+        .collect::<Vec<_>>();
+    for star_import in star_imports {
+        let module = star_import.module.as_deref();
+        let root = options.project_root();
+        if let Some(source) = resolve_import(path, module, star_import.level, &root) {
+            names.extend(star_import_names(&source, options, visited)?);
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    fn write_package(files: &[(&str, &str)]) -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (name, src) in files {
+            let path = root.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, src).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn resolve_relative_and_absolute() {
+        let root = write_package(&[
+            ("pkg/__init__.py", ""),
+            ("pkg/_foo.py", ""),
+            ("pkg/bar/__init__.py", ""),
+            ("pkg/bar/_baz.py", ""),
+        ]);
+        let root = root.path();
+        let init = root.join("pkg/bar/__init__.py");
+
+        assert_eq!(
+            resolve_import(&init, Some("_baz"), 1, root),
+            Some(root.join("pkg/bar/_baz.py"))
+        );
+        assert_eq!(
+            resolve_import(&init, Some("_foo"), 2, root),
+            Some(root.join("pkg/_foo.py"))
+        );
+        assert_eq!(
+            resolve_import(&init, None, 2, root),
+            Some(root.join("pkg/__init__.py"))
+        );
+        assert_eq!(
+            resolve_import(&init, Some("pkg.bar"), 0, root),
+            Some(root.join("pkg/bar/__init__.py"))
+        );
+        assert_eq!(resolve_import(&init, Some("numpy"), 0, root), None);
+    }
+
+    #[test]
+    fn resolve_in_namespace_package() {
+        let root = write_package(&[("ns/pkg/__init__.py", ""), ("ns/other/_foo.py", "")]);
+        let root = root.path();
+        let init = root.join("ns/pkg/__init__.py");
+
+        assert_eq!(
+            resolve_import(&init, Some("ns.other._foo"), 0, root),
+            Some(root.join("ns/other/_foo.py"))
+        );
+        assert_eq!(
+            resolve_import(&init, Some("other._foo"), 2, root),
+            Some(root.join("ns/other/_foo.py"))
+        );
+        // directories above the project aren't searched
+        let project = root.join("ns");
+        assert_eq!(
+            resolve_import(&init, Some("ns.other._foo"), 0, &project),
+            None
+        );
+    }
+
+    #[test]
+    fn defined_and_submodule_names() {
+        let root = write_package(&[
+            ("pkg/__init__.py", "from ._foo import *\n_private = 1\n"),
+            ("pkg/_foo.py", "A = 1\n"),
+            ("pkg/sub/__init__.py", ""),
+            ("pkg/lazy.py", "def __getattr__(name):\n    ...\n"),
+        ]);
+        let init = root.path().join("pkg/__init__.py");
+
+        let mut names = defined_names(&init, &Options::default())
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec![String::from("A"), String::from("_private")]);
+        assert_eq!(
+            defined_names(&root.path().join("pkg/lazy.py"), &Options::default()).unwrap(),
+            None
+        );
+        assert!(is_submodule(&init, "_foo"));
+        assert!(is_submodule(&init, "sub"));
+        assert!(!is_submodule(&init, "A"));
+    }
+
+    #[test]
+    fn expand_public_names_and_dunder_all() {
+        let root = write_package(&[
+            ("pkg/__init__.py", ""),
+            ("pkg/_foo.py", "A = 1\n_b = 2\nfrom ._bar import *\n"),
+            ("pkg/_bar.py", "__all__ = ['C']\nC = 3\nD = 4\n"),
+        ]);
+        let init = root.path().join("pkg/__init__.py");
+        let star_import = StarImport {
+            module: Some(String::from("_foo")),
+            level: 1,
+            offset: 0,
+        };
+
+        let mut names = expand_star_import(&init, &star_import, &Options::default())
+            .unwrap()
+            .unwrap();
+        names.sort();
+        assert_eq!(names, vec![String::from("A"), String::from("C")]);
+    }
+
+    #[test]
+    fn expand_cyclic_star_imports() {
+        let root = write_package(&[
+            ("pkg/__init__.py", "from ._foo import *\n"),
+            ("pkg/_foo.py", "from . import *\nA = 1\n"),
+        ]);
+        let init = root.path().join("pkg/__init__.py");
+        let star_import = StarImport {
+            module: Some(String::from("_foo")),
+            level: 1,
+            offset: 0,
+        };
+
+        let names = expand_star_import(&init, &star_import, &Options::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(names, vec![String::from("A")]);
+    }
+}
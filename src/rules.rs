@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::glob;
+
+/// A check with a stable code, e.g. `ALW001`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Rule {
+    /// The module has public names but no `__all__`.
+    MissingAll,
+    /// The allways block doesn't match the module's public names.
+    StaleAll,
+    /// `__all__` lists a name the module never defines.
+    UnknownName,
+    /// `__all__` isn't sorted.
+    UnsortedAll,
+    /// A `# allways: noqa` comment doesn't suppress any diagnostic.
+    UnusedNoqa,
+    /// A module and its `.pyi` stub export different names.
+    StubMismatch,
+    /// A name is imported from a module on disk that doesn't define it.
+    UnknownImport,
+    /// The allways block was edited since it was generated, see `Options::block_hash`.
+    EditedBlock,
+    /// The allways markers don't delimit a single block, see `repair_block`.
+    MalformedBlock,
+    /// `__all__` lists a private name, e.g. `_helper`.
+    PrivateName,
+    /// `__all__` lists a name more than once.
+    DuplicateName,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 11] = [
+        Rule::MissingAll,
+        Rule::StaleAll,
+        Rule::UnknownName,
+        Rule::UnsortedAll,
+        Rule::UnusedNoqa,
+        Rule::StubMismatch,
+        Rule::UnknownImport,
+        Rule::EditedBlock,
+        Rule::MalformedBlock,
+        Rule::PrivateName,
+        Rule::DuplicateName,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Rule::MissingAll => "ALW001",
+            Rule::StaleAll => "ALW002",
+            Rule::UnknownName => "ALW003",
+            Rule::UnsortedAll => "ALW004",
+            Rule::UnusedNoqa => "ALW005",
+            Rule::StubMismatch => "ALW006",
+            Rule::UnknownImport => "ALW007",
+            Rule::EditedBlock => "ALW008",
+            Rule::MalformedBlock => "ALW009",
+            Rule::PrivateName => "ALW010",
+            Rule::DuplicateName => "ALW011",
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.to_ascii_uppercase();
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.code() == code)
+            .ok_or_else(|| format!("Unknown rule {s:?}, expected e.g. \"ALW001\""))
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Rules serialize to their code, the way they are configured.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// How a diagnostic affects the exit code of `--check`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Fails the check.
+    Error,
+    /// Reported without failing the check.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+/// Which rules are enabled and how severe their diagnostics are.
+#[derive(Clone, Debug)]
+pub struct RuleSettings {
+    enabled: BTreeSet<Rule>,
+    /// Rules missing here are errors.
+    severities: BTreeMap<Rule, Severity>,
+    /// Rules disabled for files matching a glob pattern.
+    per_file_ignores: Vec<(String, Vec<Rule>)>,
+}
+
+impl Default for RuleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: BTreeSet::from(Rule::ALL),
+            severities: BTreeMap::new(),
+            per_file_ignores: Vec::new(),
+        }
+    }
+}
+
+impl RuleSettings {
+    /// Enable exactly `rules`.
+    pub fn select(&mut self, rules: &[Rule]) {
+        self.enabled = rules.iter().copied().collect();
+    }
+
+    pub fn ignore(&mut self, rules: &[Rule]) {
+        for rule in rules {
+            self.enabled.remove(rule);
+        }
+    }
+
+    /// Disable `rules` for files whose path ends with a match of `pattern`, e.g. `tests/*`.
+    pub fn ignore_for(&mut self, pattern: String, rules: Vec<Rule>) {
+        self.per_file_ignores.push((pattern, rules));
+    }
+
+    pub fn set_severity(&mut self, rule: Rule, severity: Severity) {
+        self.severities.insert(rule, severity);
+    }
+
+    /// The severity of `rule`, or `None` if it is disabled.
+    pub fn severity(&self, rule: Rule) -> Option<Severity> {
+        if !self.enabled.contains(&rule) {
+            return None;
+        }
+        Some(
+            self.severities
+                .get(&rule)
+                .copied()
+                .unwrap_or(Severity::Error),
+        )
+    }
+
+    /// The severity of `rule` in the file at `path`, or `None` if it is disabled there.
+    pub fn severity_in(&self, rule: Rule, path: Option<&Path>) -> Option<Severity> {
+        let ignored = path.is_some_and(|path| {
+            self.per_file_ignores
+                .iter()
+                .any(|(pattern, rules)| rules.contains(&rule) && glob::matches_path(pattern, path))
+        });
+        if ignored {
+            return None;
+        }
+        self.severity(rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rule() {
+        assert_eq!("ALW003".parse(), Ok(Rule::UnknownName));
+        assert_eq!("alw004".parse(), Ok(Rule::UnsortedAll));
+        assert!("ALW999".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn select_ignore_and_severity() {
+        let mut rules = RuleSettings::default();
+        assert_eq!(rules.severity(Rule::MissingAll), Some(Severity::Error));
+
+        rules.select(&[Rule::StaleAll, Rule::UnsortedAll]);
+        rules.ignore(&[Rule::StaleAll]);
+        rules.set_severity(Rule::UnsortedAll, Severity::Warning);
+        assert_eq!(rules.severity(Rule::MissingAll), None);
+        assert_eq!(rules.severity(Rule::StaleAll), None);
+        assert_eq!(rules.severity(Rule::UnsortedAll), Some(Severity::Warning));
+    }
+
+    #[test]
+    fn per_file_ignores() {
+        let mut rules = RuleSettings::default();
+        rules.ignore_for(String::from("tests/*"), vec![Rule::MissingAll]);
+        let test_file = Path::new("proj/tests/test_foo.py");
+        assert_eq!(rules.severity_in(Rule::MissingAll, Some(test_file)), None);
+        assert_eq!(
+            rules.severity_in(Rule::StaleAll, Some(test_file)),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            rules.severity_in(Rule::MissingAll, Some(Path::new("proj/src/foo.py"))),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            rules.severity_in(Rule::MissingAll, None),
+            Some(Severity::Error)
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+
+use rustpython_parser::ast;
+use rustpython_parser::ast::ExceptHandler;
+use rustpython_parser::ast::Ranged;
+use rustpython_parser::ast::Stmt;
+
+use crate::options::PythonVersion;
+
+/// Syntax newer than `Options::target_version`, which the parser accepts all the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct NewerSyntax {
+    /// Byte offset of the statement using it.
+    pub offset: usize,
+    pub feature: &'static str,
+    /// The first python version accepting it.
+    pub version: PythonVersion,
+}
+
+impl fmt::Display for NewerSyntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} require python {}", self.feature, self.version)
+    }
+}
+
+/// The first syntax in `statement`, nested statements included, that python `target` doesn't
+/// accept.
+pub(crate) fn newer_syntax(statement: &Stmt, target: PythonVersion) -> Option<NewerSyntax> {
+    let newer = |feature, major, minor| {
+        let version = PythonVersion::new(major, minor);
+        (target < version).then_some(NewerSyntax {
+            offset: usize::from(statement.start()),
+            feature,
+            version,
+        })
+    };
+    let nested = |statements: &[Stmt]| {
+        statements
+            .iter()
+            .find_map(|statement| newer_syntax(statement, target))
+    };
+    match statement {
+        Stmt::TypeAlias(_) => newer("`type` statements", 3, 12),
+        Stmt::FunctionDef(ast::StmtFunctionDef {
+            type_params, body, ..
+        })
+        | Stmt::AsyncFunctionDef(ast::StmtAsyncFunctionDef {
+            type_params, body, ..
+        })
+        | Stmt::ClassDef(ast::StmtClassDef {
+            type_params, body, ..
+        }) => {
+            let generic = if type_params.is_empty() {
+                None
+            } else {
+                newer("type parameter lists", 3, 12)
+            };
+            generic.or_else(|| nested(body))
+        }
+        Stmt::Match(ast::StmtMatch { cases, .. }) => newer("`match` statements", 3, 10)
+            .or_else(|| cases.iter().find_map(|case| nested(&case.body))),
+        Stmt::TryStar(ast::StmtTryStar {
+            body,
+            handlers,
+            orelse,
+            finalbody,
+            ..
+        }) => newer("`except*` clauses", 3, 11)
+            .or_else(|| nested_try(body, handlers, orelse, finalbody, target)),
+        Stmt::Try(ast::StmtTry {
+            body,
+            handlers,
+            orelse,
+            finalbody,
+            ..
+        }) => nested_try(body, handlers, orelse, finalbody, target),
+        Stmt::If(ast::StmtIf { body, orelse, .. })
+        | Stmt::While(ast::StmtWhile { body, orelse, .. })
+        | Stmt::For(ast::StmtFor { body, orelse, .. })
+        | Stmt::AsyncFor(ast::StmtAsyncFor { body, orelse, .. }) => {
+            nested(body).or_else(|| nested(orelse))
+        }
+        Stmt::With(ast::StmtWith { body, .. })
+        | Stmt::AsyncWith(ast::StmtAsyncWith { body, .. }) => nested(body),
+        _ => None,
+    }
+}
+
+fn nested_try(
+    body: &[Stmt],
+    handlers: &[ExceptHandler],
+    orelse: &[Stmt],
+    finalbody: &[Stmt],
+    target: PythonVersion,
+) -> Option<NewerSyntax> {
+    let handler_bodies = handlers
+        .iter()
+        .map(|ExceptHandler::ExceptHandler(handler)| handler.body.as_slice());
+    [body]
+        .into_iter()
+        .chain(handler_bodies)
+        .chain([orelse, finalbody])
+        .flatten()
+        .find_map(|statement| newer_syntax(statement, target))
+}
+
+#[cfg(test)]
+mod tests {
+    use rustpython_parser::ast::Suite;
+    use rustpython_parser::Parse;
+
+    use super::*;
+
+    fn first(src: &str, target: PythonVersion) -> Option<(&'static str, PythonVersion)> {
+        Suite::parse(src, "<test>")
+            .unwrap()
+            .iter()
+            .find_map(|statement| newer_syntax(statement, target))
+            .map(|newer| (newer.feature, newer.version))
+    }
+
+    #[test]
+    fn newer_features() {
+        let py39 = PythonVersion::new(3, 9);
+        let cases = [
+            ("type Alias = int\n", "`type` statements", (3, 12)),
+            (
+                "def f[T](x: T) -> T: ...\n",
+                "type parameter lists",
+                (3, 12),
+            ),
+            (
+                "class C:\n    class D[T]: ...\n",
+                "type parameter lists",
+                (3, 12),
+            ),
+            (
+                "match x:\n    case 1:\n        pass\n",
+                "`match` statements",
+                (3, 10),
+            ),
+            (
+                "try:\n    pass\nexcept* ValueError:\n    pass\n",
+                "`except*` clauses",
+                (3, 11),
+            ),
+            (
+                "if x:\n    type Alias = int\n",
+                "`type` statements",
+                (3, 12),
+            ),
+        ];
+        for (src, feature, (major, minor)) in cases {
+            let version = PythonVersion::new(major, minor);
+            assert_eq!(first(src, py39), Some((feature, version)), "{src}");
+            assert_eq!(first(src, version), None, "{src}");
+        }
+        assert_eq!(first("x = 1\ndef f(): ...\n", py39), None);
+    }
+}
@@ -0,0 +1,182 @@
+use rustpython_parser::ast;
+use rustpython_parser::ast::Comprehension;
+use rustpython_parser::ast::Expr;
+
+/// Collect the targets of named expressions (`x := ...`) in `expr` bound in the enclosing scope.
+///
+/// Per PEP 572 this includes named expressions inside comprehensions, but not comprehension
+/// loop variables nor anything inside a lambda, which has its own scope.
+pub fn named_expression_targets(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::NamedExpr(ast::ExprNamedExpr { target, value, .. }) => {
+            if let Expr::Name(ast::ExprName { id, .. }) = target.as_ref() {
+                names.push(id.to_string());
+            }
+            named_expression_targets(value, names);
+        }
+        Expr::BoolOp(ast::ExprBoolOp { values, .. })
+        | Expr::JoinedStr(ast::ExprJoinedStr { values, .. }) => {
+            visit_all(values, names);
+        }
+        Expr::BinOp(ast::ExprBinOp { left, right, .. }) => {
+            named_expression_targets(left, names);
+            named_expression_targets(right, names);
+        }
+        Expr::UnaryOp(ast::ExprUnaryOp { operand: value, .. })
+        | Expr::Await(ast::ExprAwait { value, .. })
+        | Expr::YieldFrom(ast::ExprYieldFrom { value, .. })
+        | Expr::Attribute(ast::ExprAttribute { value, .. })
+        | Expr::Starred(ast::ExprStarred { value, .. }) => {
+            named_expression_targets(value, names);
+        }
+        Expr::Yield(ast::ExprYield { value, .. }) => {
+            visit_optional(value, names);
+        }
+        Expr::IfExp(ast::ExprIfExp {
+            test, body, orelse, ..
+        }) => {
+            named_expression_targets(test, names);
+            named_expression_targets(body, names);
+            named_expression_targets(orelse, names);
+        }
+        Expr::Dict(ast::ExprDict { keys, values, .. }) => {
+            for key in keys.iter().flatten() {
+                named_expression_targets(key, names);
+            }
+            visit_all(values, names);
+        }
+        Expr::Set(ast::ExprSet { elts, .. })
+        | Expr::List(ast::ExprList { elts, .. })
+        | Expr::Tuple(ast::ExprTuple { elts, .. }) => {
+            visit_all(elts, names);
+        }
+        Expr::ListComp(ast::ExprListComp {
+            elt, generators, ..
+        })
+        | Expr::SetComp(ast::ExprSetComp {
+            elt, generators, ..
+        })
+        | Expr::GeneratorExp(ast::ExprGeneratorExp {
+            elt, generators, ..
+        }) => {
+            named_expression_targets(elt, names);
+            visit_generators(generators, names);
+        }
+        Expr::DictComp(ast::ExprDictComp {
+            key,
+            value,
+            generators,
+            ..
+        }) => {
+            named_expression_targets(key, names);
+            named_expression_targets(value, names);
+            visit_generators(generators, names);
+        }
+        Expr::Compare(ast::ExprCompare {
+            left, comparators, ..
+        }) => {
+            named_expression_targets(left, names);
+            visit_all(comparators, names);
+        }
+        Expr::Call(ast::ExprCall {
+            func,
+            args,
+            keywords,
+            ..
+        }) => {
+            named_expression_targets(func, names);
+            visit_all(args, names);
+            for keyword in keywords {
+                named_expression_targets(&keyword.value, names);
+            }
+        }
+        Expr::FormattedValue(ast::ExprFormattedValue {
+            value, format_spec, ..
+        }) => {
+            named_expression_targets(value, names);
+            visit_optional(format_spec, names);
+        }
+        Expr::Subscript(ast::ExprSubscript { value, slice, .. }) => {
+            named_expression_targets(value, names);
+            named_expression_targets(slice, names);
+        }
+        Expr::Slice(ast::ExprSlice {
+            lower, upper, step, ..
+        }) => {
+            visit_optional(lower, names);
+            visit_optional(upper, names);
+            visit_optional(step, names);
+        }
+        // lambdas have their own scope
+        _ => {}
+    }
+}
+
+fn visit_all(exprs: &[Expr], names: &mut Vec<String>) {
+    for expr in exprs {
+        named_expression_targets(expr, names);
+    }
+}
+
+fn visit_optional(expr: &Option<Box<Expr>>, names: &mut Vec<String>) {
+    if let Some(expr) = expr {
+        named_expression_targets(expr, names);
+    }
+}
+
+/// Comprehension loop variables stay local, only the iterables and conditions are visited.
+fn visit_generators(generators: &[Comprehension], names: &mut Vec<String>) {
+    for generator in generators {
+        named_expression_targets(&generator.iter, names);
+        visit_all(&generator.ifs, names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rustpython_parser::Parse;
+
+    fn targets(src: &str) -> Vec<String> {
+        let expr = Expr::parse(src, "<test>").unwrap();
+        let mut names = Vec::new();
+        named_expression_targets(&expr, &mut names);
+        names
+    }
+
+    #[test]
+    fn top_level() {
+        assert_eq!(targets("(x := 1)"), vec![String::from("x")]);
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(
+            targets("[y := f(x), y**2, {'k': (z := 3)}]"),
+            vec![String::from("y"), String::from("z")]
+        );
+    }
+
+    #[test]
+    fn comprehension() {
+        assert_eq!(
+            targets("sum(n for x in data if (n := g(x)))"),
+            vec![String::from("n")]
+        );
+        assert_eq!(
+            targets("{k: (last := v) for k, v in items}"),
+            vec![String::from("last")]
+        );
+    }
+
+    #[test]
+    fn lambda_scope() {
+        assert!(targets("lambda: (x := 1)").is_empty());
+    }
+
+    #[test]
+    fn no_named_expressions() {
+        assert!(targets("[foo for foo in bar]").is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+use anyhow::Result;
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::JsError;
+
+use crate::config::Config;
+use crate::fix::fix_source;
+use crate::options::Options;
+
+/// Fix the python module `src`, returning the [`FixOutcome`] as JSON.
+///
+/// `options` is a JSON object spelled like the `[tool.allways]` table, e.g.
+/// `{"type-aliases": false}`, or an empty string for the defaults.
+///
+/// [`FixOutcome`]: crate::FixOutcome
+#[wasm_bindgen]
+pub fn fix(src: &str, options: &str) -> Result<String, JsError> {
+    fix_json(src, options).map_err(|err| JsError::new(&format!("{err:#}")))
+}
+
+fn fix_json(src: &str, options: &str) -> Result<String> {
+    let config = if options.trim().is_empty() {
+        Config::default()
+    } else {
+        serde_json::from_str::<Config>(options)?
+    };
+    let mut fix_options = Options::default();
+    config.apply(&mut fix_options);
+    let outcome = fix_source(src, &fix_options)?;
+    Ok(serde_json::to_string(&outcome)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fix_with_json_options() {
+        let json = fix_json(
+            "type Alias = int\ndef foo(): ...\n",
+            r#"{"type-aliases": false}"#,
+        )
+        .unwrap();
+        let outcome = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(outcome["changed"], true);
+        assert_eq!(outcome["added"], serde_json::json!(["foo"]));
+
+        assert!(fix_json("x = 1\n", r#"{"unknown": true}"#).is_err());
+        assert!(fix_json("def (:\n", "").is_err());
+    }
+}
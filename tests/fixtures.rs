@@ -0,0 +1,87 @@
+//! Runs `allways` over every case of `tests/fixtures`, a directory holding:
+//!
+//! - `before.py`, the file `allways` is run on
+//! - `after.py`, what it must be afterwards, unchanged when missing
+//! - `args`, optional command line arguments
+//! - `stderr`, optional text the error output must contain
+//! - `rtc`, the exit code `allways` must return, unchecked when missing
+//!
+//! Run with `UPDATE_FIXTURES=1` to write the actual results to `after.py`.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn fixtures() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut cases = fs::read_dir(root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    cases.sort();
+    assert!(!cases.is_empty());
+    let failures = cases
+        .iter()
+        .filter_map(|case| {
+            let name = case.file_name().unwrap().to_string_lossy();
+            run_case(case)
+                .err()
+                .map(|failure| format!("{name}: {failure}"))
+        })
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+fn run_case(case: &Path) -> Result<(), String> {
+    let before = fs::read(case.join("before.py")).unwrap();
+    let after = case.join("after.py");
+    let expected = fs::read(&after).unwrap_or_else(|_| before.clone());
+    let args = fs::read_to_string(case.join("args")).unwrap_or_default();
+
+    // run from a directory of its own so no `pyproject.toml` applies
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("module.py");
+    fs::write(&file, &before).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_allways"))
+        .args(args.split_whitespace())
+        .arg(&file)
+        .current_dir(dir.path())
+        // the cache of the developer running the tests stays untouched
+        .env("XDG_CACHE_HOME", dir.path().join("cache"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let actual = fs::read(&file).unwrap();
+
+    if std::env::var_os("UPDATE_FIXTURES").is_some() && actual != before {
+        fs::write(&after, &actual).unwrap();
+        return Ok(());
+    }
+    if let Ok(expected_stderr) = fs::read_to_string(case.join("stderr")) {
+        let expected_stderr = expected_stderr.trim_end();
+        if !stderr.contains(expected_stderr) {
+            return Err(format!(
+                "stderr doesn't contain {expected_stderr:?}:\n{stderr}"
+            ));
+        }
+    }
+    if let Ok(expected_rtc) = fs::read_to_string(case.join("rtc")) {
+        let expected_rtc = expected_rtc.trim().parse::<i32>().unwrap();
+        if output.status.code() != Some(expected_rtc) {
+            return Err(format!(
+                "expected exit code {expected_rtc}, found {:?}\nstderr:\n{stderr}",
+                output.status.code()
+            ));
+        }
+    }
+    if actual != expected {
+        return Err(format!(
+            "expected:\n{}\nfound:\n{}\nstderr:\n{stderr}",
+            String::from_utf8_lossy(&expected),
+            String::from_utf8_lossy(&actual),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+def function_number_0(): ...
+def function_number_1(): ...
+def function_number_2(): ...
+def function_number_3(): ...
+def function_number_4(): ...
+def function_number_5(): ...
+
+
+# allways: start
+__all__ = [
+    "function_number_0",
+    "function_number_1",
+    "function_number_2",
+    "function_number_3",
+    "function_number_4",
+    "function_number_5",
+]
+# allways: end
@@ -0,0 +1,6 @@
+def function_number_0(): ...
+def function_number_1(): ...
+def function_number_2(): ...
+def function_number_3(): ...
+def function_number_4(): ...
+def function_number_5(): ...
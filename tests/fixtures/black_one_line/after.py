@@ -0,0 +1,10 @@
+import os
+
+
+def foo():
+    ...
+
+
+# allways: start
+__all__ = ["foo", "os"]
+# allways: end
@@ -0,0 +1,10 @@
+import os
+import sys
+
+
+# allways: start
+__all__ = [
+    "os",
+    "sys",
+]
+# allways: end
@@ -0,0 +1,13 @@
+class Fooey:
+    ...
+
+
+# allways: start
+__all__ = [
+    "Fooey",
+    "os",
+    "sys",
+]
+# allways: end
+
+import sys, os
@@ -0,0 +1,11 @@
+class Fooey:
+    ...
+
+
+# allways: start
+__all__ = [
+    "A",
+]
+# allways: end
+
+import sys, os
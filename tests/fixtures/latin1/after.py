@@ -0,0 +1,9 @@
+# -*- coding: latin-1 -*-
+nom = 't'
+
+
+# allways: start
+__all__ = [
+    "nom",
+]
+# allways: end
@@ -0,0 +1,13 @@
+import os
+
+
+def foo():
+    ...
+
+
+# allways: start
+__all__ = [
+    "foo",
+    "os",
+]
+# allways: end
@@ -0,0 +1,8 @@
+A = 1
+
+
+# allways: start
+__all__ = [
+    "A",
+]
+# allways: end
@@ -0,0 +1,10 @@
+A = 1
+
+B = 2
+
+# allways: start
+__all__ = [
+    "A",
+    "B",
+]
+# allways: end
@@ -0,0 +1 @@
+_foo = 1
@@ -0,0 +1,8 @@
+_foo = 1
+
+
+# allways: start
+__all__ = [
+    "foo",
+]
+# allways: end
@@ -0,0 +1,46 @@
+//! Runs `allways --output-format patch` over a package and applies the patch it prints with
+//! `git apply`.
+
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+#[test]
+fn git_applies_patch() {
+    // without git there is nothing to apply the patch with
+    if Command::new("git").arg("--version").output().is_err() {
+        return;
+    }
+    let dir = tempfile::Builder::new().prefix("patch").tempdir().unwrap();
+    fs::create_dir(dir.path().join("pkg")).unwrap();
+    fs::write(dir.path().join("pkg/__init__.py"), "foo = 1\n").unwrap();
+
+    // a directory walk yields `./pkg/__init__.py`
+    let output = Command::new(env!("CARGO_BIN_EXE_allways"))
+        .args(["--output-format", "patch", "--no-cache", "."])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    let patch = String::from_utf8(output.stdout).unwrap();
+    assert!(patch.contains("+++ b/pkg/__init__.py\n"), "{patch}");
+
+    let mut git = Command::new("git")
+        .args(["apply", "--check", "-"])
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    git.stdin
+        .take()
+        .unwrap()
+        .write_all(patch.as_bytes())
+        .unwrap();
+    let applied = git.wait_with_output().unwrap();
+    assert!(
+        applied.status.success(),
+        "{}",
+        String::from_utf8_lossy(&applied.stderr)
+    );
+}